@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// kennsh is only a binary, so the modules the parser needs are compiled into
+// the fuzz target as they are
+#[path = "../../src"]
+#[allow(dead_code)]
+mod kennsh {
+	pub(crate) mod math;
+	pub(crate) mod syntax_parser;
+	pub(crate) mod variable;
+}
+use kennsh::{math, syntax_parser, variable};
+
+fuzz_target!(|input: &str| {
+	let _ = syntax_parser::is_incomplete(input);
+	// Whatever parses has to print as something that parses the same
+	if let Ok(tree) = syntax_parser::parse(input.to_owned()) {
+		let printed = tree.to_string();
+		match syntax_parser::parse(printed.clone()) {
+			Ok(reparsed) => assert_eq!(reparsed.to_string(), printed, "printing {:?} is not stable", input),
+			Err(errors) => panic!("{:?} printed as {:?}, which doesn't parse: {:?}", input, printed, errors),
+		}
+	}
+});
@@ -0,0 +1,69 @@
+//! Aliases, which replace the first word of a command when it runs, and
+//! abbreviations, which replace it in the line editor as it is typed.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AliasKind {
+	Alias,
+	Abbreviation,
+}
+
+impl AliasKind {
+	/// The builtin managing this kind, which is also used to save it
+	pub(crate) fn builtin(self) -> &'static str {
+		match self {
+			AliasKind::Alias => "alias",
+			AliasKind::Abbreviation => "abbr",
+		}
+	}
+
+	fn table(self) -> &'static Mutex<BTreeMap<String, String>> {
+		match self {
+			AliasKind::Alias => &ALIASES,
+			AliasKind::Abbreviation => &ABBREVIATIONS,
+		}
+	}
+}
+
+static ALIASES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+static ABBREVIATIONS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+pub(crate) fn get(kind: AliasKind, name: &str) -> Option<String> {
+	kind.table().lock().unwrap().get(name).cloned()
+}
+
+/// Every name with its expansion, sorted by name
+pub(crate) fn all(kind: AliasKind) -> Vec<(String, String)> {
+	kind.table().lock().unwrap().iter().map(|(name, expansion)| (name.clone(), expansion.clone())).collect()
+}
+
+/// Defines name, or removes it if there is no expansion
+pub(crate) fn define(kind: AliasKind, name: String, expansion: Option<String>) {
+	let mut table = kind.table().lock().unwrap();
+	match expansion {
+		Some(expansion) => table.insert(name, expansion),
+		None => table.remove(&name),
+	};
+}
+
+/// Finds the abbreviation ending at pos, returning where it starts and its
+/// expansion. Only a word in the place of a command is expanded.
+pub(crate) fn abbreviation_at(line: &str, pos: usize) -> Option<(usize, String)> {
+	let before = &line[..pos];
+	let start = before
+		.rfind(|c: char| c.is_whitespace() || ['|', ';', '('].contains(&c))
+		.map_or(0, |index| index + 1);
+	let preceding = before[..start].trim_end();
+	let is_command_position = preceding.is_empty()
+		|| preceding.ends_with(|c| ['|', ';', '('].contains(&c))
+		|| ["if", "while", "else if"].iter().any(|keyword| {
+			preceding == *keyword || preceding.ends_with(&format!(" {}", keyword)) || preceding.ends_with(&format!(";{}", keyword))
+		});
+	if !is_command_position {
+		return None;
+	}
+	get(AliasKind::Abbreviation, &before[start..]).map(|expansion| (start, expansion))
+}
@@ -0,0 +1,329 @@
+//! Finds problems in scripts without running them: variables that are never
+//! set, subcommands whose output is split into words, output redirections
+//! that fail because their file exists and commands that can't be found.
+
+use std::{collections::HashSet, path::Path};
+
+use crate::{
+	command,
+	syntax_parser::{self, BraceExpansion, CommandItem, CommandItemKind, CommandSyntax, CommandSyntaxKind, OutputRedirectionKind, ParseError, Span, SyntaxTree, SyntaxTreeKind, VariableModifier},
+	variable,
+};
+
+// Variables the shell sets itself
+static SHELL_VARIABLES: &[&str] = &["argv", "status", "PWD", "OLDPWD"];
+
+/// Checks source, returning the problems found in it ordered by where they
+/// are, or the syntax errors if it doesn't parse
+pub(crate) fn check(source: &str) -> Result<Vec<ParseError>, Vec<ParseError>> {
+	let tree = syntax_parser::parse(source.to_owned())?;
+	let mut checker = Checker {
+		source,
+		variables: HashSet::new(),
+		lists: HashSet::new(),
+		commands: HashSet::new(),
+		created: HashSet::new(),
+		loop_depth: 0,
+		warnings: vec![],
+	};
+	checker.collect(&tree);
+	checker.statement(&tree);
+	checker.warnings.sort_by_key(|warning| warning.start_index);
+	Ok(checker.warnings)
+}
+
+struct Checker<'a> {
+	source: &'a str,
+	// Variables set anywhere in the script, and those of them set to lists
+	variables: HashSet<String>,
+	lists: HashSet<String>,
+	// Functions and aliases defined anywhere in the script
+	commands: HashSet<String>,
+	// Files created by > redirections so far
+	created: HashSet<String>,
+	loop_depth: usize,
+	warnings: Vec<ParseError>,
+}
+
+impl<'a> Checker<'a> {
+	/// Finds the variables, functions and aliases the script defines, as
+	/// they may be used before they are defined
+	fn collect(&mut self, tree: &SyntaxTree) {
+		match &tree.kind {
+			SyntaxTreeKind::Command(command) => self.collect_command(command),
+			SyntaxTreeKind::PipeChain(commands) => commands.iter().for_each(|command| self.collect_command(command)),
+			SyntaxTreeKind::Sequence(statements) => statements.iter().for_each(|statement| self.collect(statement)),
+			SyntaxTreeKind::If { branches, otherwise } => {
+				for (condition, body) in branches {
+					self.collect(condition);
+					self.collect(body);
+				}
+				if let Some(otherwise) = otherwise {
+					self.collect(otherwise);
+				}
+			}
+			SyntaxTreeKind::For { variable, body, .. } => {
+				self.variables.insert(variable.clone());
+				self.collect(body);
+			}
+			SyntaxTreeKind::While { condition, body } => {
+				self.collect(condition);
+				self.collect(body);
+			}
+			SyntaxTreeKind::Switch { cases, .. } => cases.iter().for_each(|(_, body)| self.collect(body)),
+			SyntaxTreeKind::Function { name, body } => {
+				self.commands.insert(name.clone());
+				self.collect(body);
+			}
+			SyntaxTreeKind::RedirectedBlock { block, .. } => self.collect(block),
+		}
+	}
+
+	fn collect_command(&mut self, command: &CommandSyntax) {
+		let words: Vec<_> = words(command).iter().map(literal).collect();
+		let (name, mut arguments) = match words.split_first() {
+			Some((Some(name), arguments)) => (name.as_str(), arguments),
+			_ => return,
+		};
+		match name {
+			"~set" => {
+				let mut list = false;
+				while let Some(Some(flag)) = arguments.first() {
+					match flag.as_str() {
+						"-a" | "--append" | "-p" | "--prepend" => list = true,
+						"-l" | "--local" => {}
+						_ => break,
+					}
+					arguments = &arguments[1..];
+				}
+				if let Some((Some(target), values)) = arguments.split_first() {
+					let name = target.split('[').next().unwrap_or_default().to_owned();
+					if list || values.len() != 1 || variable::is_path_variable(&name) {
+						self.lists.insert(name.clone());
+					}
+					self.variables.insert(name);
+				}
+			}
+			"alias" | "abbr" => {
+				while let Some(Some(flag)) = arguments.first() {
+					if !flag.starts_with('-') {
+						break;
+					}
+					arguments = &arguments[1..];
+				}
+				if let [Some(name), _, ..] = arguments {
+					self.commands.insert(name.clone());
+				}
+			}
+			_ => {}
+		}
+	}
+
+	fn warn(&mut self, warning: ParseError) {
+		self.warnings.push(warning);
+	}
+
+	fn statement(&mut self, tree: &SyntaxTree) {
+		match &tree.kind {
+			SyntaxTreeKind::Command(command) => self.command(command),
+			SyntaxTreeKind::PipeChain(commands) => commands.iter().for_each(|command| self.command(command)),
+			SyntaxTreeKind::Sequence(statements) => statements.iter().for_each(|statement| self.statement(statement)),
+			SyntaxTreeKind::If { branches, otherwise } => {
+				for (condition, body) in branches {
+					self.statement(condition);
+					self.statement(body);
+				}
+				if let Some(otherwise) = otherwise {
+					self.statement(otherwise);
+				}
+			}
+			SyntaxTreeKind::For { items, body, .. } => {
+				// Splitting the items into words is what for is for
+				items.iter().for_each(|item| self.item(item, false));
+				self.loop_depth += 1;
+				self.statement(body);
+				self.loop_depth -= 1;
+			}
+			SyntaxTreeKind::While { condition, body } => {
+				self.loop_depth += 1;
+				self.statement(condition);
+				self.statement(body);
+				self.loop_depth -= 1;
+			}
+			SyntaxTreeKind::Switch { value, cases } => {
+				value.iter().for_each(|item| self.item(item, true));
+				for (patterns, body) in cases {
+					patterns.iter().for_each(|item| self.item(item, false));
+					self.statement(body);
+				}
+			}
+			SyntaxTreeKind::Function { body, .. } => self.statement(body),
+			SyntaxTreeKind::RedirectedBlock { block, redirection } => {
+				self.statement(block);
+				self.command(redirection);
+			}
+		}
+	}
+
+	fn command(&mut self, command: &CommandSyntax) {
+		match &command.kind {
+			CommandSyntaxKind::InputRedirection { command: inner, filename, .. } => {
+				self.command(inner);
+				self.redirection_target(filename, command.span.end);
+			}
+			CommandSyntaxKind::OutputRedirection { command: inner, destination, kind, .. } => {
+				self.command(inner);
+				let span = self.trimmed(Span::new(inner.span.end, command.span.end));
+				if let Some(path) = self.redirection_target(destination, command.span.end) {
+					if *kind == OutputRedirectionKind::Create {
+						self.created_file(path, span);
+					}
+				}
+			}
+			CommandSyntaxKind::Command(items) => {
+				let name = items.first().and_then(literal);
+				if let (Some(name), Some(first)) = (&name, items.first()) {
+					self.command_name(name, first.span);
+				}
+				// Lists are made from the lines of subcommands on purpose
+				let splits = name.as_deref() != Some("~set");
+				for (index, item) in items.iter().enumerate() {
+					self.item(item, index > 0 && splits);
+				}
+			}
+		}
+	}
+
+	/// Checks the expansions in a redirection target ending at end,
+	/// returning the path if it is literal
+	fn redirection_target(&mut self, target: &str, end: usize) -> Option<String> {
+		if target.starts_with('&') {
+			return None;
+		}
+		let start = end.checked_sub(target.len()).filter(|&start| self.source.get(start..end) == Some(target))?;
+		let item = syntax_parser::parse_command_item(start, target).ok()?;
+		self.item(&item, false);
+		literal(&item).filter(|path| !path.starts_with('~') && !path.contains('*'))
+	}
+
+	/// Checks a > redirection to path, which fails if the file exists
+	fn created_file(&mut self, path: String, span: Span) {
+		let help = "use >| to overwrite the file or >> to append to it";
+		if self.loop_depth > 0 {
+			self.warn(ParseError::new(span, format!("The file {} is created in a loop, so > fails after the first time", path))
+				.with_help(help));
+		}
+		else if Path::new(&path).exists() {
+			self.warn(ParseError::new(span, format!("The file {} already exists, so > will fail", path))
+				.with_help(help));
+		}
+		else if !self.created.insert(path.clone()) {
+			self.warn(ParseError::new(span, format!("The file {} was already created before, so > will fail", path))
+				.with_help(help));
+		}
+	}
+
+	fn command_name(&mut self, name: &str, span: Span) {
+		if command::builtin_help(name).is_some() || self.commands.contains(name) || name.is_empty() {
+			return;
+		}
+		if name.contains('/') {
+			if !name.starts_with('~') && !Path::new(name).exists() {
+				self.warn(ParseError::new(span, format!("The command {} doesn't exist", name)));
+			}
+		}
+		else if command::find_in_path(name).is_none() {
+			self.warn(ParseError::new(span, format!("The command {} was not found", name))
+				.with_note("it is not a builtin, a function or alias of the script, or a program in $PATH"));
+		}
+	}
+
+	/// Checks the variables an item uses. splits says whether the item is an
+	/// argument, where splitting the output of a subcommand into words is
+	/// likely a mistake.
+	fn item(&mut self, item: &CommandItem, splits: bool) {
+		match &item.kind {
+			CommandItemKind::ShellVariable(name) | CommandItemKind::IndexedVariable { name, .. } => {
+				self.variable(name, item.span);
+			}
+			CommandItemKind::VariableExpansion { name, modifier } => match modifier {
+				// These are for variables that may be unset
+				VariableModifier::Default(word) | VariableModifier::Alternative(word) | VariableModifier::ErrorIfUnset(word) => {
+					self.item(word, false);
+				}
+				VariableModifier::RemovePrefix { pattern, .. } | VariableModifier::RemoveSuffix { pattern, .. } => {
+					self.variable(name, item.span);
+					self.item(pattern, false);
+				}
+				VariableModifier::Substitute { pattern, replacement, .. } => {
+					self.variable(name, item.span);
+					self.item(pattern, false);
+					self.item(replacement, false);
+				}
+				VariableModifier::Length | VariableModifier::Substring { .. } => self.variable(name, item.span),
+			},
+			CommandItemKind::Subcommand(tree) => {
+				if splits {
+					self.warn(ParseError::new(item.span, "The output of this subcommand is split into a word for each line")
+						.with_help("quote it as \"$(...)\" to keep the output as one word"));
+				}
+				self.statement(tree);
+			}
+			CommandItemKind::QuotedSubcommand(tree) => self.statement(tree),
+			CommandItemKind::BraceExpansion(BraceExpansion::List(items)) => {
+				items.iter().for_each(|item| self.item(item, splits));
+			}
+			CommandItemKind::Combination(items) => {
+				for part in items {
+					if let CommandItemKind::ShellVariable(name) = &part.kind {
+						if self.lists.contains(name) || name == "argv" {
+							self.warn(ParseError::new(part.span, format!("${} is a list, so the word is repeated for each of its elements", name))
+								.with_label(item.span, "this word")
+								.with_help(format!("use ${}[1] for a single element", name)));
+						}
+					}
+					self.item(part, splits);
+				}
+			}
+			CommandItemKind::String(_)
+			| CommandItemKind::RawString(_)
+			| CommandItemKind::BraceExpansion(_)
+			| CommandItemKind::ArithmeticExpansion(_) => {}
+		}
+	}
+
+	fn variable(&mut self, name: &str, span: Span) {
+		if self.variables.contains(name) || SHELL_VARIABLES.contains(&name) || std::env::var_os(name).is_some() {
+			return;
+		}
+		self.warn(ParseError::new(span, format!("The variable ${} is never set", name))
+			.with_help(format!("set it with ~set, or use ${{{}:-default}} if it may be unset", name)));
+	}
+
+	/// span without the whitespace around it
+	fn trimmed(&self, span: Span) -> Span {
+		let text = &self.source[span.start..span.end];
+		let start = span.start + text.len() - text.trim_start().len();
+		Span::new(start, start + text.trim().len())
+	}
+}
+
+/// The words of a command inside of its redirections
+fn words(command: &CommandSyntax) -> &[CommandItem] {
+	match &command.kind {
+		CommandSyntaxKind::InputRedirection { command, .. } | CommandSyntaxKind::OutputRedirection { command, .. } => words(command),
+		CommandSyntaxKind::Command(items) => items,
+	}
+}
+
+/// The text of an item without expansions
+fn literal(item: &CommandItem) -> Option<String> {
+	match &item.kind {
+		CommandItemKind::String(text) | CommandItemKind::RawString(text) => Some(text.clone()),
+		CommandItemKind::Combination(items) => items.iter().map(literal).collect(),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,87 @@
+use super::*;
+
+/// The reasons of the warnings for input, with the text they point at
+fn warnings(input: &str) -> Vec<(String, &str)> {
+	match check(input) {
+		Ok(warnings) => warnings
+			.into_iter()
+			.map(|warning| {
+				let text = &input[warning.start_index..warning.end_index];
+				(warning.reason, text)
+			})
+			.collect(),
+		Err(errors) => {
+			let reasons: Vec<_> = errors.iter().map(|error| error.reason.as_str()).collect();
+			panic!("{:?} didn't parse: {:?}", input, reasons)
+		}
+	}
+}
+
+fn assert_clean(input: &str) {
+	assert_eq!(warnings(input), [], "checking {:?}", input);
+}
+
+#[test]
+fn finds_unset_variables() {
+	let found = warnings("echo $kennsh_check_a ${kennsh_check_b%.rs} $kennsh_check_c[1]");
+	let texts: Vec<_> = found.iter().map(|(_, text)| *text).collect();
+	assert_eq!(texts, ["$kennsh_check_a", "${kennsh_check_b%.rs}", "$kennsh_check_c[1]"]);
+	assert_eq!(found[0].0, "The variable $kennsh_check_a is never set");
+
+	assert_clean("echo $kennsh_check_x; ~set kennsh_check_x 1");
+	assert_clean("for kennsh_check_x in a; echo $kennsh_check_x; end");
+	assert_clean("function f; ~set -l kennsh_check_x 1; echo $kennsh_check_x; end");
+	assert_clean("echo ${kennsh_check_x:-default} $argv $status");
+}
+
+#[test]
+fn finds_split_subcommands() {
+	let found = warnings("echo (echo a) \"$(echo b)\"");
+	assert_eq!(found.len(), 1);
+	assert_eq!(found[0].1, "(echo a)");
+
+	assert_clean("for x in (echo a); echo $x; end");
+	assert_clean("~set kennsh_check_x (echo a)");
+	assert_clean("(echo echo) a");
+}
+
+#[test]
+fn finds_list_variables_in_words() {
+	let found = warnings("~set kennsh_check_x a b; echo $kennsh_check_x/file $kennsh_check_x");
+	let texts: Vec<_> = found.iter().map(|(_, text)| *text).collect();
+	assert_eq!(texts, ["$kennsh_check_x"]);
+	assert!(found[0].0.contains("is a list"));
+}
+
+#[test]
+fn finds_failing_redirections() {
+	let found = warnings("echo a > / >> /");
+	assert_eq!(found, [("The file / already exists, so > will fail".to_owned(), "> /")]);
+
+	let found = warnings("echo a > kennsh_check_out; echo b > kennsh_check_out");
+	assert_eq!(found.len(), 1);
+	assert!(found[0].0.contains("already created"));
+
+	let found = warnings("for x in a b\necho $x > kennsh_check_out\nend");
+	assert_eq!(found.len(), 1);
+	assert!(found[0].0.contains("in a loop"));
+
+	assert_clean("echo a >| / 2>&1");
+	assert_clean("while true; echo a >> kennsh_check_out; end");
+}
+
+#[test]
+fn finds_missing_commands() {
+	let found = warnings("kennsh-check-missing a | ~unknown");
+	let texts: Vec<_> = found.iter().map(|(_, text)| *text).collect();
+	assert_eq!(texts, ["kennsh-check-missing", "~unknown"]);
+
+	assert_clean("function kennsh-check-f; end; kennsh-check-f");
+	assert_clean("alias kennsh-check-a echo; kennsh-check-a");
+	assert_clean("~set kennsh_check_x 1; cd /; exit");
+}
+
+#[test]
+fn reports_syntax_errors() {
+	assert!(check("echo (").is_err());
+}
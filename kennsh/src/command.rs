@@ -1,26 +1,35 @@
 //mod external;
 
+mod alias;
+mod ast;
 mod cat;
 mod cd;
 use cd::cd;
 mod color_test;
+mod config;
+mod count;
 use color_test::color_test;
 mod env;
 mod exit;
 use exit::exit_command;
+mod functions;
 mod head;
+mod math;
 mod prompt;
 mod server;
 mod set;
+mod syscalls;
+mod trace;
 
-use std::{ffi::CString, io::{Read, Write}, mem, process::exit};
+use std::{ffi::CString, io::{Read, Write}, mem, os::unix::fs::PermissionsExt, path::PathBuf, process::exit, rc::Rc};
 
 use c_wrapper::{c_error::CError, chdir::chdir, exec, file, file::{FileDescriptor, open}, fork::{ForkResult, fork}, pipe::pipe, wait};
 use file::access::{AccessCheck, access};
 use kennsh_syscall_macro::syscall;
-use syntax_parser::CommandSyntax;
+use serde::{Deserialize, Serialize};
+use syntax_parser::{CommandSyntax, CommandSyntaxKind, SyntaxTree, SyntaxTreeKind};
 
-use crate::{env_util::env_is_true, error::Error, syntax_parser::{self, CommandItem, parse}, window_title::{WindowTitleElement, set_window_title}};
+use crate::{alias::AliasKind, error::Error, glob::{self, GlobWord, Pattern}, syntax_parser::{self, BraceExpansion, CommandItem, CommandItemKind, VariableModifier, parse}, settings, tilde, variable, window_title::{WindowTitleElement, set_window_title}};
 
 pub(crate) fn handle_subcommand<'a, Bytes: AsRef<[u8]>>(subcommand: String, stdin: Option<Bytes>) -> crate::Result<(u8, FileDescriptor)> {
 	// Create pipe for stdin of subcommand
@@ -33,7 +42,7 @@ pub(crate) fn handle_subcommand<'a, Bytes: AsRef<[u8]>>(subcommand: String, stdi
 	// Fork and handle
 	syscall!(fork match {
 		ForkResult::Child => {
-			std::env::set_var("no_title", "yes");
+			settings::update(|settings| settings.no_title = true);
 			if let Some(stdin_pipe) = stdin_pipe {
 				let stdin_read = stdin_pipe.drop_write();
 				FileDescriptor::wrap_stdin(|stdin| {
@@ -45,7 +54,9 @@ pub(crate) fn handle_subcommand<'a, Bytes: AsRef<[u8]>>(subcommand: String, stdi
 				syscall!(FileDescriptor::redirect_from(stdout, &stdout_write))
 			})?;
 
+			crate::trace::enter();
 			// In subcommand, don't re-print syntax tree
+			settings::update(|settings| settings.print_syntax_tree = false);
 			std::env::remove_var("print_syntax_tree");
 
 			exit(handle(subcommand).unwrap().into())
@@ -56,13 +67,13 @@ pub(crate) fn handle_subcommand<'a, Bytes: AsRef<[u8]>>(subcommand: String, stdi
 				let mut stdin_write = stdin_pipe.unwrap().drop_read();
 				let mut last_index = 0;
 				while last_index < stdin.len() {
-					let bytes_written = syscall!(FileDescriptor::write_slice(&mut stdin_write, &stdin[last_index..]))?;
+					let bytes_written = syscall!(retry FileDescriptor::write_slice(&mut stdin_write, &stdin[last_index..]))?;
 					last_index += bytes_written;
 				}
 			}
 			let stdout_read = stdout_pipe.drop_write();
 
-			let exit_code = syscall!(wait::waitpid(child_pid); it.status.exit_status())?;
+			let exit_code = syscall!(retry wait::waitpid(child_pid); it.status.exit_status())?;
 
 			(exit_code, stdout_read)
 		}
@@ -70,32 +81,53 @@ pub(crate) fn handle_subcommand<'a, Bytes: AsRef<[u8]>>(subcommand: String, stdi
 }
 
 pub(crate) fn handle(command: String) -> crate::Result<u8> {
-	if !env_is_true("no_title") {
+	if !settings::get().no_title {
 		set_window_title(vec![
 			WindowTitleElement::CustomText(command.split(' ').nth(0).unwrap().to_owned()),
 			WindowTitleElement::Separator,
 			WindowTitleElement::ShortCurrentWorkingDirectory,
 		]);
 	}
+	let tree = parse(command)?;
+	if settings::get().print_syntax_tree {
+		print_syntax_tree(&tree);
+	}
+	handle_tree(tree, &[])
+}
+
+/// What a forked command sends back through its result pipe
+#[derive(Serialize, Deserialize)]
+struct ForkedResult {
+	result: crate::Result<u8>,
+	// The syscalls it recorded, which only the shell keeps
+	syscalls: Vec<crate::trace::SyscallRecord>,
+}
+
+/// Runs a command or a pipe chain in a forked child, then applies the state
+/// change it requested, if any
+fn handle_forked(tree: SyntaxTree, redirections: &[Rc<BlockRedirection>]) -> crate::Result<u8> {
 	// Create subprocess to execute the command into
 	let result_pipe = syscall!(pipe)?;
 	syscall!(fork match {
 		ForkResult::Child => {
 			let mut result_write = result_pipe.drop_read();
+			let recorded = crate::trace::recorded_count();
 
-			let parsed_command = parse(command);
-			let result = match parsed_command {
-				Ok(tree) => {
-					handle_tree(tree)
-				}
-				Err(e) => {
-					Err(Error::ParseError(e))
-				}
-			};
+			let redirected = redirections.iter().try_for_each(|redirection| {
+				FileDescriptor::wrap_unowned(redirection.file_descriptor, |fd| {
+					syscall!(FileDescriptor::redirect_from(fd, &redirection.file))
+				})
+			});
+			let result = redirected.and_then(|_| match tree.kind {
+				SyntaxTreeKind::Command(c) => handle_command(c),
+				SyntaxTreeKind::PipeChain(chain) => handle_pipe(chain),
+				_ => unreachable!("blocks are evaluated by handle_tree"),
+			});
 
 			
 			// syscall!(FileDescriptor::write_any(&mut result_write, result))?;
-			let _ = Write::write_all(&mut result_write, serde_json::to_string(&result).unwrap().as_bytes());
+			let forked = ForkedResult { result, syscalls: crate::trace::syscalls_recorded_since(recorded) };
+			let _ = Write::write_all(&mut result_write, serde_json::to_string(&forked).unwrap().as_bytes());
 			exit(0);
 		},
 		ForkResult::Parent(child_pid) => {
@@ -104,56 +136,112 @@ pub(crate) fn handle(command: String) -> crate::Result<u8> {
 			// let result = unsafe { syscall!(FileDescriptor::read_any(&mut result_read))? };
 			let mut s = String::new();
 			let _ = result_read.read_to_string(&mut s);
-			let result = serde_json::from_str(&s).unwrap();
-			syscall!(wait::waitpid(child_pid))?;
-			match result {
-				Err(Error::ChdirRequested(path)) => {
-					syscall!(chdir(CString::new(path.clone()).unwrap()); match_error {
-						CError::NotFound => {
-							return Err(Error::FileNotFound(Some(path)))
-						}
-					})?;
-					let _ = syscall!(
-						c_wrapper::cwd::getcwd; 
-						std::env::set_var("PWD", it)
-					);
-					Ok(0)
-				},
-				Err(Error::EnvChangeRequested(key, value)) => {
-					std::env::set_var(key, value);
-					Ok(0)
-				},
-				Err(Error::EnvRemoveRequested(key)) => {
-					std::env::remove_var(key);
-					Ok(0)
-				}
-				any => any,
-			}
+			let forked: ForkedResult = serde_json::from_str(&s).unwrap();
+			crate::trace::keep_syscalls(forked.syscalls);
+			syscall!(retry wait::waitpid(child_pid))?;
+			apply_state_change(forked.result)
 		}
 	})?
 }
 
-fn handle_tree(command: syntax_parser::SyntaxTree) -> crate::Result<u8> {
+/// Applies the state change requested by a command, if any
+fn apply_state_change(result: crate::Result<u8>) -> crate::Result<u8> {
+	match result {
+		Err(Error::ChdirRequested(path)) => {
+			let previous_directory = c_wrapper::cwd::getcwd();
+			syscall!(chdir(CString::new(path.clone()).unwrap()); match_error file(path))?;
+			// Remembered for ~-
+			if let Ok(previous_directory) = previous_directory {
+				std::env::set_var("OLDPWD", previous_directory);
+			}
+			let _ = syscall!(
+				c_wrapper::cwd::getcwd; 
+				std::env::set_var("PWD", it)
+			);
+			Ok(0)
+		},
+		Err(Error::EnvChangeRequested(key, value)) => {
+			std::env::set_var(&key, value);
+			settings::variable_changed(&key);
+			Ok(0)
+		},
+		Err(Error::LocalEnvChangeRequested(key, value)) => {
+			std::env::set_var(&key, value);
+			settings::variable_changed(&key);
+			functions::mark_local(key);
+			Ok(0)
+		},
+		Err(Error::EnvRemoveRequested(key)) => {
+			std::env::remove_var(&key);
+			settings::variable_changed(&key);
+			Ok(0)
+		}
+		Err(Error::FunctionEraseRequested(names)) => {
+			for name in names {
+				functions::erase(&name);
+			}
+			Ok(0)
+		}
+		Err(Error::AliasChangeRequested { kind, name, expansion }) => {
+			crate::alias::define(kind, name, expansion);
+			Ok(0)
+		}
+		Err(Error::SettingsChangeRequested(settings)) => {
+			settings::replace(settings);
+			Ok(0)
+		}
+		Err(Error::StateChangesRequested { status, changes }) => {
+			for change in changes {
+				apply_state_change(Err(change))?;
+			}
+			Ok(status)
+		}
+		any => any,
+	}
+}
+
+fn print_syntax_tree(command: &SyntaxTree) {
 	static INDENT_STR: &str = "    ";
-	let print_tree = env_is_true("print_syntax_tree");
-	if print_tree {
+	{
 		eprintln!("\x1b[4m$print_syntax_tree\x1b[24m:");
 		fn ci_print(item: &CommandItem, indent: usize) {
-			match item {
-			    CommandItem::String(s) => {
+			match &item.kind {
+			    CommandItemKind::String(s) => {
 					eprintln!("{}String: {}", INDENT_STR.repeat(indent), s);
 				}
-			    CommandItem::RawString(rs) => {
+			    CommandItemKind::RawString(rs) => {
 					eprintln!("{}Raw String: {}", INDENT_STR.repeat(indent), rs);
 				}
-			    CommandItem::ShellVariable(var) => {
+			    CommandItemKind::ShellVariable(var) => {
 					eprintln!("{}Shell Variable: {}", INDENT_STR.repeat(indent), var);
 				}
-			    CommandItem::Subcommand(sc) => {
+			    CommandItemKind::IndexedVariable { name, index } => {
+					eprintln!("{}Indexed Shell Variable: {} [{}]", INDENT_STR.repeat(indent), name, index);
+				}
+			    CommandItemKind::Subcommand(sc) => {
 					eprintln!("{}Subcommand", INDENT_STR.repeat(indent));
-					cs_print(sc, indent + 1);
+					st_print(sc, indent + 1);
+				}
+			    CommandItemKind::QuotedSubcommand(sc) => {
+					eprintln!("{}Quoted Subcommand", INDENT_STR.repeat(indent));
+					st_print(sc, indent + 1);
+				}
+			    CommandItemKind::BraceExpansion(BraceExpansion::List(items)) => {
+					for (index, item) in items.iter().enumerate() {
+						eprintln!("{}Brace expansion item {}", INDENT_STR.repeat(indent), index + 1);
+						ci_print(item, indent + 1);
+					}
+				}
+			    CommandItemKind::BraceExpansion(range) => {
+					eprintln!("{}Brace range: {}", INDENT_STR.repeat(indent), range);
 				}
-			    CommandItem::Combination(cmb) => {
+			    CommandItemKind::VariableExpansion { .. } => {
+					eprintln!("{}Variable expansion: {}", INDENT_STR.repeat(indent), item);
+				}
+			    CommandItemKind::ArithmeticExpansion(expression) => {
+					eprintln!("{}Arithmetic expansion: {}", INDENT_STR.repeat(indent), expression);
+				}
+			    CommandItemKind::Combination(cmb) => {
 					for (index, item) in cmb.iter().enumerate() {
 						eprintln!("{}Combination item {}", INDENT_STR.repeat(indent), index + 1);
 						ci_print(item, indent + 1);
@@ -162,8 +250,8 @@ fn handle_tree(command: syntax_parser::SyntaxTree) -> crate::Result<u8> {
 			}
 		}
 		fn cs_print(command: &CommandSyntax, indent: usize) {
-			match command {
-			    CommandSyntax::InputRedirection { command, file_descriptor, filename } => {
+			match &command.kind {
+			    CommandSyntaxKind::InputRedirection { command, file_descriptor, filename } => {
 					eprintln!("{}Input redirection", INDENT_STR.repeat(indent));
 					eprintln!(
 						"{}File descriptor: {}", 
@@ -177,7 +265,7 @@ fn handle_tree(command: syntax_parser::SyntaxTree) -> crate::Result<u8> {
 					);
 					cs_print(command, indent + 1);
 				}
-			    CommandSyntax::OutputRedirection { command, file_descriptor, destination, kind } => {
+			    CommandSyntaxKind::OutputRedirection { command, file_descriptor, destination, kind } => {
 					eprintln!("{}Output redirection", INDENT_STR.repeat(indent));
 					eprintln!(
 						"{}File descriptor: {}", 
@@ -201,7 +289,7 @@ fn handle_tree(command: syntax_parser::SyntaxTree) -> crate::Result<u8> {
 					);
 					cs_print(command, indent + 1);
 				}
-			    CommandSyntax::Command(cmd) => {
+			    CommandSyntaxKind::Command(cmd) => {
 					for (index, item) in cmd.iter().enumerate() {
 						eprintln!("{}Command item {}", INDENT_STR.repeat(indent), index + 1);
 						ci_print(item, indent + 1);
@@ -209,32 +297,221 @@ fn handle_tree(command: syntax_parser::SyntaxTree) -> crate::Result<u8> {
 				}
 			}
 		}
-		fn st_print(tree: &syntax_parser::SyntaxTree, indent: usize) {
-			match tree {
-			    syntax_parser::SyntaxTree::Command(cs) => {
+		fn st_print(tree: &SyntaxTree, indent: usize) {
+			match &tree.kind {
+			    SyntaxTreeKind::Command(cs) => {
 					eprintln!("{}Command", INDENT_STR.repeat(indent));
 					cs_print(cs, indent + 1);
 				}
-			    syntax_parser::SyntaxTree::PipeChain(chain) => {
+			    SyntaxTreeKind::PipeChain(chain) => {
 					for (index, cs) in chain.iter().enumerate() {
 						eprintln!("{}Pipe chain - Command {}", INDENT_STR.repeat(indent), index + 1);
 						cs_print(cs, indent + 1);
 					}
 				}
+			    SyntaxTreeKind::Sequence(statements) => {
+					for (index, statement) in statements.iter().enumerate() {
+						eprintln!("{}Statement {}", INDENT_STR.repeat(indent), index + 1);
+						st_print(statement, indent + 1);
+					}
+				}
+			    SyntaxTreeKind::If { branches, otherwise } => {
+					for (index, (condition, body)) in branches.iter().enumerate() {
+						eprintln!("{}If - Condition {}", INDENT_STR.repeat(indent), index + 1);
+						st_print(condition, indent + 1);
+						eprintln!("{}If - Body {}", INDENT_STR.repeat(indent), index + 1);
+						st_print(body, indent + 1);
+					}
+					if let Some(otherwise) = otherwise {
+						eprintln!("{}If - Else", INDENT_STR.repeat(indent));
+						st_print(otherwise, indent + 1);
+					}
+				}
+			    SyntaxTreeKind::For { variable, items, body } => {
+					eprintln!("{}For - Variable: {}", INDENT_STR.repeat(indent), variable);
+					for (index, item) in items.iter().enumerate() {
+						eprintln!("{}For - Item {}", INDENT_STR.repeat(indent), index + 1);
+						ci_print(item, indent + 1);
+					}
+					eprintln!("{}For - Body", INDENT_STR.repeat(indent));
+					st_print(body, indent + 1);
+				}
+			    SyntaxTreeKind::While { condition, body } => {
+					eprintln!("{}While - Condition", INDENT_STR.repeat(indent));
+					st_print(condition, indent + 1);
+					eprintln!("{}While - Body", INDENT_STR.repeat(indent));
+					st_print(body, indent + 1);
+				}
+			    SyntaxTreeKind::Switch { value, cases } => {
+					eprintln!("{}Switch - Value", INDENT_STR.repeat(indent));
+					for item in value {
+						ci_print(item, indent + 1);
+					}
+					for (index, (patterns, body)) in cases.iter().enumerate() {
+						eprintln!("{}Switch - Case {} patterns", INDENT_STR.repeat(indent), index + 1);
+						for pattern in patterns {
+							ci_print(pattern, indent + 1);
+						}
+						eprintln!("{}Switch - Case {} body", INDENT_STR.repeat(indent), index + 1);
+						st_print(body, indent + 1);
+					}
+				}
+			    SyntaxTreeKind::Function { name, body } => {
+					eprintln!("{}Function: {}", INDENT_STR.repeat(indent), name);
+					st_print(body, indent + 1);
+				}
+			    SyntaxTreeKind::RedirectedBlock { block, redirection } => {
+					eprintln!("{}Redirected block", INDENT_STR.repeat(indent));
+					cs_print(redirection, indent + 1);
+					st_print(block, indent + 1);
+				}
 			}
 		}
 
-		st_print(&command, 0);
+		st_print(command, 0);
 	}
+}
 
-	match command {
-	    syntax_parser::SyntaxTree::Command(c) => handle_command(c),
-	    syntax_parser::SyntaxTree::PipeChain(chain) => handle_pipe(chain),
+/// Evaluates a syntax tree in the shell process.
+///
+/// Control flow is evaluated right here, while commands and pipe chains run
+/// in a forked child. The state changes requested by the child, like those of
+/// cd or ~set, are applied here afterwards, so that the following statements
+/// of a block see them as well.
+fn handle_tree(tree: SyntaxTree, redirections: &[Rc<BlockRedirection>]) -> crate::Result<u8> {
+	match tree.kind {
+	    SyntaxTreeKind::Command(_) | SyntaxTreeKind::PipeChain(_) => handle_forked(tree, redirections),
+	    SyntaxTreeKind::Sequence(statements) => {
+			let mut status = 0;
+			for statement in statements {
+				status = handle_statement(statement, redirections)?;
+			}
+			Ok(status)
+		}
+	    SyntaxTreeKind::If { branches, otherwise } => {
+			for (condition, body) in branches {
+				if handle_statement(condition, redirections)? == 0 {
+					return handle_tree(body, redirections);
+				}
+			}
+			match otherwise {
+				Some(otherwise) => handle_tree(*otherwise, redirections),
+				None => Ok(0),
+			}
+		}
+	    SyntaxTreeKind::For { variable, items, body } => {
+			let mut words = vec![];
+			for item in items {
+				words.append(&mut evaluate_command_item(item)?);
+			}
+			let mut status = 0;
+			for word in words {
+				std::env::set_var(&variable, word);
+				settings::variable_changed(&variable);
+				match handle_tree((*body).clone(), redirections) {
+					Ok(body_status) => status = body_status,
+					Err(Error::BreakRequested) => break,
+					Err(Error::ContinueRequested) => continue,
+					Err(error) => return Err(error),
+				}
+			}
+			Ok(status)
+		}
+	    SyntaxTreeKind::While { condition, body } => {
+			let mut status = 0;
+			while handle_statement((*condition).clone(), redirections)? == 0 {
+				match handle_tree((*body).clone(), redirections) {
+					Ok(body_status) => status = body_status,
+					Err(Error::BreakRequested) => break,
+					Err(Error::ContinueRequested) => continue,
+					Err(error) => return Err(error),
+				}
+			}
+			Ok(status)
+		}
+	    SyntaxTreeKind::Switch { value, cases } => {
+			let mut words = vec![];
+			for item in value {
+				words.append(&mut evaluate_command_item(item)?);
+			}
+			if words.len() != 1 {
+				return Err(Error::OtherError(format!(
+					"\x1b[4mswitch\x1b[24m: Expected the value to expand to 1 word, but it expanded to {}",
+					words.len(),
+				)));
+			}
+			let value = &words[0];
+			for (patterns, body) in cases {
+				for pattern in patterns {
+					// Quoting doesn't keep wildcards from matching here, as patterns
+					// are never matched against files
+					for pattern in evaluate_command_item_2(pattern, false)? {
+						if Pattern::from_word(&GlobWord::unquoted(&pattern.text())).matches(value) {
+							return handle_tree(body, redirections);
+						}
+					}
+				}
+			}
+			Ok(0)
+		}
+	    SyntaxTreeKind::Function { name, body } => {
+			functions::define(name, *body);
+			Ok(0)
+		}
+	    SyntaxTreeKind::RedirectedBlock { block, redirection } => {
+			// The files are opened once, so that > doesn't complain about the
+			// file created by the first command of the block
+			let mut redirections = redirections.to_vec();
+			let mut layer = redirection;
+			loop {
+				layer = match layer.kind {
+				    CommandSyntaxKind::InputRedirection { command, file_descriptor, filename } => {
+						redirections.push(Rc::new(BlockRedirection {
+							file_descriptor: file_descriptor.unwrap_or(file::constants::STDIN_FILENO),
+							file: open_input_redirection(filename)?,
+						}));
+						*command
+					}
+				    CommandSyntaxKind::OutputRedirection { command, file_descriptor, destination, kind } => {
+						redirections.push(Rc::new(BlockRedirection {
+							file_descriptor: file_descriptor.unwrap_or(file::constants::STDOUT_FILENO),
+							file: open_output_redirection(destination, kind)?,
+						}));
+						*command
+					}
+				    CommandSyntaxKind::Command(_) => break,
+				}
+			}
+			handle_tree(*block, &redirections)
+		}
 	}
 }
 
+/// Evaluates a statement of a block. Its errors are reported right away
+/// instead of being returned, so that the rest of the block still runs.
+/// Only break, continue and return are passed on to the enclosing block.
+fn handle_statement(statement: SyntaxTree, redirections: &[Rc<BlockRedirection>]) -> crate::Result<u8> {
+	let source = statement.to_string();
+	let status = match handle_tree(statement, redirections) {
+		Ok(status) => status,
+		Err(error @ Error::BreakRequested)
+		| Err(error @ Error::ContinueRequested)
+		| Err(error @ Error::ReturnRequested(_)) => return Err(error),
+		Err(error) => crate::report_error(error, &source).unwrap_or_else(crate::last_status),
+	};
+	std::env::set_var("status", status.to_string());
+	Ok(status)
+}
+
+/// A file opened for a redirection after the end of a block. Every command
+/// of the block redirects the file descriptor to it in its child.
+struct BlockRedirection {
+	file_descriptor: i32,
+	file: FileDescriptor,
+}
+
 fn handle_pipe(commands: Vec<CommandSyntax>) -> crate::Result<u8> {
-	let mut old_pipe_read = None;
+	let mut old_pipe_read: Option<FileDescriptor> = None;
 	for (index, command) in commands.iter().enumerate() {
 		let new_pipe = syscall!(pipe)?;
 		syscall!(fork match {
@@ -262,7 +539,11 @@ fn handle_pipe(commands: Vec<CommandSyntax>) -> crate::Result<u8> {
 				// Since the child process is in another process entirely from
 				// the parent process, there's no read way to transmit the error to the 
 				// parent, so at least have it visible.
-				let cmd_result = handle_command(command.clone()).unwrap();
+				let cmd_result = match handle_command(command.clone()) {
+					// Like in a subcommand, state changes are lost in a pipe chain
+					Err(Error::StateChangesRequested { status, .. }) => status,
+					result => result.unwrap(),
+				};
 				// Exit with the exit call of the child
 				// This is only relevant for the last command in the chain, which will
 				// be waited for by the main process, and whose exit code will be 
@@ -274,7 +555,7 @@ fn handle_pipe(commands: Vec<CommandSyntax>) -> crate::Result<u8> {
 				old_pipe_read = Some(new_pipe.drop_write());
 				// If this is the last process, wait for it and return its status
 				if index == commands.len() - 1 {
-					return syscall!(wait::waitpid(child_pid); res => res.status.exit_status())
+					return syscall!(retry wait::waitpid(child_pid); res => res.status.exit_status())
 				}
 			}
 		})?
@@ -282,16 +563,10 @@ fn handle_pipe(commands: Vec<CommandSyntax>) -> crate::Result<u8> {
 	panic!("Reached end of pipe loop without returning the status of last command")
 }
 
-fn handle_command(command: syntax_parser::CommandSyntax) -> crate::Result<u8> {
-	match command {
-	    CommandSyntax::InputRedirection { command, file_descriptor, filename } => {
-			// Open the given file, possibly returning error
-			let file = syscall!(
-				open::open_with_flags(CString::new(filename.clone()).unwrap(), file::open::flags::O_RDONLY); 
-				match_error {
-					CError::NotFound => crate::Error::FileNotFound(Some(filename)),
-				}
-			)?;
+fn handle_command(command: CommandSyntax) -> crate::Result<u8> {
+	match command.kind {
+	    CommandSyntaxKind::InputRedirection { command, file_descriptor, filename } => {
+			let file = open_input_redirection(filename)?;
 			// Redirect stdin by default
 			let file_descriptor = file_descriptor.unwrap_or(file::constants::STDIN_FILENO);
 			// Redirect the file descriptor to the file
@@ -305,34 +580,8 @@ fn handle_command(command: syntax_parser::CommandSyntax) -> crate::Result<u8> {
 			// Run command
 			handle_command(*command)
 		}
-	    CommandSyntax::OutputRedirection { command, file_descriptor, destination, kind } => {
-			// Open the given file, possibly returning error
-			let file_flags = file::open::flags::O_WRONLY | match kind {
-			    syntax_parser::OutputRedirectionKind::Create => {
-					// Check if file exists
-					if syscall!(access(CString::new(destination.clone()).unwrap(), AccessCheck::FileExists))? {
-						return Err(
-							Error::OtherError(
-								format!("The file {} already exists. Use >| to overwrite the file or >> to append to it.", destination)
-							)
-						);
-					}
-					file::open::flags::O_CREAT
-				}
-			    syntax_parser::OutputRedirectionKind::Append => {
-					file::open::flags::O_APPEND | file::open::flags::O_CREAT
-				}
-			    syntax_parser::OutputRedirectionKind::Overwrite => {
-					file::open::flags::O_TRUNC
-				}
-			};
-			let file = syscall!(
-				open::open_with_mode(CString::new(destination.clone()).unwrap(), file_flags, 0o777); 
-				match_error {
-					CError::NotFound => crate::Error::FileNotFound(Some(destination)),
-					CError::PermissionDenied => crate::Error::FilePermissionDenied(Some(destination)),
-				}
-			)?;
+	    CommandSyntaxKind::OutputRedirection { command, file_descriptor, destination, kind } => {
+			let file = open_output_redirection(destination, kind)?;
 			// Redirect stdout by default
 			let file_descriptor = file_descriptor.unwrap_or(file::constants::STDOUT_FILENO);
 			// Redirect the file descriptor to the file
@@ -342,133 +591,516 @@ fn handle_command(command: syntax_parser::CommandSyntax) -> crate::Result<u8> {
 			// Run command
 			handle_command(*command)
 		}
-	    CommandSyntax::Command(command_items) => {
-			let command_items: crate::Result<Vec<String>> =
-				command_items
+	    CommandSyntaxKind::Command(command_items) => {
+			let command_items: crate::Result<Vec<Vec<String>>> =
+				expand_aliases(command_items)?
 				.into_iter()
 				.map(|ci| {
 					evaluate_command_item(ci)
 				})
         		.collect();
-			execute_command(&command_items?)
+			let command: Vec<String> = command_items?.into_iter().flatten().collect();
+			if command.is_empty() {
+				// Everything expanded to nothing, e.g. (true)
+				return Ok(0);
+			}
+			execute_command(&command)
+		}
+	}
+}
+
+/// Replaces an alias at the start of a command by its expansion, as long as
+/// the command starts with an unquoted word. Every alias is expanded at most
+/// once, so that `alias ls 'ls --color'` doesn't recurse.
+fn expand_aliases(mut command_items: Vec<CommandItem>) -> crate::Result<Vec<CommandItem>> {
+	let mut expanded = vec![];
+	while let Some(CommandItem { kind: CommandItemKind::RawString(name), .. }) = command_items.first() {
+		if expanded.contains(name) {
+			break;
+		}
+		let expansion = match crate::alias::get(AliasKind::Alias, name) {
+			Some(expansion) => expansion,
+			None => break,
+		};
+		let items = match syntax_parser::parse_command(0, &expansion) {
+			Ok(CommandSyntax { kind: CommandSyntaxKind::Command(items), .. }) => items,
+			_ => return Err(Error::OtherError(format!("The alias {} is not a valid command: {}", name, expansion))),
+		};
+		expanded.push(name.clone());
+		command_items.splice(0..1, items);
+	}
+	Ok(command_items)
+}
+
+fn open_input_redirection(filename: String) -> crate::Result<FileDescriptor> {
+	let filename = evaluate_redirection_target(filename)?;
+	// Open the given file, possibly returning error
+	syscall!(
+		retry open::open_with_flags(CString::new(filename.clone()).unwrap(), file::open::flags::O_RDONLY); 
+		match_error file(filename)
+	)
+}
+
+fn open_output_redirection(destination: String, kind: syntax_parser::OutputRedirectionKind) -> crate::Result<FileDescriptor> {
+	let destination = evaluate_redirection_target(destination)?;
+	// Open the given file, possibly returning error
+	let file_flags = file::open::flags::O_WRONLY | match kind {
+	    syntax_parser::OutputRedirectionKind::Create => {
+			// Check if file exists
+			if syscall!(access(CString::new(destination.clone()).unwrap(), AccessCheck::FileExists))? {
+				return Err(
+					Error::OtherError(
+						format!("The file {} already exists. Use >| to overwrite the file or >> to append to it.", destination)
+					)
+				);
+			}
+			file::open::flags::O_CREAT
+		}
+	    syntax_parser::OutputRedirectionKind::Append => {
+			file::open::flags::O_APPEND | file::open::flags::O_CREAT
 		}
+	    syntax_parser::OutputRedirectionKind::Overwrite => {
+			file::open::flags::O_TRUNC
+		}
+	};
+	syscall!(
+		retry open::open_with_mode(CString::new(destination.clone()).unwrap(), file_flags, 0o777); 
+		match_error file(destination)
+	)
+}
+
+/// Evaluates the target of a redirection, which has to expand to exactly
+/// one word. File descriptor targets like &2 are returned as they are.
+fn evaluate_redirection_target(target: String) -> crate::Result<String> {
+	if target.starts_with('&') {
+		return Ok(target);
+	}
+	let mut words = evaluate_command_item(syntax_parser::parse_command_item(0, &target)?)?;
+	if words.len() != 1 {
+		return Err(Error::OtherError(format!(
+			"The redirection target {} expanded to {} words instead of 1",
+			target,
+			words.len(),
+		)));
+	}
+	Ok(words.remove(0))
+}
+
+// The most words a range like {1..10} can expand to
+const MAX_RANGE_LENGTH: i128 = 1_000_000;
+
+/// The values of a range from start to end, stepping towards end by step
+fn range_values(start: i64, end: i64, step: i64, range: &BraceExpansion) -> crate::Result<impl Iterator<Item = i64>> {
+	let length = (i128::from(end) - i128::from(start)).abs() / i128::from(step) + 1;
+	if length > MAX_RANGE_LENGTH {
+		return Err(Error::OtherError(format!("The range {} has more than {} elements", range, MAX_RANGE_LENGTH)));
 	}
+	let step = if start <= end { step } else { -step };
+	// Stops early instead of overflowing at the ends of i64
+	let values = std::iter::successors(Some(start), move |value| value.checked_add(step));
+	Ok(values.take_while(move |value| if step > 0 { *value <= end } else { *value >= end }))
 }
 
-fn evaluate_command_item(command_item: CommandItem) -> crate::Result<String> {
-	evaluate_command_item_2(command_item, false)
+fn evaluate_command_item(command_item: CommandItem) -> crate::Result<Vec<String>> {
+	let mut result = vec![];
+	for mut word in evaluate_command_item_2(command_item, false)? {
+		tilde::expand(&mut word);
+		if !word.has_wildcards() {
+			result.push(word.text());
+			continue;
+		}
+		let matches = glob::expand(&word);
+		if !matches.is_empty() {
+			result.extend(matches);
+		}
+		else if settings::get().glob_pass_unmatched {
+			result.push(word.text());
+		}
+		else {
+			return Err(Error::NoGlobMatch(word.text()));
+		}
+	}
+	Ok(result)
 }
 
-fn evaluate_command_item_2(command_item: CommandItem, raw_as_normal_str: bool) -> crate::Result<String> {
-	match command_item {
-	    CommandItem::String(s) => Ok(s),
-	    CommandItem::ShellVariable(var_name) => {
-			Ok(std::env::var(var_name).unwrap_or("".to_string()))
+/// Evaluates a command item into the words it expands to.
+///
+/// Most items expand to exactly one word. An unquoted subcommand expands to
+/// one word per line of its output, and to no words if there is no output.
+/// A brace expansion expands to each of its items or each value in its range.
+/// A `${...}` expansion can expand to the words of its default or alternative.
+/// A combination expands to the cartesian product of the words of its items,
+/// in order: `a(printf 'b\nc')d` becomes `abd acd`. If any of its items
+/// expands to no words, so does the whole combination.
+fn evaluate_command_item_2(command_item: CommandItem, raw_as_normal_str: bool) -> crate::Result<Vec<GlobWord>> {
+	match command_item.kind {
+	    CommandItemKind::String(s) => Ok(vec![GlobWord::quoted(&s)]),
+	    CommandItemKind::ShellVariable(var_name) => {
+			if variable::is_list(&var_name) {
+				// A list expands to one word per element
+				let elements = variable::get_list(&var_name).unwrap_or_default();
+				Ok(elements.iter().map(|element| GlobWord::quoted(element)).collect())
+			}
+			else {
+				Ok(vec![GlobWord::quoted(&std::env::var(var_name).unwrap_or("".to_string()))])
+			}
+		}
+	    CommandItemKind::IndexedVariable { name, index } => {
+			let elements = variable::get_list(&name).unwrap_or_default();
+			Ok(variable::slice(&elements, &index).iter().map(|element| GlobWord::quoted(element)).collect())
+		}
+	    CommandItemKind::Subcommand(sc) => {
+			let output = subcommand_output(*sc)?;
+			Ok(output.lines().map(GlobWord::quoted).collect())
+		}
+	    CommandItemKind::QuotedSubcommand(sc) => {
+			let output = subcommand_output(*sc)?;
+			Ok(vec![GlobWord::quoted(output.trim_end_matches('\n'))])
+		}
+	    CommandItemKind::BraceExpansion(BraceExpansion::List(items)) => {
+			let mut result = vec![];
+			for item in items {
+				result.append(&mut evaluate_command_item_2(item, true)?);
+			}
+			Ok(result)
+		}
+	    CommandItemKind::BraceExpansion(ref range @ BraceExpansion::NumericRange { start, end, step, width }) => {
+			let values = range_values(start, end, step, range)?;
+			Ok(values.map(|value| GlobWord::quoted(&format!("{:0width$}", value, width = width))).collect())
+		}
+	    CommandItemKind::BraceExpansion(ref range @ BraceExpansion::CharacterRange { start, end, step }) => {
+			let values = range_values(start as i64, end as i64, step, range)?;
+			Ok(values.filter_map(|value| std::char::from_u32(value as u32)).map(|c| GlobWord::quoted(&c.to_string())).collect())
+		}
+	    CommandItemKind::VariableExpansion { name, modifier } => {
+			evaluate_variable_expansion(name, modifier)
 		}
-	    CommandItem::Subcommand(sc) => {
-			// Make pipe, run sc, return stdout of sc
-			let (_, mut read_pipe) = handle_subcommand(sc.to_string(), None::<Vec<u8>>)?;
-			let mut res = String::new();
-			let _ = read_pipe.read_to_string(&mut res);
-			Ok(res.trim().to_owned())
+	    CommandItemKind::ArithmeticExpansion(expression) => {
+			match expression.evaluate() {
+				Ok(number) => Ok(vec![GlobWord::quoted(&number.to_string())]),
+				Err(error) => Err(Error::MathError {
+					expression: expression.source().to_owned(),
+					error,
+				}),
+			}
 		}
-	    CommandItem::Combination(items) => {
-			let mut result = String::new();
+	    CommandItemKind::Combination(items) => {
+			let mut result = vec![GlobWord::default()];
 
 			for item in items {
-				result += &evaluate_command_item_2(item, true)?;
+				let words = evaluate_command_item_2(item, true)?;
+				result = result
+					.iter()
+					.flat_map(|prefix| words.iter().map(move |word| {
+						let mut combined = prefix.clone();
+						combined.append(word);
+						combined
+					}))
+					.collect();
 			}
 
 			Ok(result)
 		}
-	    CommandItem::RawString(rs) => {
+	    CommandItemKind::RawString(rs) => {
 			// TODO: Is RawString actually necessary?
 			// 2020-12-31 21:26  Okay, seriously, why the heck did I do this? XD
 			if raw_as_normal_str {
-				Ok(rs)
+				Ok(vec![GlobWord::unquoted(&rs)])
 			}
 			else {
-				Ok(rs)
+				Ok(vec![GlobWord::unquoted(&rs)])
 			}
 		}
 	}
 }
 
+fn evaluate_variable_expansion(name: String, modifier: VariableModifier) -> crate::Result<Vec<GlobWord>> {
+	// Evaluates a word inside the expansion, joining it if it expands to several
+	fn joined(item: CommandItem) -> crate::Result<GlobWord> {
+		let mut result = GlobWord::default();
+		for (index, word) in evaluate_command_item_2(item, true)?.iter().enumerate() {
+			if index != 0 {
+				result.append(&GlobWord::quoted(" "));
+			}
+			result.append(word);
+		}
+		Ok(result)
+	}
+
+	let value = variable::get_joined(&name).unwrap_or_default();
+	let result = match modifier {
+		VariableModifier::Default(word) => {
+			if value.is_empty() {
+				return evaluate_command_item_2(*word, true);
+			}
+			value
+		}
+		VariableModifier::Alternative(word) => {
+			if !value.is_empty() {
+				return evaluate_command_item_2(*word, true);
+			}
+			value
+		}
+		VariableModifier::ErrorIfUnset(message) => {
+			if value.is_empty() {
+				let message = joined(*message)?.text();
+				return Err(Error::OtherError(format!(
+					"\x1b[4m${}\x1b[24m: {}",
+					name,
+					if message.is_empty() { "The variable is not set" } else { &message },
+				)));
+			}
+			value
+		}
+		VariableModifier::Length if variable::is_list(&name) => {
+			variable::get_list(&name).unwrap_or_default().len().to_string()
+		}
+		VariableModifier::Length => value.chars().count().to_string(),
+		VariableModifier::RemovePrefix { pattern, longest } => {
+			Pattern::from_word(&joined(*pattern)?).strip_prefix(&value, longest).to_owned()
+		}
+		VariableModifier::RemoveSuffix { pattern, longest } => {
+			Pattern::from_word(&joined(*pattern)?).strip_suffix(&value, longest).to_owned()
+		}
+		VariableModifier::Substitute { pattern, replacement, all } => {
+			let replacement = joined(*replacement)?.text();
+			Pattern::from_word(&joined(*pattern)?).replace(&value, &replacement, all)
+		}
+		VariableModifier::Substring { offset, length } => {
+			let chars: Vec<char> = value.chars().collect();
+			let count = chars.len() as i64;
+			// Negative numbers count from the end
+			let start = if offset < 0 { count + offset } else { offset }.max(0).min(count);
+			let end = match length {
+				None => count,
+				Some(length) if length < 0 => count + length,
+				Some(length) => start.saturating_add(length),
+			}.max(start).min(count);
+			chars[start as usize..end as usize].iter().collect()
+		}
+	};
+	Ok(vec![GlobWord::quoted(&result)])
+}
+
+fn subcommand_output(subcommand: SyntaxTree) -> crate::Result<String> {
+	// Make pipe, run subcommand, return stdout of subcommand
+	let (_, mut read_pipe) = handle_subcommand(subcommand.to_string(), None::<Vec<u8>>)?;
+	let mut res = String::new();
+	let _ = read_pipe.read_to_string(&mut res);
+	Ok(res)
+}
+
+/// The builtins execute_command runs, with a short help for each
+pub(crate) static BUILTINS: &[(&str, &str)] = &[
+	("exit", "`exit [STATUS]` exits the shell, by default with the status of the last command"),
+	("~server", "`~server [PORT]` runs commands sent by clients connecting to PORT, 7500 by default"),
+	("~color_test", "`~color_test` prints the terminal colors"),
+	("~prompt", "`~prompt` prints the prompt"),
+	("~set", "`~set NAME VALUE` sets a plain string, while any other number of values makes a list. `--append` and `--prepend` add values to an existing list, and `~set 'NAME[INDEX]' VALUES...` replaces the selected elements. Inside of a function, `--local` keeps the change from reaching the caller."),
+	("~unset", "`~unset NAME` removes a variable, while `~unset 'NAME[INDEX]'` only removes the selected elements from a list"),
+	("~count", "`~count ARGUMENTS...` prints the number of arguments, so `~count $xs` is the length of a list"),
+	("~math", "`~math EXPRESSION` prints the value of an arithmetic expression"),
+	("~config", "`~config` prints the settings, `~config get KEY` prints one and `~config set KEY VALUE` changes one for the session. `~config reload` reads the config file again, dropping the changes made with set."),
+	("~trace", "`~trace on` prints every command before it runs, and with `--syscalls` also the syscalls it makes. `--file PATH` appends the trace to a file instead of printing it to stderr. `~trace off` stops tracing, and `~trace` tells whether it is on."),
+	("~syscalls", "`~syscalls [COUNT]` prints the last COUNT syscalls recorded, 20 by default, with their arguments, results and how long they took. `~syscalls on` starts recording them and `~syscalls off` stops."),
+	("~ast", "`~ast COMMAND...` prints the syntax tree of the command as JSON"),
+	("break", "`break` ends the innermost loop"),
+	("continue", "`continue` starts the next iteration of the innermost loop"),
+	("return", "`return [STATUS]` ends the running function, by default with the status of the last command"),
+	("alias", "`alias` lists the aliases, `alias NAME` prints one, `alias NAME VALUE...` defines one and `alias --erase NAME...` removes them. With `--save`, the change is also made in the rc file."),
+	("abbr", "`abbr` is like `alias`, but for abbreviations, which expand while typing"),
+	("functions", "`functions` lists the names of all functions, `functions NAME...` prints their definitions and `functions --erase NAME...` removes them"),
+	("cd", "`cd PATH` changes the current directory"),
+	("env", "`env` prints the environment, and `env COMMAND...` runs a command"),
+	("head", "`head [-n LINES | -c BYTES] [FILES...]` prints the start of files"),
+	("cat", "`cat [OPTIONS] [FILES...]` prints files"),
+];
+
+/// The executable file called name in the first directory of $PATH having one
+pub(crate) fn find_in_path(name: &str) -> Option<PathBuf> {
+	let path = std::env::var_os("PATH")?;
+	std::env::split_paths(&path)
+		.map(|directory| directory.join(name))
+		.find(|file| file.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0))
+}
+
+/// The help of the builtin called name, if there is one
+pub(crate) fn builtin_help(name: &str) -> Option<&'static str> {
+	BUILTINS.iter().find(|(builtin, _)| *builtin == name).map(|(_, help)| *help)
+}
+
 fn execute_command(command: &[String]) -> crate::Result<u8> {
 	let command_executable = command[0].clone();
 
-	let who_is_running = env_is_true("who_is_running");
+	crate::trace::command(command, || {
+		if builtin_help(&command_executable).is_some() {
+			crate::trace::Runner::Builtin
+		}
+		else if functions::get(&command_executable).is_some() {
+			crate::trace::Runner::Function
+		}
+		else {
+			let path = if command_executable.contains('/') {
+				Some(PathBuf::from(&command_executable))
+			}
+			else {
+				find_in_path(&command_executable)
+			};
+			crate::trace::Runner::External(path)
+		}
+	});
 
 	if command_executable == "exit" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: exit")
-		}
 		exit_command(&command)
 	}
 	else if command_executable == "~server" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: ~server")
-		}
 		server::server(&command)
 	}
 	else if command_executable == "~color_test" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: ~color_test")
-		}
 		color_test()
 	}
 	else if command_executable == "~prompt" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: ~prompt")
-		}
 		prompt::prompt()
 	}
 	else if command_executable == "~set" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: ~set")
-		}
 		set::set(&command)
 	}
 	else if command_executable == "~unset" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: ~unset")
-		}
 		set::unset(&command)
 	}
+	else if command_executable == "~count" {
+		count::count(&command)
+	}
+	else if command_executable == "~math" {
+		math::math(&command)
+	}
+	else if command_executable == "~config" {
+		config::config(&command)
+	}
+	else if command_executable == "~trace" {
+		trace::trace(&command)
+	}
+	else if command_executable == "~syscalls" {
+		syscalls::syscalls(&command)
+	}
+	else if command_executable == "~ast" {
+		ast::ast(&command)
+	}
+	else if command_executable == "break" {
+		Err(Error::BreakRequested)
+	}
+	else if command_executable == "continue" {
+		Err(Error::ContinueRequested)
+	}
+	else if command_executable == "return" {
+		return_command(&command)
+	}
+	else if command_executable == "alias" {
+		alias::alias(&command)
+	}
+	else if command_executable == "abbr" {
+		alias::abbr(&command)
+	}
+	else if command_executable == "functions" {
+		functions::functions(&command)
+	}
 	else if command_executable == "cd" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: cd")
-		}
 		cd(&command)
 	}
 	else if command_executable == "env" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: env")
-		}
 		env::env(&command)
 	}
 	else if command_executable == "head" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: head")
-		}
 		head::head(&command)
 	}
 	else if command_executable == "cat" {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: Internal command: cat")
-		}
 		cat::cat(&command)
 	}
+	else if let Some(body) = functions::get(&command_executable) {
+		call_function(body, &command[1..])
+	}
 	// else if command detection
 	else {
-		if who_is_running {
-			eprintln!("\x1b[4m$who_is_running\x1b[24m: External command")
-		}
 		handle_extern(&command)
 	}
 
 }
 
+/// `return [STATUS]` ends the running function, by default with the status
+/// of the last command
+fn return_command(command: &[String]) -> crate::Result<u8> {
+	match command.get(1) {
+		Some(status) => match status.parse() {
+			Ok(status) => Err(Error::ReturnRequested(status)),
+			Err(_) => Err(Error::ExitCodeParseError(status.clone())),
+		},
+		None => Err(Error::ReturnRequested(crate::last_status())),
+	}
+}
+
+/// Runs the body of a function with $argv set to the arguments.
+///
+/// This already happens in the child of the command, so every change is passed
+/// on to the caller explicitly: variables that were set, changed or removed,
+/// except those declared with ~set --local, the settings and the working
+/// directory.
+fn call_function(body: SyntaxTree, arguments: &[String]) -> crate::Result<u8> {
+	let caller_variables: Vec<(String, String)> = std::env::vars().collect();
+	let caller_directory = c_wrapper::cwd::getcwd().ok();
+	let caller_settings = settings::configured();
+	functions::clear_locals();
+	std::env::set_var("argv", variable::encode_list("argv", arguments));
+
+	crate::trace::enter();
+	let result = handle_tree(body, &[]);
+	crate::trace::leave();
+	// A failed call still passes on what it changed before failing; the error
+	// goes last so that the caller applies the changes and then reports it
+	let (status, error) = match result {
+		Ok(status) => (status, None),
+		Err(Error::ReturnRequested(status)) => (status, None),
+		// Loops of the caller can't be broken out of from within a function
+		Err(Error::BreakRequested) | Err(Error::ContinueRequested) => (1, Some(Error::OtherError(
+			"break and continue can only be used inside of a loop of the same function".to_string()
+		))),
+		Err(error) => (1, Some(error)),
+	};
+
+	let mut changes = vec![];
+	let directory = c_wrapper::cwd::getcwd().ok();
+	if let Some(directory) = directory.filter(|directory| caller_directory.as_ref() != Some(directory)) {
+		changes.push(Error::ChdirRequested(directory));
+	}
+	let settings = settings::configured();
+	if settings != caller_settings {
+		changes.push(Error::SettingsChangeRequested(settings));
+	}
+	// The directory variables are updated by the caller's chdir
+	let passed_on = |key: &str| !["argv", "status", "PWD", "OLDPWD"].contains(&key) && !functions::is_local(key);
+	for (key, value) in std::env::vars() {
+		if passed_on(&key) && !caller_variables.iter().any(|(caller_key, _)| *caller_key == key) {
+			changes.push(Error::EnvChangeRequested(key, value));
+		}
+	}
+	for (key, caller_value) in caller_variables {
+		if !passed_on(&key) {
+			continue;
+		}
+		match std::env::var(&key) {
+			Ok(value) if value != caller_value => changes.push(Error::EnvChangeRequested(key, value)),
+			Ok(_) => {}
+			Err(_) => changes.push(Error::EnvRemoveRequested(key)),
+		}
+	}
+	changes.extend(error);
+	if changes.is_empty() {
+		Ok(status)
+	}
+	else {
+		Err(Error::StateChangesRequested { status, changes })
+	}
+}
+
 // fn redirect_then_handle(command: String) -> crate::Result<u8> {
 // 	let redirect_split = command.split('>').collect::<Vec<&str>>();
 // 	let append = if redirect_split.len() == 1 {
@@ -537,25 +1169,7 @@ fn handle_extern(command: &[String]) -> crate::Result<u8> {
 
 	syscall!(fork match no_wrap {
 		ForkResult::Child => {
-			if env_is_true("who_is_running_ext") {
-				eprintln!(" idx │ c │ dec │ hex ");
-				eprintln!("━━━━━┿━━━┿━━━━━┿━━━━━");
-				for (i, b) in command[0].clone().as_bytes().iter().enumerate() {
-					let c = char::from(*b);
-					eprintln!(
-						" {0:3} │ {1} │ {2:3} │ {2:3X} ", 
-						i, 
-						if c.is_control() {
-							' '
-						}
-						else {
-							c
-						}, 
-						b,
-					)
-				}
-			}
-
+			variable::export_lists();
 			syscall!(
 				exec::execp(&command[0], command);
 				match_error {
@@ -566,7 +1180,7 @@ fn handle_extern(command: &[String]) -> crate::Result<u8> {
 			exit(1)
 		},
 		ForkResult::Parent(child_pid) => {
-			match syscall!(wait::waitpid(child_pid); it.status.exit_status()) {
+			match syscall!(retry wait::waitpid(child_pid); it.status.exit_status()) {
 				Ok(127) => return Err(Error::CommandNotFound(command[0].clone())),
 				Ok(126) => return Err(Error::CommandPermissionDenied(command[0].clone())),
 				any => any,
@@ -0,0 +1,85 @@
+use crate::{alias::{self, AliasKind}, error::Error, rc, syntax_parser::{self, CommandSyntax, CommandSyntaxKind}};
+
+/// `alias` lists the aliases, `alias NAME` prints one, `alias NAME VALUE...`
+/// defines one and `alias --erase NAME...` removes them. With `--save`, the
+/// change is also made in the rc file.
+pub(crate) fn alias(command: &[String]) -> crate::Result<u8> {
+	manage(AliasKind::Alias, command)
+}
+
+/// Like `alias`, but for abbreviations, which expand while typing
+pub(crate) fn abbr(command: &[String]) -> crate::Result<u8> {
+	manage(AliasKind::Abbreviation, command)
+}
+
+fn manage(kind: AliasKind, command: &[String]) -> crate::Result<u8> {
+	let builtin = kind.builtin();
+	let mut arguments = &command[1..];
+	let mut erase = false;
+	let mut save = false;
+	while let Some(flag) = arguments.first() {
+		match flag.as_str() {
+			"-e" | "--erase" => erase = true,
+			"-s" | "--save" => save = true,
+			_ => break,
+		}
+		arguments = &arguments[1..];
+	}
+
+	if erase {
+		if arguments.is_empty() {
+			return Err(Error::OtherError(format!("\x1b[4m{}\x1b[24m: No name given to erase", builtin)));
+		}
+		let mut changes = vec![];
+		for name in arguments {
+			if alias::get(kind, name).is_none() {
+				return Err(Error::OtherError(format!("\x1b[4m{}\x1b[24m: {} is not defined", builtin, name)));
+			}
+			if save {
+				rc::save_definition(kind, name, None)?;
+			}
+			changes.push(Error::AliasChangeRequested { kind, name: name.clone(), expansion: None });
+		}
+		return Err(Error::StateChangesRequested { status: 0, changes });
+	}
+
+	match arguments {
+		[] => {
+			for (name, expansion) in alias::all(kind) {
+				println!("{}", definition(kind, &name, &expansion));
+			}
+			Ok(0)
+		}
+		[name] => match alias::get(kind, name) {
+			Some(expansion) => {
+				println!("{}", definition(kind, name, &expansion));
+				Ok(0)
+			}
+			None => Err(Error::OtherError(format!("\x1b[4m{}\x1b[24m: {} is not defined", builtin, name))),
+		},
+		[name, values @ ..] => {
+			let expansion = values.join(" ");
+			// Aliases are spliced into commands, so they have to be one themselves
+			let is_command = matches!(
+				syntax_parser::parse_command(0, &expansion),
+				Ok(CommandSyntax { kind: CommandSyntaxKind::Command(items), .. }) if !items.is_empty()
+			);
+			if kind == AliasKind::Alias && !is_command {
+				return Err(Error::OtherError(format!(
+					"\x1b[4m{}\x1b[24m: {} has to expand to a single command without redirections",
+					builtin,
+					name,
+				)));
+			}
+			if save {
+				rc::save_definition(kind, name, Some(&definition(kind, name, &expansion)))?;
+			}
+			Err(Error::AliasChangeRequested { kind, name: name.clone(), expansion: Some(expansion) })
+		}
+	}
+}
+
+/// The command defining name, as listed and saved
+fn definition(kind: AliasKind, name: &str, expansion: &str) -> String {
+	format!("{} {} {}", kind.builtin(), name, rc::quote(expansion))
+}
@@ -0,0 +1,17 @@
+use crate::{error::Error, syntax_parser::parse};
+
+/// Prints the syntax tree of the arguments, joined by spaces, as JSON
+pub(crate) fn ast(command: &[String]) -> crate::Result<u8> {
+	let source = command[1..].join(" ");
+	if source.trim().is_empty() {
+		return Err(Error::OtherError("\x1b[4m~ast\x1b[24m: No command given".to_owned()));
+	}
+	match parse(source.clone()) {
+		Ok(tree) => {
+			println!("{}", serde_json::to_string_pretty(&tree).expect("syntax trees can always be serialized"));
+			Ok(0)
+		}
+		// The spans of the errors are relative to the joined arguments
+		Err(errors) => Ok(crate::report_error(Error::ParseError(errors), &source).unwrap_or(2)),
+	}
+}
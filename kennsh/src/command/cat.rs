@@ -1,6 +1,6 @@
 use std::iter::once;
 
-use c_wrapper::{c_error::CError, file::FileDescriptor};
+use c_wrapper::file::FileDescriptor;
 use kennsh_syscall_macro::syscall;
 
 pub(crate) fn cat(command: &[String]) -> crate::Result<u8> {
@@ -30,13 +30,10 @@ pub(crate) fn cat(command: &[String]) -> crate::Result<u8> {
 fn cat_print(filename: &str, options: &CatOptions) -> crate::Result<()> {
 	let reader: FileDescriptor = match filename {
 		"-" => syscall!(FileDescriptor::try_clone_stdin)?,
-		filename => syscall!(c_wrapper::file::open::open_with_flags(
+		filename => syscall!(retry c_wrapper::file::open::open_with_flags(
 			std::ffi::CString::new(filename).unwrap(),
 			c_wrapper::file::open::flags::O_RDONLY,
-		); match_error {
-			CError::NotFound => crate::Error::FileNotFound(Some(filename.to_owned())),
-			CError::PermissionDenied => crate::Error::FilePermissionDenied(Some(filename.to_owned())),
-		})?,
+		); match_error file(filename))?,
 	};
 
 	// if options.no_buffering {
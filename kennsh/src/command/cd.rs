@@ -4,11 +4,7 @@ pub(crate) fn cd(command: &[String]) -> crate::Result<u8> {
 	match command.len() {
 		1 => Err(Error::OtherError("cd requires 1 parameter with the path to change into".to_owned())),
 		2 => {
-			let mut path = command[1].clone();
-			if let Some(index) = path.find('~') {
-				path.replace_range(index..=index, &std::env::var("HOME").unwrap());
-			}
-			Err(Error::ChdirRequested(path))
+			Err(Error::ChdirRequested(command[1].clone()))
 			// syscall!(chdir(CString::new(path).unwrap()))?;
 			// Ok(0)
 		},
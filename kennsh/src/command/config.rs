@@ -0,0 +1,42 @@
+use crate::{error::Error, settings};
+
+/// `~config` prints the settings, `~config get KEY` prints one and
+/// `~config set KEY VALUE` changes one for the session. `~config reload`
+/// reads the config file again, dropping the changes made with set.
+pub(crate) fn config(command: &[String]) -> crate::Result<u8> {
+	let arguments: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+	match arguments[..] {
+		[] => {
+			for (key, value) in settings::get().to_table() {
+				if settings::is_overridden(&key) {
+					println!("{} = {} # from ${}", key, value, key);
+				}
+				else {
+					println!("{} = {}", key, value);
+				}
+			}
+			Ok(0)
+		}
+		["get", key] => match settings::get().to_table().get(key) {
+			Some(value) => {
+				println!("{}", value);
+				Ok(0)
+			}
+			None => Err(Error::OtherError(format!("\x1b[4m~config\x1b[24m: There is no setting called {}", key))),
+		},
+		["set", key, value] => {
+			let changed = settings::configured()
+				.with(key, value)
+				.map_err(|error| Error::OtherError(format!("\x1b[4m~config\x1b[24m: {}", error)))?;
+			if settings::is_overridden(key) {
+				eprintln!("\x1b[4m~config\x1b[24m: ${} overrides the setting until it is unset", key);
+			}
+			Err(Error::SettingsChangeRequested(changed))
+		}
+		["reload"] => match settings::read() {
+			Ok(settings) => Err(Error::SettingsChangeRequested(settings)),
+			Err(error) => Err(Error::OtherError(format!("\x1b[4m~config\x1b[24m: The config file could not be loaded: {}", error))),
+		},
+		_ => Err(Error::OtherError("\x1b[4m~config\x1b[24m: Expected no arguments, get KEY, set KEY VALUE or reload".to_owned())),
+	}
+}
@@ -0,0 +1,6 @@
+/// Prints the number of arguments, so `~count $xs` is the length of a list
+pub(crate) fn count(command: &[String]) -> crate::Result<u8> {
+	let count = command.len() - 1;
+	println!("{}", count);
+	Ok(if count == 0 { 1 } else { 0 })
+}
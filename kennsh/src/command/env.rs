@@ -6,6 +6,7 @@ pub(crate) fn env(command: &[String]) -> crate::Result<u8> {
 	if command.len() == 1 {
 		// Print environment if no argument supplied
 		for (key, value) in std::env::vars() {
+			let value = crate::variable::exported_value(&key, &value);
 			let formatted_string = if should_print_color() {
 				format!("{2}{0}{4}={3}{1}{4}", key, value, "\x1b[94m", "\x1b[92m", "\x1b[39m")
 			}
@@ -107,7 +108,7 @@ pub(crate) fn env(command: &[String]) -> crate::Result<u8> {
 				let _ = std::io::Read::read_to_string(&mut result_read, &mut s);
 
 				let result = serde_json::from_str(&s).unwrap();
-				syscall!(wait::waitpid(child_pid))?;
+				syscall!(retry wait::waitpid(child_pid))?;
 
 				result
 			}
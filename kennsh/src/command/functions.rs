@@ -0,0 +1,79 @@
+//! Functions defined with `function NAME ... end`.
+//!
+//! The definitions are kept in the shell process, and as every command runs
+//! in a forked child, each child gets a copy of them to call.
+
+use std::{collections::{BTreeMap, BTreeSet}, sync::Mutex};
+
+use crate::{error::Error, syntax_parser::{Span, SyntaxTree, SyntaxTreeKind}};
+
+static FUNCTIONS: Mutex<BTreeMap<String, SyntaxTree>> = Mutex::new(BTreeMap::new());
+// Variables made local to the running function with ~set --local
+static LOCAL_VARIABLES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+pub(crate) fn define(name: String, body: SyntaxTree) {
+	FUNCTIONS.lock().unwrap().insert(name, body);
+}
+
+pub(crate) fn get(name: &str) -> Option<SyntaxTree> {
+	FUNCTIONS.lock().unwrap().get(name).cloned()
+}
+
+/// Removes a function, returning whether it was defined
+pub(crate) fn erase(name: &str) -> bool {
+	FUNCTIONS.lock().unwrap().remove(name).is_some()
+}
+
+pub(crate) fn mark_local(name: String) {
+	LOCAL_VARIABLES.lock().unwrap().insert(name);
+}
+
+pub(crate) fn is_local(name: &str) -> bool {
+	LOCAL_VARIABLES.lock().unwrap().contains(name)
+}
+
+/// Forgets the local variables, when a function call starts
+pub(crate) fn clear_locals() {
+	LOCAL_VARIABLES.lock().unwrap().clear();
+}
+
+/// `functions` lists the names of all functions, `functions NAME...` prints
+/// their definitions and `functions --erase NAME...` removes them
+pub(crate) fn functions(command: &[String]) -> crate::Result<u8> {
+	let arguments = &command[1..];
+	match arguments.first().map(String::as_str) {
+		None => {
+			for name in FUNCTIONS.lock().unwrap().keys() {
+				println!("{}", name);
+			}
+			Ok(0)
+		}
+		Some("-e") | Some("--erase") => {
+			if arguments.len() == 1 {
+				return Err(Error::OtherError("\x1b[4mfunctions\x1b[24m: No function name given to erase".to_owned()));
+			}
+			for name in &arguments[1..] {
+				if get(name).is_none() {
+					return Err(Error::OtherError(format!("\x1b[4mfunctions\x1b[24m: No function named {}", name)));
+				}
+			}
+			Err(Error::FunctionEraseRequested(arguments[1..].to_vec()))
+		}
+		Some(_) => {
+			let mut status = 0;
+			for name in arguments {
+				match get(name) {
+					Some(body) => println!("{}", SyntaxTree::new(
+						SyntaxTreeKind::Function { name: name.clone(), body: Box::new(body) },
+						Span::default(),
+					)),
+					None => {
+						eprintln!("\x1b[4mfunctions\x1b[24m: No function named {}", name);
+						status = 1;
+					}
+				}
+			}
+			Ok(status)
+		}
+	}
+}
@@ -1,6 +1,6 @@
 use std::ffi::CString;
 
-use c_wrapper::{c_error::CError, file::{FileDescriptor, open}};
+use c_wrapper::file::{FileDescriptor, open};
 use kennsh_syscall_macro::syscall;
 use skip_last_iter::SkipLastIterator;
 
@@ -156,11 +156,8 @@ pub(crate) fn head(command: &[String]) -> crate::Result<u8> {
 		}
 		else { 
 			syscall!(
-				open::open_with_flags(CString::new(file.to_string()).unwrap(), open::flags::O_RDONLY);
-				match_error {
-					CError::NotFound => crate::Error::FileNotFound(Some(file.to_owned())),
-					CError::PermissionDenied => crate::Error::FilePermissionDenied(Some(file.to_owned())),
-				}
+				retry open::open_with_flags(CString::new(file.to_string()).unwrap(), open::flags::O_RDONLY);
+				match_error file(file)
 			)?
 		};
 
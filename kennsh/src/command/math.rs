@@ -0,0 +1,16 @@
+use crate::{error::Error, math::Expression};
+
+pub(crate) fn math(command: &[String]) -> crate::Result<u8> {
+	let expression = command[1..].join(" ");
+	if expression.trim().is_empty() {
+		return Err(Error::OtherError("\x1b[4m~math\x1b[24m: No expression given".to_owned()));
+	}
+	let result = Expression::parse(&expression).and_then(|parsed| parsed.evaluate());
+	match result {
+		Ok(number) => {
+			println!("{}", number);
+			Ok(0)
+		}
+		Err(error) => Err(Error::MathError { expression, error }),
+	}
+}
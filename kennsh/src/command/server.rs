@@ -2,10 +2,10 @@ use std::net::{Ipv4Addr, SocketAddrV4};
 use c_wrapper::{file::FileDescriptor, fork::{ForkResult, fork}, pipe::pipe};
 use socket2::*;
 use uuid::Uuid;
-use crate::env_util::env_is_true;
+use crate::settings;
 
 pub(crate) fn server(command: &[String]) -> crate::Result<u8> {
-	let socket_debug = env_is_true("socket_debug");
+	let socket_debug = settings::get().socket_debug;
 
 	let port = if command.len() > 2 {
 		eprintln!("\x1b[4mserver\x1b[24m: More than 1 argument was supplied; all others will be ignored");
@@ -75,7 +75,7 @@ pub(crate) fn server(command: &[String]) -> crate::Result<u8> {
 use std::io::{Read, Write};
 
 fn handle_client(mut client: Client) {
-	let socket_debug = env_is_true("socket_debug");
+	let socket_debug = settings::get().socket_debug;
 	if socket_debug {
 		eprintln!("\x1b[4mserver\x1b[24m: {}: Starting thread", client.uuid);
 	}
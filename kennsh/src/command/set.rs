@@ -1,28 +1,111 @@
+use crate::{error::Error, syntax_parser::{self, ListIndex}, variable};
+
+enum Mode {
+	Assign,
+	Append,
+	Prepend,
+}
+
+/// `~set NAME VALUE` sets a plain string, while any other number of values
+/// makes a list. `--append` and `--prepend` add values to an existing list,
+/// and `~set 'NAME[INDEX]' VALUES...` replaces the selected elements.
+/// Inside of a function, `--local` keeps the change from reaching the caller.
 pub(crate) fn set(command: &[String]) -> crate::Result<u8> {
-	let command = &command[1..];
-	if command.len() < 2 {
-		Err(crate::Error::OtherError("\x1b[4munset\x1b[24m: Less than 2 arguments given".to_owned()))
+	let mut arguments = &command[1..];
+	let mut mode = Mode::Assign;
+	let mut local = false;
+	while let Some(flag) = arguments.first() {
+		match flag.as_str() {
+			"-a" | "--append" => mode = Mode::Append,
+			"-p" | "--prepend" => mode = Mode::Prepend,
+			"-l" | "--local" => local = true,
+			_ => break,
+		}
+		arguments = &arguments[1..];
 	}
-	else if command.len() == 2 {
-		Err(crate::Error::EnvChangeRequested(command[0].to_string(), command[1].to_string()))
+	let change = |name, value| if local {
+		Error::LocalEnvChangeRequested(name, value)
 	}
 	else {
-		eprintln!("\x1b[4munset\x1b[24m: More than 2 arguments were supplied; all others will be ignored");
-		unset(&command[..2])
-	}
+		Error::EnvChangeRequested(name, value)
+	};
+	let (target, values) = match arguments.split_first() {
+		Some(split) => split,
+		None => return Err(Error::OtherError("\x1b[4m~set\x1b[24m: No variable name given".to_owned())),
+	};
+	let (name, index) = split_target(target)?;
+
+	let current = variable::get_list(&name).unwrap_or_default();
+	let elements = match (mode, index) {
+		(Mode::Assign, None) if values.len() == 1 && !variable::is_path_variable(&name) => {
+			return Err(change(name, values[0].clone()));
+		}
+		(Mode::Assign, None) => values.to_vec(),
+		(Mode::Append, None) => current.into_iter().chain(values.iter().cloned()).collect(),
+		(Mode::Prepend, None) => values.iter().cloned().chain(current.into_iter()).collect(),
+		(Mode::Assign, Some(index)) => {
+			let positions = variable::positions(current.len(), &index);
+			if positions.len() != values.len() {
+				return Err(Error::OtherError(format!(
+					"\x1b[4m~set\x1b[24m: The index selects {} element(s) of ${}, but {} value(s) were given",
+					positions.len(),
+					name,
+					values.len(),
+				)));
+			}
+			let mut elements = current;
+			for (position, value) in positions.into_iter().zip(values) {
+				elements[position] = value.clone();
+			}
+			elements
+		}
+		(_, Some(_)) => {
+			return Err(Error::OtherError("\x1b[4m~set\x1b[24m: --append and --prepend can't be used with an index".to_owned()));
+		}
+	};
+	let value = variable::encode_list(&name, &elements);
+	Err(change(name, value))
 }
 
+/// `~unset NAME` removes a variable, while `~unset 'NAME[INDEX]'` only
+/// removes the selected elements from a list.
 pub(crate) fn unset(command: &[String]) -> crate::Result<u8> {
-	let command = &command[1..];
+	let arguments = &command[1..];
 
-	if command.len() == 0 {
-		Err(crate::Error::OtherError("\x1b[4munset\x1b[24m: No arguments given; expected 1 argument".to_owned()))
+	if arguments.len() == 0 {
+		Err(Error::OtherError("\x1b[4m~unset\x1b[24m: No arguments given; expected 1 argument".to_owned()))
 	}
-	else if command.len() == 1 {
-		Err(crate::Error::EnvRemoveRequested(command[0].to_string()))
+	else if arguments.len() == 1 {
+		match split_target(&arguments[0])? {
+			(name, None) => Err(Error::EnvRemoveRequested(name)),
+			(name, Some(index)) => {
+				let current = variable::get_list(&name).unwrap_or_default();
+				let removed = variable::positions(current.len(), &index);
+				let elements: Vec<String> = current
+					.into_iter()
+					.enumerate()
+					.filter(|(position, _)| !removed.contains(position))
+					.map(|(_, element)| element)
+					.collect();
+				let value = variable::encode_list(&name, &elements);
+				Err(Error::EnvChangeRequested(name, value))
+			}
+		}
 	}
 	else {
-		eprintln!("\x1b[4munset\x1b[24m: More than 1 argument was supplied; all others will be ignored");
-		unset(&command[..1])
+		eprintln!("\x1b[4m~unset\x1b[24m: More than 1 argument was supplied; all others will be ignored");
+		unset(&command[..2])
+	}
+}
+
+/// Splits NAME[INDEX] into the name and the index
+fn split_target(target: &str) -> crate::Result<(String, Option<ListIndex>)> {
+	match target.find('[') {
+		Some(bracket) if target.ends_with(']') => {
+			let (index, _) = syntax_parser::parse_list_index(0, &target[bracket..])
+				.map_err(|error| Error::OtherError(format!("{}: {}", target, error.reason)))?;
+			Ok((target[..bracket].to_owned(), Some(index)))
+		}
+		_ => Ok((target.to_owned(), None)),
 	}
 }
@@ -0,0 +1,30 @@
+use crate::{error::Error, settings, trace};
+
+const DEFAULT_COUNT: usize = 20;
+
+/// `~syscalls [COUNT]` prints the last COUNT syscalls recorded, 20 by
+/// default, with their arguments, results and how long they took.
+/// `~syscalls on` starts recording them and `~syscalls off` stops.
+pub(crate) fn syscalls(command: &[String]) -> crate::Result<u8> {
+	let arguments: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+	let count = match arguments[..] {
+		[] => DEFAULT_COUNT,
+		["on"] | ["off"] => {
+			let mut settings = settings::configured();
+			settings.record_syscalls = arguments[0] == "on";
+			return Err(Error::SettingsChangeRequested(settings));
+		}
+		[count] => count.parse().map_err(|_| {
+			Error::OtherError(format!("\x1b[4m~syscalls\x1b[24m: {} is not a number of syscalls", count))
+		})?,
+		_ => return Err(Error::OtherError("\x1b[4m~syscalls\x1b[24m: Expected no arguments, a number of syscalls, on or off".to_owned())),
+	};
+	if !settings::get().record_syscalls {
+		eprintln!("\x1b[4m~syscalls\x1b[24m: Syscalls are not recorded, ~syscalls on starts recording them");
+		return Ok(1);
+	}
+	for (index, syscall) in trace::recent_syscalls(count).iter().enumerate() {
+		println!("{:>4} {}", index + 1, syscall);
+	}
+	Ok(0)
+}
@@ -0,0 +1,47 @@
+use crate::{error::Error, settings};
+
+/// `~trace on` prints every command before it runs, and with `--syscalls`
+/// also the syscalls it makes. `--file PATH` appends the trace to a file
+/// instead of printing it to stderr. `~trace off` stops tracing, and
+/// `~trace` tells whether it is on.
+pub(crate) fn trace(command: &[String]) -> crate::Result<u8> {
+	let mut settings = settings::configured();
+	let mut arguments = command[1..].iter().map(String::as_str);
+	match arguments.next() {
+		None => {
+			let settings = settings::get();
+			let destination = if settings.trace_file.is_empty() { "stderr" } else { &settings.trace_file };
+			match (settings.trace, settings.trace_syscalls) {
+				(false, _) => println!("off"),
+				(true, false) => println!("on, to {}", destination),
+				(true, true) => println!("on with syscalls, to {}", destination),
+			}
+			Ok(0)
+		}
+		Some("on") => {
+			settings.trace = true;
+			settings.trace_syscalls = false;
+			settings.trace_file = String::new();
+			while let Some(argument) = arguments.next() {
+				match argument {
+					"-s" | "--syscalls" => settings.trace_syscalls = true,
+					"-f" | "--file" => {
+						let path = arguments.next().ok_or_else(|| {
+							Error::OtherError("\x1b[4m~trace\x1b[24m: No file given after --file".to_owned())
+						})?;
+						// The trace keeps going to the same file after a cd
+						let path = std::env::current_dir().map_or_else(|_| path.into(), |directory| directory.join(path));
+						settings.trace_file = path.to_string_lossy().into_owned();
+					}
+					_ => return Err(Error::OtherError(format!("\x1b[4m~trace\x1b[24m: Unknown option {}", argument))),
+				}
+			}
+			Err(Error::SettingsChangeRequested(settings))
+		}
+		Some("off") if arguments.next().is_none() => {
+			settings.trace = false;
+			Err(Error::SettingsChangeRequested(settings))
+		}
+		_ => Err(Error::OtherError("\x1b[4m~trace\x1b[24m: Expected no arguments, on [--syscalls] [--file PATH] or off".to_owned())),
+	}
+}
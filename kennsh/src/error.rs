@@ -8,22 +8,45 @@ pub(crate) type Result<T> = core::result::Result<T, Error>;
 pub(crate) enum Error {
 	SyscallError{call_name: String, error: CError},
 	RequestExit(Option<u8>),
-	ParseError(crate::syntax_parser::ParseError),
+	// All errors found in the input, ordered by where they are
+	ParseError(Vec<crate::syntax_parser::ParseError>),
 	CommandNotFound(String),
 	CommandPermissionDenied(String),
 	FilePermissionDenied(Option<String>),
 	FileNotFound(Option<String>),
+	IsADirectory(Option<String>),
 	ExitCodeParseError(String),
 	OtherError(String),
 	ChdirRequested(String),
 	EnvRemoveRequested(String),
 	EnvChangeRequested(String, String),
+	// Like EnvChangeRequested, but the variable is local to the running function
+	LocalEnvChangeRequested(String, String),
+	FunctionEraseRequested(Vec<String>),
+	// Replaces the settings with the ones changed or reloaded by ~config
+	SettingsChangeRequested(crate::settings::Settings),
+	// Defines an alias or abbreviation, or removes it if there is no expansion
+	AliasChangeRequested{kind: crate::alias::AliasKind, name: String, expansion: Option<String>},
 	DynamicLibraryError(String),
+	NoGlobMatch(String),
+	MathError{expression: String, error: crate::syntax_parser::ParseError},
 	NoStatusChange,
+	BreakRequested,
+	ContinueRequested,
+	ReturnRequested(u8),
+	// The state changes made by a function call, which have to be applied by
+	// the caller together with the status of the call
+	StateChangesRequested{status: u8, changes: Vec<Error>},
 }
 
 impl From<crate::syntax_parser::ParseError> for Error {
     fn from(e: crate::syntax_parser::ParseError) -> Self {
+        Self::ParseError(vec![e])
+    }
+}
+
+impl From<Vec<crate::syntax_parser::ParseError>> for Error {
+    fn from(e: Vec<crate::syntax_parser::ParseError>) -> Self {
         Self::ParseError(e)
     }
 }
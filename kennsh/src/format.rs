@@ -0,0 +1,220 @@
+//! Formats scripts: every statement on its own line, the bodies of blocks
+//! indented by a tab, and the words of every command printed the way the
+//! syntax tree prints them. Comments stay where they were, either on their own
+//! line or after the statement they followed, and a blank line between two
+//! statements is kept as a single one.
+
+use crate::syntax_parser::{self, CommandItem, CommandSyntax, ParseError, Span, SyntaxTree, SyntaxTreeKind};
+
+static INDENT_STR: &str = "\t";
+
+/// Formats source, which has to parse without errors
+pub(crate) fn format(source: &str) -> Result<String, Vec<ParseError>> {
+	// A #! line is kept as it is, and blanked out for the parser
+	let mut blanked = source.to_owned();
+	let shebang_length = if source.starts_with("#!") {
+		let length = source.find('\n').unwrap_or(source.len());
+		blanked.replace_range(..length, &" ".repeat(length));
+		Some(length)
+	}
+	else {
+		None
+	};
+
+	let tree = syntax_parser::parse(blanked.clone())?;
+	let mut formatter = Formatter {
+		source: &blanked,
+		comments: syntax_parser::comments(&blanked),
+		next_comment: 0,
+		output: String::new(),
+		indent: 0,
+		last_end: None,
+		at_block_start: false,
+	};
+	if let Some(length) = shebang_length {
+		formatter.output.push_str(&source[..length]);
+		formatter.output.push('\n');
+		formatter.last_end = Some(length);
+	}
+	formatter.statement(&tree, None);
+	formatter.comments_before(blanked.len());
+	Ok(formatter.output)
+}
+
+struct Formatter<'a> {
+	source: &'a str,
+	comments: Vec<Span>,
+	// The first comment that hasn't been written yet
+	next_comment: usize,
+	output: String,
+	indent: usize,
+	// Where the last written line ended in the source
+	last_end: Option<usize>,
+	// Whether the last written line opened a block
+	at_block_start: bool,
+}
+
+impl<'a> Formatter<'a> {
+	/// Writes a line at the current indentation, followed by the comment
+	/// after span if there is nothing else between them
+	fn line(&mut self, text: &str, span: Span) {
+		self.output.push_str(&INDENT_STR.repeat(self.indent));
+		self.output.push_str(text);
+		let mut end = span.end;
+		if let Some(comment) = self.comments.get(self.next_comment).copied() {
+			let between = self.source.get(span.end..comment.start).unwrap_or("x");
+			if between.chars().all(|c| c == ' ' || c == '\t' || c == ';') {
+				self.output.push(' ');
+				self.output.push_str(self.source[comment.start..comment.end].trim_end());
+				self.next_comment += 1;
+				end = comment.end;
+			}
+		}
+		self.output.push('\n');
+		self.last_end = Some(end);
+		self.at_block_start = false;
+	}
+
+	/// Keeps a blank line the source had before start
+	fn blank_line_before(&mut self, start: usize) {
+		if let Some(last_end) = self.last_end {
+			let blank = self.source.get(last_end..start).is_some_and(|between| between.matches('\n').count() > 1);
+			if blank && !self.at_block_start {
+				self.output.push('\n');
+			}
+		}
+	}
+
+	/// Writes the comments starting before index on their own lines
+	fn comments_before(&mut self, index: usize) {
+		while let Some(comment) = self.comments.get(self.next_comment).copied() {
+			if comment.start >= index {
+				break;
+			}
+			self.next_comment += 1;
+			self.blank_line_before(comment.start);
+			let text = self.source[comment.start..comment.end].trim_end().to_owned();
+			self.line(&text, comment);
+		}
+	}
+
+	/// The span of the first word after index, skipping whitespace,
+	/// separators and comments, like the keyword that closes a body
+	fn next_word(&self, index: usize) -> Span {
+		let mut start = index;
+		loop {
+			let rest = &self.source[start..];
+			start += rest.len() - rest.trim_start_matches(|c: char| c.is_whitespace() || c == ';').len();
+			match self.comments.iter().find(|comment| comment.start == start) {
+				Some(comment) => start = comment.end,
+				None => break,
+			}
+		}
+		let length = self.source[start..].find(|c: char| c.is_whitespace() || c == ';').unwrap_or(self.source.len() - start);
+		Span::new(start, start + length)
+	}
+
+	/// Writes a statement, with the redirections that followed the end of
+	/// its block
+	fn statement(&mut self, tree: &SyntaxTree, redirection: Option<&CommandSyntax>) {
+		let start = tree.span.start;
+		if !matches!(tree.kind, SyntaxTreeKind::Sequence(_) | SyntaxTreeKind::RedirectedBlock { .. }) {
+			self.comments_before(start);
+			self.blank_line_before(start);
+		}
+		match &tree.kind {
+			SyntaxTreeKind::Command(_) | SyntaxTreeKind::PipeChain(_) => self.line(&tree.to_string(), tree.span),
+			SyntaxTreeKind::Sequence(statements) => {
+				for statement in statements {
+					self.statement(statement, None);
+				}
+			}
+			SyntaxTreeKind::RedirectedBlock { block, redirection } => self.statement(block, Some(redirection)),
+			SyntaxTreeKind::If { branches, otherwise } => {
+				let mut previous_end = start;
+				for (index, (condition, body)) in branches.iter().enumerate() {
+					if index == 0 {
+						self.line(&format!("if {}", condition), Span::new(start, condition.span.end));
+					}
+					else {
+						let else_start = self.next_word(previous_end).start;
+						self.line(&format!("else if {}", condition), Span::new(else_start, condition.span.end));
+					}
+					previous_end = self.body(body, condition.span.end);
+				}
+				if let Some(otherwise) = otherwise {
+					let else_span = self.next_word(previous_end);
+					self.line("else", else_span);
+					self.body(otherwise, else_span.end);
+				}
+				self.close(tree, redirection);
+			}
+			SyntaxTreeKind::For { variable, items, body } => {
+				let header_end = items.last().map_or(start, |item| item.span.end);
+				let mut header = format!("for {} in", variable);
+				if !items.is_empty() {
+					header = format!("{} {}", header, words(items));
+				}
+				self.line(&header, Span::new(start, header_end));
+				self.body(body, header_end);
+				self.close(tree, redirection);
+			}
+			SyntaxTreeKind::While { condition, body } => {
+				self.line(&format!("while {}", condition), Span::new(start, condition.span.end));
+				self.body(body, condition.span.end);
+				self.close(tree, redirection);
+			}
+			SyntaxTreeKind::Switch { value, cases } => {
+				let mut previous_end = value.last().map_or(start, |item| item.span.end);
+				self.line(&format!("switch {}", words(value)), Span::new(start, previous_end));
+				self.indent += 1;
+				for (patterns, body) in cases {
+					let case_start = self.next_word(previous_end).start;
+					self.comments_before(case_start);
+					let header_end = patterns.last().map_or(case_start, |item| item.span.end);
+					self.line(&format!("case {}", words(patterns)), Span::new(case_start, header_end));
+					previous_end = self.body(body, header_end);
+				}
+				self.indent -= 1;
+				self.close(tree, redirection);
+			}
+			SyntaxTreeKind::Function { name, body } => {
+				let name_span = self.next_word(self.next_word(start).end);
+				self.line(&format!("function {}", name), Span::new(start, name_span.end));
+				self.body(body, name_span.end);
+				self.close(tree, redirection);
+			}
+		}
+	}
+
+	/// Writes the body of a block one level deeper, together with the
+	/// comments up to the keyword ending it, returning where it ends
+	fn body(&mut self, body: &SyntaxTree, header_end: usize) -> usize {
+		self.indent += 1;
+		self.at_block_start = true;
+		self.statement(body, None);
+		let end = if body.is_empty() { header_end } else { body.span.end };
+		let next = self.next_word(end);
+		self.comments_before(next.start);
+		self.indent -= 1;
+		end
+	}
+
+	/// Writes the end of a block, which ends with the block
+	fn close(&mut self, block: &SyntaxTree, redirection: Option<&CommandSyntax>) {
+		let end_start = block.span.end - "end".len();
+		self.comments_before(end_start);
+		match redirection {
+			Some(redirection) => self.line(&format!("end{}", redirection), Span::new(end_start, redirection.span.end)),
+			None => self.line("end", Span::new(end_start, block.span.end)),
+		}
+	}
+}
+
+/// Items separated by spaces
+fn words(items: &[CommandItem]) -> String {
+	items.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,70 @@
+use super::*;
+
+fn format_ok(input: &str) -> String {
+	match format(input) {
+		Ok(formatted) => formatted,
+		Err(errors) => {
+			let reasons: Vec<_> = errors.iter().map(|error| error.reason.as_str()).collect();
+			panic!("{:?} didn't format: {:?}", input, reasons)
+		}
+	}
+}
+
+// Inputs with what they are formatted as
+static FORMATTED: &[(&str, &str)] = &[
+	("", ""),
+	("echo   a  'b'", "echo a \"b\"\n"),
+	("a; b\n\n\n\nc | d", "a\nb\n\nc | d\n"),
+	("if a; b; else if c; d; else; e; end", "if a\n\tb\nelse if c\n\td\nelse\n\te\nend\n"),
+	("for x in a b\necho $x\nend > out", "for x in a b\n\techo $x\nend >out\n"),
+	("while true;end", "while true\nend\n"),
+	(
+		"switch $x\ncase a b\necho ab\ncase '*'\nend",
+		"switch $x\n\tcase a b\n\t\techo ab\n\tcase \"*\"\nend\n",
+	),
+	("function f\nif a\nreturn 1\nend\nend", "function f\n\tif a\n\t\treturn 1\n\tend\nend\n"),
+	("#!/bin/kennsh\necho hi", "#!/bin/kennsh\necho hi\n"),
+];
+
+// Inputs with their comments
+static COMMENTED: &[(&str, &str)] = &[
+	("# top\necho a # after a\n  # own line\necho b", "# top\necho a # after a\n# own line\necho b\n"),
+	("a; b # after b", "a\nb # after b\n"),
+	(
+		"if a # condition\n# first\nb\n# last\nelse # else\nc\nend # end",
+		"if a # condition\n\t# first\n\tb\n\t# last\nelse # else\n\tc\nend # end\n",
+	),
+	("for x in a\n\n# nothing\nend > f # out", "for x in a\n\t# nothing\nend >f # out\n"),
+	("switch x # value\n# before\ncase y\nend", "switch x # value\n\t# before\n\tcase y\nend\n"),
+	("echo a\n\n# trailing", "echo a\n\n# trailing\n"),
+];
+
+#[test]
+fn formats_statements() {
+	for (input, expected) in FORMATTED.iter().chain(COMMENTED) {
+		assert_eq!(&format_ok(input), expected, "formatting {:?}", input);
+	}
+}
+
+#[test]
+fn formatting_is_stable() {
+	for (input, _) in FORMATTED.iter().chain(COMMENTED) {
+		let formatted = format_ok(input);
+		assert_eq!(format_ok(&formatted), formatted, "formatting {:?} twice", input);
+	}
+}
+
+#[test]
+fn keeps_the_meaning() {
+	for (input, _) in FORMATTED.iter().skip(1) {
+		let original = syntax_parser::parse(input.to_string()).unwrap().to_string();
+		let formatted = syntax_parser::parse(format_ok(input)).unwrap().to_string();
+		assert_eq!(original, formatted, "formatting {:?}", input);
+	}
+}
+
+#[test]
+fn rejects_invalid_input() {
+	assert!(format("echo (").is_err());
+	assert!(format("if a\n").is_err());
+}
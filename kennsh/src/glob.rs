@@ -0,0 +1,366 @@
+use std::path::Path;
+
+/// A word in the process of being expanded.
+///
+/// Every character remembers whether it came from unquoted text, as only
+/// those characters can act as wildcards: `*.rs` is a pattern, but `"*.rs"`,
+/// `` `*.rs `` and the value of a variable containing `*.rs` are not.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GlobWord(Vec<(char, bool)>);
+
+impl GlobWord {
+	pub(crate) fn quoted(text: &str) -> Self {
+		Self(text.chars().map(|c| (c, false)).collect())
+	}
+
+	pub(crate) fn unquoted(text: &str) -> Self {
+		Self(text.chars().map(|c| (c, true)).collect())
+	}
+
+	pub(crate) fn append(&mut self, other: &GlobWord) {
+		self.0.extend_from_slice(&other.0);
+	}
+
+	pub(crate) fn chars(&self) -> &[(char, bool)] {
+		&self.0
+	}
+
+	pub(crate) fn replace_range(&mut self, range: std::ops::Range<usize>, replacement: &GlobWord) {
+		self.0.splice(range, replacement.0.iter().cloned());
+	}
+
+	pub(crate) fn text(&self) -> String {
+		self.0.iter().map(|(c, _)| c).collect()
+	}
+
+	pub(crate) fn has_wildcards(&self) -> bool {
+		self.0.iter().any(|(c, active)| *active && WILDCARD_CHARS.contains(c))
+	}
+}
+
+static WILDCARD_CHARS: [char; 3] = ['*', '?', '['];
+
+#[derive(Clone, Debug)]
+enum Token {
+	Literal(char),
+	// ?
+	AnyChar,
+	// *
+	AnyString,
+	// [...]
+	Class {
+		negated: bool,
+		ranges: Vec<(char, char)>,
+	},
+}
+
+/// A compiled wildcard pattern for a single string, without any special
+/// meaning for `/`.
+#[derive(Clone, Debug)]
+pub(crate) struct Pattern {
+	tokens: Vec<Token>,
+}
+
+impl Pattern {
+	/// Compiles a pattern from a word, where only the unquoted characters
+	/// can be wildcards.
+	pub(crate) fn from_word(word: &GlobWord) -> Self {
+		Self::compile(&word.0)
+	}
+
+	fn compile(chars: &[(char, bool)]) -> Self {
+		let mut tokens = vec![];
+		let mut i = 0;
+		while i < chars.len() {
+			let (c, active) = chars[i];
+			i += 1;
+			if !active {
+				tokens.push(Token::Literal(c));
+			}
+			else if c == '*' {
+				// Consecutive stars behave like a single one
+				if !matches!(tokens.last(), Some(Token::AnyString)) {
+					tokens.push(Token::AnyString);
+				}
+			}
+			else if c == '?' {
+				tokens.push(Token::AnyChar);
+			}
+			else if c == '[' {
+				match Self::compile_class(&chars[i..]) {
+					Some((class, length)) => {
+						tokens.push(class);
+						i += length;
+					}
+					// An unterminated class is just a [
+					None => tokens.push(Token::Literal(c)),
+				}
+			}
+			else {
+				tokens.push(Token::Literal(c));
+			}
+		}
+		Self { tokens }
+	}
+
+	/// Compiles the inside of a character class, returning the class and the
+	/// number of characters used, including the closing ].
+	fn compile_class(chars: &[(char, bool)]) -> Option<(Token, usize)> {
+		let mut i = 0;
+		let negated = match chars.get(0) {
+			Some(('!', true)) | Some(('^', true)) => {
+				i += 1;
+				true
+			}
+			_ => false,
+		};
+		let mut ranges = vec![];
+		let mut first = true;
+		loop {
+			let (c, active) = *chars.get(i)?;
+			i += 1;
+			// A ] right at the start is part of the class instead of closing it
+			if c == ']' && active && !first {
+				break;
+			}
+			first = false;
+			if let (Some(('-', true)), Some((end, _))) = (chars.get(i), chars.get(i + 1)) {
+				if !(*end == ']' && chars[i + 1].1) {
+					ranges.push((c, *end));
+					i += 2;
+					continue;
+				}
+			}
+			ranges.push((c, c));
+		}
+		Some((Token::Class { negated, ranges }, i))
+	}
+
+	pub(crate) fn matches(&self, text: &str) -> bool {
+		let text: Vec<char> = text.chars().collect();
+		Self::matches_from(&self.tokens, &text)
+	}
+
+	fn matches_from(tokens: &[Token], text: &[char]) -> bool {
+		// Classic wildcard matching, backtracking to the last star on mismatch
+		let mut t = 0;
+		let mut s = 0;
+		let mut last_star: Option<(usize, usize)> = None;
+		while s < text.len() {
+			let matched = match tokens.get(t) {
+				Some(Token::AnyString) => {
+					last_star = Some((t, s));
+					t += 1;
+					continue;
+				}
+				Some(Token::AnyChar) => true,
+				Some(Token::Literal(c)) => *c == text[s],
+				Some(Token::Class { negated, ranges }) => {
+					let c = text[s];
+					ranges.iter().any(|(start, end)| *start <= c && c <= *end) != *negated
+				}
+				None => false,
+			};
+			if matched {
+				t += 1;
+				s += 1;
+			}
+			else if let Some((star_t, star_s)) = last_star {
+				// Let the last star eat one more character and retry
+				t = star_t + 1;
+				s = star_s + 1;
+				last_star = Some((star_t, star_s + 1));
+			}
+			else {
+				return false;
+			}
+		}
+		tokens[t..].iter().all(|token| matches!(token, Token::AnyString))
+	}
+
+	/// Removes the shortest (or longest) prefix of text matching the pattern
+	pub(crate) fn strip_prefix<'a>(&self, text: &'a str, longest: bool) -> &'a str {
+		let mut ends = char_boundaries(text);
+		let end = if longest {
+			ends.rev().find(|end| self.matches(&text[..*end]))
+		}
+		else {
+			ends.find(|end| self.matches(&text[..*end]))
+		};
+		end.map_or(text, |end| &text[end..])
+	}
+
+	/// Removes the shortest (or longest) suffix of text matching the pattern
+	pub(crate) fn strip_suffix<'a>(&self, text: &'a str, longest: bool) -> &'a str {
+		let mut starts = char_boundaries(text);
+		let start = if longest {
+			starts.find(|start| self.matches(&text[*start..]))
+		}
+		else {
+			starts.rev().find(|start| self.matches(&text[*start..]))
+		};
+		start.map_or(text, |start| &text[..start])
+	}
+
+	/// Replaces the first (or every) longest non-empty match of the pattern
+	pub(crate) fn replace(&self, text: &str, replacement: &str, all: bool) -> String {
+		let boundaries: Vec<usize> = char_boundaries(text).collect();
+		let mut result = String::new();
+		let mut i = 0;
+		let mut replaced = false;
+		while i + 1 < boundaries.len() {
+			let start = boundaries[i];
+			let end = if replaced && !all {
+				None
+			}
+			else {
+				(i + 1..boundaries.len())
+					.rev()
+					.find(|end| self.matches(&text[start..boundaries[*end]]))
+			};
+			if let Some(end) = end {
+				result += replacement;
+				replaced = true;
+				i = end;
+			}
+			else {
+				result += &text[start..boundaries[i + 1]];
+				i += 1;
+			}
+		}
+		result
+	}
+
+	fn is_literal(&self) -> bool {
+		self.tokens.iter().all(|token| matches!(token, Token::Literal(_)))
+	}
+
+	fn literal_text(&self) -> String {
+		self.tokens.iter().filter_map(|token| match token {
+			Token::Literal(c) => Some(*c),
+			_ => None,
+		}).collect()
+	}
+
+	fn starts_with_dot(&self) -> bool {
+		matches!(self.tokens.first(), Some(Token::Literal('.')))
+	}
+}
+
+/// Every index in text where a character starts, plus the end of text
+fn char_boundaries(text: &str) -> impl DoubleEndedIterator<Item = usize> + '_ {
+	text.char_indices().map(|(index, _)| index).chain(std::iter::once(text.len()))
+}
+
+enum Segment {
+	Pattern(Pattern),
+	// **
+	Recursive,
+}
+
+/// Expands a word containing wildcards into the sorted list of existing
+/// paths it matches.
+///
+/// Wildcards never match `/`, and they only match names starting with `.`
+/// if the pattern itself starts with a literal `.` at that point. `**` as a
+/// whole path component matches any number of nested (non-hidden)
+/// directories, including none at all, and as the last component also the
+/// files in them.
+pub(crate) fn expand(word: &GlobWord) -> Vec<String> {
+	let chars = &word.0;
+	let absolute = matches!(chars.first(), Some(('/', _)));
+	let trailing_slash = chars.len() > 1 && matches!(chars.last(), Some(('/', _)));
+	let segments: Vec<Segment> = chars
+		.split(|(c, _)| *c == '/')
+		.filter(|segment| !segment.is_empty())
+		.map(|segment| match segment {
+			[('*', true), ('*', true)] => Segment::Recursive,
+			segment => Segment::Pattern(Pattern::compile(segment)),
+		})
+		.collect();
+
+	let mut paths = vec![if absolute { "/".to_owned() } else { String::new() }];
+	for (index, segment) in segments.iter().enumerate() {
+		let last = index == segments.len() - 1;
+		let mut next_paths = vec![];
+		for path in &paths {
+			match segment {
+				Segment::Recursive => {
+					next_paths.push(path.clone());
+					collect_entries(path, last, &mut next_paths);
+				}
+				Segment::Pattern(pattern) if pattern.is_literal() => {
+					next_paths.push(path.clone() + &pattern.literal_text() + "/");
+				}
+				Segment::Pattern(pattern) => {
+					for name in read_directory(path) {
+						if name.starts_with('.') && !pattern.starts_with_dot() {
+							continue;
+						}
+						if pattern.matches(&name) {
+							next_paths.push(path.clone() + &name + "/");
+						}
+					}
+				}
+			}
+		}
+		paths = next_paths;
+	}
+
+	let mut result: Vec<String> = paths
+		.into_iter()
+		.filter_map(|path| {
+			let trimmed = if path.len() > 1 { &path[..path.len() - 1] } else { &path[..] };
+			if trimmed.is_empty() {
+				return None;
+			}
+			let metadata = std::fs::metadata(trimmed).ok()?;
+			if trailing_slash {
+				if metadata.is_dir() { Some(path) } else { None }
+			}
+			else {
+				Some(trimmed.to_owned())
+			}
+		})
+		.collect();
+	result.sort();
+	result.dedup();
+	result
+}
+
+fn read_directory(path: &str) -> Vec<String> {
+	let directory = if path.is_empty() { "." } else { path };
+	match std::fs::read_dir(directory) {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok())
+			.filter_map(|entry| entry.file_name().into_string().ok())
+			.collect(),
+		Err(_) => vec![],
+	}
+}
+
+/// Adds every non-hidden directory below path, and with files every other
+/// non-hidden entry too, recursively, without following symbolic links.
+fn collect_entries(path: &str, files: bool, result: &mut Vec<String>) {
+	let directory = if path.is_empty() { Path::new(".") } else { Path::new(path) };
+	if let Ok(entries) = std::fs::read_dir(directory) {
+		for entry in entries.filter_map(|entry| entry.ok()) {
+			let is_directory = entry.file_type().map_or(false, |t| t.is_dir());
+			let name = match entry.file_name().into_string() {
+				Ok(name) if !name.starts_with('.') => name,
+				_ => continue,
+			};
+			if is_directory {
+				let subdirectory = path.to_owned() + &name + "/";
+				result.push(subdirectory.clone());
+				collect_entries(&subdirectory, files, result);
+			}
+			else if files {
+				result.push(path.to_owned() + &name + "/");
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests;
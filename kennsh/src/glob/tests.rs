@@ -0,0 +1,104 @@
+use super::*;
+
+fn matches(pattern: &str, text: &str) -> bool {
+	Pattern::from_word(&GlobWord::unquoted(pattern)).matches(text)
+}
+
+/// A directory with the given files in it, removed when dropped
+struct TestDirectory(std::path::PathBuf);
+
+impl TestDirectory {
+	fn new(name: &str, files: &[&str]) -> Self {
+		let root = std::env::temp_dir().join(format!("kennsh_glob_test_{}_{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&root);
+		for file in files {
+			let path = root.join(file);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			if !file.ends_with('/') {
+				std::fs::write(&path, "").unwrap();
+			}
+		}
+		Self(root)
+	}
+
+	/// Expands pattern inside the directory, returning the paths relative to it
+	fn expand(&self, pattern: &str) -> Vec<String> {
+		let root = self.0.to_string_lossy().into_owned() + "/";
+		let mut word = GlobWord::quoted(&root);
+		word.append(&GlobWord::unquoted(pattern));
+		expand(&word).into_iter().map(|path| path[root.len()..].to_owned()).collect()
+	}
+}
+
+impl Drop for TestDirectory {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.0);
+	}
+}
+
+#[test]
+fn matches_wildcards() {
+	assert!(matches("*.rs", "main.rs"));
+	assert!(matches("*.rs", ".rs"));
+	assert!(!matches("*.rs", "main.rs.bak"));
+	assert!(matches("a*b*c", "aXbYbZc"));
+	assert!(matches("**", ""));
+	assert!(matches("?", "é"));
+	assert!(!matches("?", ""));
+	assert!(!matches("??", "a"));
+}
+
+#[test]
+fn matches_classes() {
+	assert!(matches("[abc]", "b"));
+	assert!(!matches("[abc]", "d"));
+	assert!(matches("[a-c]x", "cx"));
+	assert!(matches("[!a-c]", "d"));
+	assert!(matches("[^a-c]", "d"));
+	assert!(!matches("[!a-c]", "a"));
+	// A ] first and a - last are members
+	assert!(matches("[]]", "]"));
+	assert!(matches("[a-]", "-"));
+	// An unterminated class is a literal [
+	assert!(matches("[ab", "[ab"));
+}
+
+#[test]
+fn quoted_characters_are_literal() {
+	let mut word = GlobWord::quoted("*");
+	word.append(&GlobWord::unquoted("*"));
+	assert!(word.has_wildcards());
+	let pattern = Pattern::from_word(&word);
+	assert!(pattern.matches("*.rs"));
+	assert!(!pattern.matches("main.rs"));
+	assert!(!GlobWord::quoted("*.rs").has_wildcards());
+}
+
+#[test]
+fn expands_wildcards() {
+	let directory = TestDirectory::new("wildcards", &["a.rs", "b.rs", "c.txt", ".hidden.rs", "src/d.rs"]);
+	assert_eq!(directory.expand("*.rs"), ["a.rs", "b.rs"]);
+	assert_eq!(directory.expand("?.*"), ["a.rs", "b.rs", "c.txt"]);
+	assert_eq!(directory.expand("[!a].rs"), ["b.rs"]);
+	assert_eq!(directory.expand("*/*.rs"), ["src/d.rs"]);
+	assert_eq!(directory.expand("*/"), ["src/"]);
+	assert!(directory.expand("*.md").is_empty());
+}
+
+#[test]
+fn expands_dotfiles_only_with_a_dot() {
+	let directory = TestDirectory::new("dotfiles", &["a", ".b", ".c/d"]);
+	assert_eq!(directory.expand("*"), ["a"]);
+	assert_eq!(directory.expand(".*"), [".b", ".c"]);
+	assert_eq!(directory.expand(".c/*"), [".c/d"]);
+}
+
+#[test]
+fn expands_recursive_wildcards() {
+	let directory = TestDirectory::new("recursive", &["a.rs", "src/b.rs", "src/c/d.rs", "src/c/e.txt", "src/.git/f.rs", "empty/"]);
+	assert_eq!(directory.expand("**/*.rs"), ["a.rs", "src/b.rs", "src/c/d.rs"]);
+	assert_eq!(directory.expand("src/**/*.rs"), ["src/b.rs", "src/c/d.rs"]);
+	assert_eq!(directory.expand("src/**"), ["src", "src/b.rs", "src/c", "src/c/d.rs", "src/c/e.txt"]);
+	assert_eq!(directory.expand("src/**/"), ["src/", "src/c/"]);
+	assert_eq!(directory.expand("**/e.*"), ["src/c/e.txt"]);
+}
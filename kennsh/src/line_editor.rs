@@ -0,0 +1,41 @@
+use rustyline::{Cmd, ConditionalEventHandler, Event, EventContext, Helper, Movement, RepeatCount, completion::Completer, highlight::Highlighter, hint::Hinter, validate::{ValidationContext, ValidationResult, Validator}};
+
+use crate::{alias, syntax_parser};
+
+/// Hooks the shell into the line editor
+pub(crate) struct ShellHelper;
+
+impl Completer for ShellHelper {
+	type Candidate = String;
+}
+
+impl Hinter for ShellHelper {
+	type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {
+	fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+		// Keep editing on a new line while a block is still open
+		if syntax_parser::is_incomplete(ctx.input()) {
+			Ok(ValidationResult::Incomplete)
+		}
+		else {
+			Ok(ValidationResult::Valid(None))
+		}
+	}
+}
+
+impl Helper for ShellHelper {}
+
+/// Expands the abbreviation before the cursor when space is pressed
+pub(crate) struct ExpandAbbreviation;
+
+impl ConditionalEventHandler for ExpandAbbreviation {
+	fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+		let (start, expansion) = alias::abbreviation_at(ctx.line(), ctx.pos())?;
+		let typed = ctx.line()[start..ctx.pos()].chars().count();
+		Some(Cmd::Replace(Movement::BackwardChar(typed), Some(expansion + " ")))
+	}
+}
@@ -0,0 +1,339 @@
+//! A language server for scripts, speaking JSON-RPC over stdio. It publishes
+//! the syntax errors and the problems found by the checker as diagnostics,
+//! completes builtins, keywords and the functions of a script, shows the
+//! help of builtins on hover and goes to the definitions of functions.
+
+use std::{collections::HashMap, io::{BufRead, Write}};
+
+use serde_json::{Value, json};
+
+use crate::{check, command, syntax_parser::{self, ParseError, Span, SyntaxTree, SyntaxTreeKind}};
+
+static KEYWORDS: &[&str] = &["if", "else", "for", "in", "while", "switch", "case", "function", "end"];
+
+// JSON-RPC error codes
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// LSP enumerations
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_KEYWORD: u8 = 14;
+
+/// Serves requests read from input until the client sends exit, returning
+/// whether it asked for a shutdown before that
+pub(crate) fn serve(mut input: impl BufRead, output: impl Write) -> std::io::Result<bool> {
+	let mut server = Server {
+		output,
+		documents: HashMap::new(),
+		shutdown: false,
+	};
+	while let Some(message) = read_message(&mut input)? {
+		if message["method"] == "exit" {
+			break;
+		}
+		server.handle(&message)?;
+	}
+	Ok(server.shutdown)
+}
+
+/// Reads a message with its Content-Length header, or None at the end of
+/// input
+fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<Value>> {
+	let mut length = None;
+	loop {
+		let mut header = String::new();
+		if input.read_line(&mut header)? == 0 {
+			return Ok(None);
+		}
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = header.split_once(':') {
+			if name.eq_ignore_ascii_case("Content-Length") {
+				length = value.trim().parse::<usize>().ok();
+			}
+		}
+	}
+	let length = length.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "a message has no Content-Length"))?;
+	let mut body = vec![0; length];
+	input.read_exact(&mut body)?;
+	Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> std::io::Result<()> {
+	let body = message.to_string();
+	write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+	output.flush()
+}
+
+struct Server<W: Write> {
+	output: W,
+	// The text of the open documents by their URIs
+	documents: HashMap<String, String>,
+	shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+	fn handle(&mut self, message: &Value) -> std::io::Result<()> {
+		let params = &message["params"];
+		let id = match message.get("id") {
+			Some(id) => id,
+			None => return self.notification(message["method"].as_str().unwrap_or_default(), params),
+		};
+		if self.shutdown {
+			return self.error(id, INVALID_REQUEST, "the server was shut down");
+		}
+		let result = match message["method"].as_str().unwrap_or_default() {
+			"initialize" => json!({
+				"capabilities": {
+					"textDocumentSync": 1,
+					"completionProvider": {},
+					"hoverProvider": true,
+					"definitionProvider": true,
+				},
+				"serverInfo": { "name": "kennsh" },
+			}),
+			"shutdown" => {
+				self.shutdown = true;
+				Value::Null
+			}
+			"textDocument/completion" => self.completion(params),
+			"textDocument/hover" => self.hover(params),
+			"textDocument/definition" => self.definition(params),
+			method => return self.error(id, METHOD_NOT_FOUND, &format!("unknown method {}", method)),
+		};
+		write_message(&mut self.output, &json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+	}
+
+	fn notification(&mut self, method: &str, params: &Value) -> std::io::Result<()> {
+		let uri = match params["textDocument"]["uri"].as_str() {
+			Some(uri) => uri.to_owned(),
+			None => return Ok(()),
+		};
+		match method {
+			"textDocument/didOpen" => {
+				let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+				self.documents.insert(uri.clone(), text.to_owned());
+			}
+			// The whole text is sent on changes, as is asked for by initialize
+			"textDocument/didChange" => match params["contentChanges"].as_array().and_then(|changes| changes.last()) {
+				Some(change) => {
+					let text = change["text"].as_str().unwrap_or_default();
+					self.documents.insert(uri.clone(), text.to_owned());
+				}
+				None => return Ok(()),
+			},
+			"textDocument/didClose" => {
+				self.documents.remove(&uri);
+			}
+			_ => return Ok(()),
+		}
+		self.publish_diagnostics(&uri)
+	}
+
+	fn error(&mut self, id: &Value, code: i64, message: &str) -> std::io::Result<()> {
+		write_message(&mut self.output, &json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"error": { "code": code, "message": message },
+		}))
+	}
+
+	fn publish_diagnostics(&mut self, uri: &str) -> std::io::Result<()> {
+		let diagnostics: Vec<Value> = match self.documents.get(uri) {
+			Some(text) => {
+				let source = blank_shebang(text);
+				let (problems, severity) = match check::check(&source) {
+					Ok(warnings) => (warnings, SEVERITY_WARNING),
+					Err(errors) => (errors, SEVERITY_ERROR),
+				};
+				problems.iter().map(|problem| diagnostic(uri, &source, problem, severity)).collect()
+			}
+			// Closed documents lose their diagnostics
+			None => vec![],
+		};
+		write_message(&mut self.output, &json!({
+			"jsonrpc": "2.0",
+			"method": "textDocument/publishDiagnostics",
+			"params": { "uri": uri, "diagnostics": diagnostics },
+		}))
+	}
+
+	/// The text of the document a request is about, with the byte index of
+	/// its position
+	fn document(&self, params: &Value) -> Option<(&str, usize)> {
+		let text = self.documents.get(params["textDocument"]["uri"].as_str()?)?;
+		Some((text, offset(text, &params["position"])))
+	}
+
+	fn completion(&self, params: &Value) -> Value {
+		let mut items: Vec<Value> = command::BUILTINS
+			.iter()
+			.map(|(name, help)| json!({
+				"label": name,
+				"kind": COMPLETION_FUNCTION,
+				"detail": "builtin",
+				"documentation": { "kind": "markdown", "value": help },
+			}))
+			.collect();
+		items.extend(KEYWORDS.iter().map(|keyword| json!({ "label": keyword, "kind": COMPLETION_KEYWORD })));
+		if let Some((text, _)) = self.document(params) {
+			items.extend(functions(text).into_iter().map(|(name, _)| json!({
+				"label": name,
+				"kind": COMPLETION_FUNCTION,
+				"detail": "function",
+			})));
+		}
+		Value::Array(items)
+	}
+
+	fn hover(&self, params: &Value) -> Value {
+		let (text, index) = match self.document(params) {
+			Some(document) => document,
+			None => return Value::Null,
+		};
+		let word = word_at(text, index);
+		let help = match command::builtin_help(&text[word.start..word.end]) {
+			Some(help) => help.to_owned(),
+			None => match functions(text).into_iter().find(|(name, _)| *name == text[word.start..word.end]) {
+				Some((name, span)) => {
+					format!("`function {}`, defined on line {}", name, text[..span.start].matches('\n').count() + 1)
+				},
+				None => return Value::Null,
+			},
+		};
+		json!({
+			"contents": { "kind": "markdown", "value": help },
+			"range": range(text, word),
+		})
+	}
+
+	fn definition(&self, params: &Value) -> Value {
+		let (text, index) = match self.document(params) {
+			Some(document) => document,
+			None => return Value::Null,
+		};
+		let word = word_at(text, index);
+		match functions(text).into_iter().find(|(name, _)| *name == text[word.start..word.end]) {
+			Some((_, span)) => json!({ "uri": params["textDocument"]["uri"], "range": range(text, span) }),
+			None => Value::Null,
+		}
+	}
+}
+
+fn diagnostic(uri: &str, source: &str, problem: &ParseError, severity: u8) -> Value {
+	let mut message = problem.reason.clone();
+	for note in &problem.notes {
+		message.push_str(&format!("\nnote: {}", note));
+	}
+	if let Some(help) = &problem.help {
+		message.push_str(&format!("\nhelp: {}", help));
+	}
+	let related: Vec<Value> = problem.labels.iter().map(|label| json!({
+		"location": { "uri": uri, "range": range(source, Span::new(label.start_index, label.end_index)) },
+		"message": label.message,
+	})).collect();
+	json!({
+		"range": range(source, Span::new(problem.start_index, problem.end_index)),
+		"severity": severity,
+		"source": "kennsh",
+		"message": message,
+		"relatedInformation": related,
+	})
+}
+
+/// text with a #! line replaced by spaces, which keeps positions intact
+fn blank_shebang(text: &str) -> String {
+	let mut source = text.to_owned();
+	if source.starts_with("#!") {
+		let length = source.find('\n').unwrap_or(source.len());
+		source.replace_range(..length, &" ".repeat(length));
+	}
+	source
+}
+
+/// The functions a script defines, with the spans of their names
+fn functions(text: &str) -> Vec<(String, Span)> {
+	fn collect(text: &str, tree: &SyntaxTree, found: &mut Vec<(String, Span)>) {
+		match &tree.kind {
+			SyntaxTreeKind::Sequence(statements) => statements.iter().for_each(|statement| collect(text, statement, found)),
+			SyntaxTreeKind::If { branches, otherwise } => {
+				branches.iter().for_each(|(_, body)| collect(text, body, found));
+				if let Some(otherwise) = otherwise {
+					collect(text, otherwise, found);
+				}
+			}
+			SyntaxTreeKind::For { body, .. } | SyntaxTreeKind::While { body, .. } => collect(text, body, found),
+			SyntaxTreeKind::Switch { cases, .. } => cases.iter().for_each(|(_, body)| collect(text, body, found)),
+			SyntaxTreeKind::RedirectedBlock { block, .. } => collect(text, block, found),
+			SyntaxTreeKind::Function { name, body } => {
+				// The name is the first word after the keyword
+				let after_keyword = tree.span.start + "function".len();
+				let start = text[after_keyword..].find(name.as_str()).map_or(tree.span.start, |index| after_keyword + index);
+				found.push((name.clone(), Span::new(start, start + name.len())));
+				collect(text, body, found);
+			}
+			SyntaxTreeKind::Command(_) | SyntaxTreeKind::PipeChain(_) => {}
+		}
+	}
+	let mut found = vec![];
+	if let Ok(tree) = syntax_parser::parse(blank_shebang(text)) {
+		collect(text, &tree, &mut found);
+	}
+	found
+}
+
+/// The span of the word around index
+fn word_at(text: &str, index: usize) -> Span {
+	let is_separator = |c: char| c.is_whitespace() || "; |<>()\"'$".contains(c);
+	let start = match text[..index].char_indices().rev().find(|(_, c)| is_separator(*c)) {
+		Some((start, separator)) => start + separator.len_utf8(),
+		None => 0,
+	};
+	let end = text[index..].find(is_separator).map_or(text.len(), |end| index + end);
+	Span::new(start, end)
+}
+
+/// The LSP position of a byte index, whose character counts UTF-16 code units
+fn position(text: &str, index: usize) -> Value {
+	let mut index = index.min(text.len());
+	while !text.is_char_boundary(index) {
+		index -= 1;
+	}
+	let line_start = text[..index].rfind('\n').map_or(0, |start| start + 1);
+	json!({
+		"line": text[..index].matches('\n').count(),
+		"character": text[line_start..index].encode_utf16().count(),
+	})
+}
+
+fn range(text: &str, span: Span) -> Value {
+	json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// The byte index of an LSP position
+fn offset(text: &str, position: &Value) -> usize {
+	let line = position["line"].as_u64().unwrap_or(0) as usize;
+	let character = position["character"].as_u64().unwrap_or(0) as usize;
+	let line_start = match line {
+		0 => 0,
+		line => match text.match_indices('\n').nth(line - 1) {
+			Some((index, _)) => index + 1,
+			None => return text.len(),
+		},
+	};
+	let mut units = 0;
+	for (index, c) in text[line_start..].char_indices() {
+		if units >= character || c == '\n' {
+			return line_start + index;
+		}
+		units += c.len_utf16();
+	}
+	text.len()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,143 @@
+use super::*;
+
+/// A client sending messages to the server and reading its replies
+#[derive(Default)]
+struct FakeClient {
+	input: Vec<u8>,
+	next_id: u64,
+}
+
+impl FakeClient {
+	fn request(&mut self, method: &str, params: Value) -> u64 {
+		self.next_id += 1;
+		let message = json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params });
+		write_message(&mut self.input, &message).unwrap();
+		self.next_id
+	}
+
+	fn notify(&mut self, method: &str, params: Value) {
+		write_message(&mut self.input, &json!({ "jsonrpc": "2.0", "method": method, "params": params })).unwrap();
+	}
+
+	fn open(&mut self, uri: &str, text: &str) {
+		self.notify("textDocument/didOpen", json!({
+			"textDocument": { "uri": uri, "languageId": "kennsh", "version": 1, "text": text },
+		}));
+	}
+
+	/// Runs the server on the messages sent so far, returning whether it
+	/// was shut down and its replies
+	fn run(self) -> (bool, Vec<Value>) {
+		let mut output = vec![];
+		let shutdown = serve(&self.input[..], &mut output).unwrap();
+		let mut replies = vec![];
+		let mut reader = &output[..];
+		while let Some(message) = read_message(&mut reader).unwrap() {
+			replies.push(message);
+		}
+		(shutdown, replies)
+	}
+}
+
+fn at(uri: &str, line: u64, character: u64) -> Value {
+	json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+}
+
+fn reply(replies: &[Value], id: u64) -> &Value {
+	replies.iter().find(|reply| reply["id"] == id).unwrap_or_else(|| panic!("no reply to {} in {:?}", id, replies))
+}
+
+#[test]
+fn initializes_and_shuts_down() {
+	let mut client = FakeClient::default();
+	let initialize = client.request("initialize", json!({ "capabilities": {} }));
+	client.notify("initialized", json!({}));
+	let unknown = client.request("textDocument/rename", json!({}));
+	let shutdown = client.request("shutdown", Value::Null);
+	client.notify("exit", Value::Null);
+	// Ignored after exit
+	let late = client.request("shutdown", Value::Null);
+
+	let (was_shut_down, replies) = client.run();
+	assert!(was_shut_down);
+	assert_eq!(replies.len(), 3);
+	let capabilities = &reply(&replies, initialize)["result"]["capabilities"];
+	assert_eq!(capabilities["textDocumentSync"], 1);
+	assert_eq!(capabilities["hoverProvider"], true);
+	assert_eq!(reply(&replies, unknown)["error"]["code"], METHOD_NOT_FOUND);
+	assert_eq!(reply(&replies, shutdown)["result"], Value::Null);
+	assert!(replies.iter().all(|reply| reply["id"] != late));
+}
+
+#[test]
+fn publishes_diagnostics() {
+	let mut client = FakeClient::default();
+	client.open("file:///a.ksh", "echo )\necho ok");
+	client.notify("textDocument/didChange", json!({
+		"textDocument": { "uri": "file:///a.ksh", "version": 2 },
+		"contentChanges": [{ "text": "#!/bin/kennsh\necho $kennsh_lsp_unset" }],
+	}));
+	client.notify("textDocument/didClose", json!({ "textDocument": { "uri": "file:///a.ksh" } }));
+
+	let (_, replies) = client.run();
+	let diagnostics: Vec<&Value> = replies.iter().map(|reply| &reply["params"]["diagnostics"]).collect();
+	assert_eq!(diagnostics.len(), 3);
+
+	assert_eq!(diagnostics[0][0]["severity"], SEVERITY_ERROR);
+	assert_eq!(diagnostics[0][0]["range"]["start"], json!({ "line": 0, "character": 5 }));
+
+	assert_eq!(diagnostics[1][0]["severity"], SEVERITY_WARNING);
+	assert_eq!(diagnostics[1][0]["range"], json!({
+		"start": { "line": 1, "character": 5 },
+		"end": { "line": 1, "character": 22 },
+	}));
+	assert!(diagnostics[1][0]["message"].as_str().unwrap().contains("never set"));
+
+	assert_eq!(diagnostics[2], &json!([]));
+}
+
+#[test]
+fn completes_builtins_and_functions() {
+	let mut client = FakeClient::default();
+	client.open("file:///a.ksh", "function greet\nend\n");
+	let completion = client.request("textDocument/completion", at("file:///a.ksh", 2, 0));
+
+	let (_, replies) = client.run();
+	let items = reply(&replies, completion)["result"].as_array().unwrap().clone();
+	let labels: Vec<&str> = items.iter().map(|item| item["label"].as_str().unwrap()).collect();
+	for label in ["~set", "cd", "while", "greet"] {
+		assert!(labels.contains(&label), "{} is not completed in {:?}", label, labels);
+	}
+}
+
+#[test]
+fn hovers_and_goes_to_definitions() {
+	let text = "# é日本\nfunction greet\n\techo hi\nend\n~set x (greet)\n";
+	let mut client = FakeClient::default();
+	client.open("file:///a.ksh", text);
+	let builtin = client.request("textDocument/hover", at("file:///a.ksh", 4, 2));
+	let function = client.request("textDocument/hover", at("file:///a.ksh", 4, 9));
+	let nothing = client.request("textDocument/hover", at("file:///a.ksh", 2, 2));
+	let definition = client.request("textDocument/definition", at("file:///a.ksh", 4, 10));
+
+	let (_, replies) = client.run();
+	let hover = &reply(&replies, builtin)["result"];
+	assert_eq!(hover["contents"]["value"], command::builtin_help("~set").unwrap());
+	assert_eq!(hover["range"]["end"], json!({ "line": 4, "character": 4 }));
+	assert!(reply(&replies, function)["result"]["contents"]["value"].as_str().unwrap().contains("line 2"));
+	assert_eq!(reply(&replies, nothing)["result"], Value::Null);
+	assert_eq!(reply(&replies, definition)["result"]["range"], json!({
+		"start": { "line": 1, "character": 9 },
+		"end": { "line": 1, "character": 14 },
+	}));
+}
+
+#[test]
+fn converts_positions() {
+	let text = "a\n😀b\n";
+	assert_eq!(position(text, 6), json!({ "line": 1, "character": 2 }));
+	assert_eq!(position(text, 7), json!({ "line": 1, "character": 3 }));
+	assert_eq!(offset(text, &json!({ "line": 1, "character": 2 })), 6);
+	assert_eq!(offset(text, &json!({ "line": 1, "character": 9 })), 7);
+	assert_eq!(offset(text, &json!({ "line": 5, "character": 0 })), text.len());
+}
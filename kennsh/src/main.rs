@@ -1,8 +1,20 @@
 mod str_extension;
+mod alias;
 mod command;
 mod error;
 mod window_title;
 mod syntax_parser;
+mod glob;
+mod tilde;
+mod math;
+mod variable;
+mod line_editor;
+mod rc;
+mod format;
+mod check;
+mod lsp;
+mod settings;
+mod trace;
 // mod highlight;
 mod env_util;
 
@@ -11,10 +23,13 @@ use error::Error;
 pub(crate) use error::Result;
 
 use kennsh_syscall_macro::syscall;
-use rustyline::{Editor, error::ReadlineError};
+use rustyline::{Editor, EventHandler, KeyEvent, error::ReadlineError};
 use str_extension::StringExtensions;
+use std::io::{Read, Write};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use window_title::{WindowTitleElement, set_window_title};
 use crate::syntax_parser::ParseError;
+use line_editor::{ExpandAbbreviation, ShellHelper};
 
 static SHELL_NAME: &str = "kennsh";
 
@@ -87,7 +102,7 @@ fn powerline_blocks(blocks: Vec<(String, ANSIColor)>) -> String {
 
 // TODO: Make the prompt customizable instead of a fixed function
 fn prompt(error: bool) -> String {
-    let use_powerline = env_util::env_is_true("use_powerline");
+    let use_powerline = settings::get().use_powerline;
     if use_powerline {
         use ANSIColor::*;
         let mut blocks = vec![];
@@ -116,9 +131,425 @@ fn prompt(error: bool) -> String {
     }
 }
 
+/// Prints an error that was returned while running source, returning the
+/// exit code it results in, or None if it doesn't change the exit code
+pub(crate) fn report_error(error: Error, source: &str) -> Option<u8> {
+    let mut exit_code = None;
+    match error {
+        Error::SyscallError { call_name: f, error: e } => {
+            // TODO: Once settings are up, print only in verbose flag
+            eprintln!("\x1b[3mkennsh: While attempting to use the \x1b[m\x1b[4m{}\x1b[m\x1b[3m system call, the following error occured:\x1b[m {}", f, e);
+            eprintln!("\x1b[3m        This is generally a sign of an internal error; please file a bug report\x1b[0m");
+        }
+        Error::RequestExit(errorcode) => std::process::exit(errorcode.unwrap_or_else(last_status).into()),
+        Error::ParseError(errors) => {
+            for (index, error) in errors.iter().enumerate() {
+                if index != 0 {
+                    eprintln!();
+                }
+                print_diagnostic(source, "Syntax error", error);
+            }
+            if errors.len() > 1 {
+                eprintln!("\x1b[3mkennsh: {} syntax errors were found\x1b[0m", errors.len());
+            }
+        }
+        Error::CommandNotFound(cmd) => {
+            exit_code = Some(127);
+            eprintln!("\x1b[3mkennsh: The command was not found:\x1b[0m {}", cmd)
+        }
+        Error::FileNotFound(path) => {
+            exit_code = Some(125);
+            eprint!("\x1b[3mkennsh: The file or directory was not found");
+            if let Some(path) = path {
+                eprint!(":\x1b[0m {}", path);
+            }
+            eprintln!("\x1b[0m")
+        }
+        Error::IsADirectory(path) => {
+            exit_code = Some(1);
+            eprint!("\x1b[3mkennsh: A directory was given where a file was expected");
+            if let Some(path) = path {
+                eprint!(":\x1b[0m {}", path);
+            }
+            eprintln!("\x1b[0m")
+        }
+        Error::ExitCodeParseError(code) => {
+            exit_code = Some(1);
+            eprintln!("\x1b[3mkennsh: An invalid exit code was given to the exit command:\x1b[0m {}", code)
+        }
+        Error::CommandPermissionDenied(cmd) => {
+            exit_code = Some(126);
+            eprintln!("\x1b[3mkennsh: Permission was denied to run the following command (is it executable?):\x1b[0m {}", cmd)
+        }
+        Error::FilePermissionDenied(file) => {
+            exit_code = Some(126);
+            if let Some(file) = file {
+                eprintln!("\x1b[3mkennsh: Permission was denied to access the following file:\x1b[0m {}", file)
+            }
+            else {
+                eprintln!("\x1b[3mkennsh: Permission was denied to access a file\x1b[0m")
+            }
+        }
+        Error::OtherError(message) => {
+            exit_code = Some(1);
+            eprintln!("\x1b[3mkennsh: Error:\x1b[0m {}", message)
+        }
+        Error::ChdirRequested(path) => {
+            panic!(format!("chdir to path {} was not handled", path))
+        }
+        Error::DynamicLibraryError(error) => {
+            exit_code = Some(124);
+            eprintln!("\x1b[3mkennsh: Dynamic Library error:\x1b[0m {}", error)
+        }
+        Error::NoGlobMatch(pattern) => {
+            exit_code = Some(1);
+            eprintln!("\x1b[3mkennsh: No files matched the pattern (quote it or set glob_pass_unmatched to pass it as is):\x1b[0m {}", pattern)
+        }
+        Error::MathError { expression, error } => {
+            exit_code = Some(1);
+            print_diagnostic(&expression, "Math error", &error);
+        }
+        Error::NoStatusChange => {}
+        Error::BreakRequested | Error::ContinueRequested => {
+            exit_code = Some(1);
+            eprintln!("\x1b[3mkennsh: break and continue can only be used inside of a loop\x1b[0m")
+        }
+        Error::ReturnRequested(_) => {
+            exit_code = Some(1);
+            eprintln!("\x1b[3mkennsh: return can only be used inside of a function\x1b[0m")
+        }
+        Error::EnvRemoveRequested(key) => {
+            panic!(format!("env key {} removal was not handled", key))
+        }
+        Error::EnvChangeRequested(key, value) | Error::LocalEnvChangeRequested(key, value) => {
+            panic!(format!("env key {} set to {} was not handled", key, value))
+        }
+        Error::AliasChangeRequested { name, .. } => {
+            panic!("the change of alias {} was not handled", name)
+        }
+        Error::FunctionEraseRequested(names) => {
+            panic!("erasing the functions {:?} was not handled", names)
+        }
+        Error::SettingsChangeRequested(settings) => {
+            panic!("the settings change to {:?} was not handled", settings)
+        }
+        Error::StateChangesRequested { changes, .. } => {
+            panic!("the state changes {:?} were not handled", changes)
+        }
+    }
+    exit_code
+}
+
+/// The exit code of the last command, as stored in $status
+pub(crate) fn last_status() -> u8 {
+    std::env::var("status").ok().and_then(|status| status.parse().ok()).unwrap_or(0)
+}
+
+/// A range of source to underline, with what it is unless it's the place
+/// of the error itself
+struct Annotation<'a> {
+    start_index: usize,
+    end_index: usize,
+    message: Option<&'a str>,
+}
+
+/// Prints error like a compiler diagnostic: the reason, then every line of
+/// source the error or its labels point to, with the error underlined by
+/// carets and the labels by dashes, followed by the notes and the help. The
+/// underlines line up by display width, so wide characters get as many marks
+/// as the columns they take, and tabs are kept as tabs.
+fn print_diagnostic(source: &str, kind: &str, error: &ParseError) {
+    eprintln!("\x1b[3mkennsh: {}:\x1b[0m {}", kind, error.reason);
+    let floor_boundary = |index: usize| {
+        let mut index = index.min(source.len());
+        // Point at the end of the last line rather than the empty one after it
+        if index == source.len() && source.ends_with('\n') {
+            index -= 1;
+        }
+        while !source.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    };
+    let line_start = |index: usize| source[..index].rfind('\n').map_or(0, |index| index + 1);
+    let line_number = |index: usize| source[..index].matches('\n').count() + 1;
+
+    let mut annotations = vec![Annotation {
+        start_index: floor_boundary(error.start_index),
+        end_index: error.end_index,
+        message: None,
+    }];
+    annotations.extend(error.labels.iter().map(|label| Annotation {
+        start_index: floor_boundary(label.start_index),
+        end_index: label.end_index,
+        message: Some(&label.message),
+    }));
+    // Labels on the same line as the error come after it
+    annotations.sort_by_key(|annotation| (line_number(annotation.start_index), annotation.message.is_some(), annotation.start_index));
+
+    let error_start = floor_boundary(error.start_index);
+    let column = source[line_start(error_start)..error_start].chars().count() + 1;
+    let gutter = annotations.iter().map(|annotation| line_number(annotation.start_index)).max().unwrap_or(1).to_string().len();
+    let margin = " ".repeat(gutter);
+    eprintln!("{}\x1b[34m-->\x1b[0m {}:{}", margin, line_number(error_start), column);
+    eprintln!("{} \x1b[34m|\x1b[0m", margin);
+
+    let mut last_line = None;
+    for annotation in &annotations {
+        let start_index = annotation.start_index;
+        let line_start = line_start(start_index);
+        let line_end = source[start_index..].find('\n').map_or(source.len(), |index| start_index + index);
+        let number = line_number(start_index);
+        if last_line != Some(number) {
+            if last_line.map_or(false, |last| number > last + 1) {
+                eprintln!("\x1b[34m...\x1b[0m");
+            }
+            eprintln!("\x1b[34m{:>width$} |\x1b[0m {}", number, &source[line_start..line_end], width = gutter);
+            last_line = Some(number);
+        }
+        let end_index = floor_boundary(annotation.end_index.min(line_end)).max(start_index);
+        let padding: String = source[line_start..start_index]
+            .chars()
+            .map(|c| if c == '\t' { "\t".to_owned() } else { " ".repeat(c.width().unwrap_or(0)) })
+            .collect();
+        let width = source[start_index..end_index].width().max(1);
+        match annotation.message {
+            None => eprintln!("{} \x1b[34m|\x1b[0m {}\x1b[31m{}\x1b[0m", margin, padding, "^".repeat(width)),
+            Some(message) => eprintln!("{} \x1b[34m|\x1b[0m {}\x1b[34m{} {}\x1b[0m", margin, padding, "-".repeat(width), message),
+        }
+    }
+    for note in &error.notes {
+        eprintln!("{} \x1b[34m=\x1b[0m note: {}", margin, note);
+    }
+    if let Some(help) = &error.help {
+        eprintln!("{} \x1b[34m=\x1b[0m help: {}", margin, help);
+    }
+}
+
+/// Reads a script file, or prints why it couldn't be read
+fn read_script(path: &str) -> Option<String> {
+    let mut source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("\x1b[3mkennsh: The script could not be read:\x1b[0m {}: {}", path, error);
+            return None;
+        }
+    };
+    // Blank out a #! line, keeping the positions of errors intact
+    if source.starts_with("#!") {
+        let length = source.find('\n').unwrap_or(source.len());
+        source.replace_range(..length, &" ".repeat(length));
+    }
+    Some(source)
+}
+
+/// Runs a script file, returning its exit code
+fn run_script(path: &str) -> u8 {
+    let source = match read_script(path) {
+        Some(source) => source,
+        None => return 127,
+    };
+    settings::update(|settings| settings.no_title = true);
+    match command::handle(source.clone()) {
+        Ok(exit_code) => exit_code,
+        Err(e @ Error::ParseError(_)) => {
+            report_error(e, &source);
+            2
+        }
+        Err(e) => report_error(e, &source).unwrap_or_else(last_status),
+    }
+}
+
+/// Runs the rc file, if there is one
+fn run_rc() {
+    let path = match rc::path() {
+        Some(path) if path.exists() => path,
+        _ => return,
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(source) => {
+            if let Err(e) = command::handle(source.clone()) {
+                report_error(e, &source);
+            }
+        }
+        Err(error) => eprintln!("\x1b[3mkennsh: The rc file could not be read:\x1b[0m {}: {}", path.display(), error),
+    }
+}
+
+/// Prints the syntax tree of a script or of the command after -c as JSON,
+/// returning the exit code
+fn dump_ast(arguments: &[String]) -> u8 {
+    let source = match arguments {
+        [flag, command] if flag == "-c" => command.clone(),
+        [path] => match read_script(path) {
+            Some(source) => source,
+            None => return 127,
+        },
+        _ => {
+            eprintln!("\x1b[3mkennsh: Usage:\x1b[0m kennsh --dump-ast (-c command | script)");
+            return 2;
+        }
+    };
+    match syntax_parser::parse(source.clone()) {
+        Ok(tree) => {
+            let json = serde_json::to_string_pretty(&tree).expect("syntax trees can always be serialized");
+            match writeln!(std::io::stdout().lock(), "{}", json) {
+                Ok(()) => 0,
+                Err(error) => output_failed(error),
+            }
+        }
+        Err(errors) => {
+            report_error(Error::ParseError(errors), &source);
+            2
+        }
+    }
+}
+
+/// Formats the given scripts in place, or stdin to stdout if there are none.
+/// With --check nothing is written, and the exit code is 1 if a script is not
+/// formatted
+fn format_scripts(arguments: &[String]) -> u8 {
+    let check = arguments.first().map(String::as_str) == Some("--check");
+    let paths = if check { &arguments[1..] } else { arguments };
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("\x1b[3mkennsh: The input could not be read:\x1b[0m {}", error);
+            return 2;
+        }
+        return match format::format(&source) {
+            Ok(formatted) if check => (formatted != source).into(),
+            Ok(formatted) => match write!(std::io::stdout().lock(), "{}", formatted) {
+                Ok(()) => 0,
+                Err(error) => output_failed(error),
+            },
+            Err(errors) => {
+                report_error(Error::ParseError(errors), &source);
+                2
+            }
+        };
+    }
+
+    let mut exit_code = 0;
+    for path in paths {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("\x1b[3mkennsh: The script could not be read:\x1b[0m {}: {}", path, error);
+                exit_code = 2;
+                continue;
+            }
+        };
+        let formatted = match format::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                eprintln!("\x1b[3mkennsh: The script could not be formatted:\x1b[0m {}", path);
+                report_error(Error::ParseError(errors), &source);
+                exit_code = 2;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            exit_code = exit_code.max(1);
+            if let Err(error) = writeln!(std::io::stdout().lock(), "{}", path) {
+                return exit_code.max(output_failed(error));
+            }
+        }
+        else if let Err(error) = std::fs::write(path, formatted) {
+            eprintln!("\x1b[3mkennsh: The script could not be written:\x1b[0m {}: {}", path, error);
+            exit_code = 2;
+        }
+    }
+    exit_code
+}
+
+/// The exit code after stdout couldn't be written to. A closed stdout, like
+/// after `| head`, only means the rest of the output isn't wanted.
+fn output_failed(error: std::io::Error) -> u8 {
+    if error.kind() == std::io::ErrorKind::BrokenPipe {
+        return 0;
+    }
+    eprintln!("\x1b[3mkennsh: The output could not be written:\x1b[0m {}", error);
+    2
+}
+
+/// Checks scripts without running them, printing the problems found. The
+/// exit code is 1 if there were problems, and 2 if a script couldn't be read
+/// or parsed.
+fn check_scripts(paths: &[String]) -> u8 {
+    if paths.is_empty() {
+        eprintln!("\x1b[3mkennsh: Usage:\x1b[0m kennsh --check script...");
+        return 2;
+    }
+    let mut exit_code = 0;
+    for path in paths {
+        let source = match read_script(path) {
+            Some(source) => source,
+            None => {
+                exit_code = 2;
+                continue;
+            }
+        };
+        match check::check(&source) {
+            Ok(warnings) => {
+                for (index, warning) in warnings.iter().enumerate() {
+                    if index > 0 {
+                        eprintln!();
+                    }
+                    print_diagnostic(&source, &format!("Warning in {}", path), warning);
+                }
+                if !warnings.is_empty() {
+                    exit_code = exit_code.max(1);
+                }
+            }
+            Err(errors) => {
+                eprintln!("\x1b[3mkennsh: The script could not be checked:\x1b[0m {}", path);
+                report_error(Error::ParseError(errors), &source);
+                exit_code = 2;
+            }
+        }
+    }
+    exit_code
+}
+
 fn main() {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    match arguments.first().map(String::as_str) {
+        Some("--dump-ast") => std::process::exit(dump_ast(&arguments[1..]).into()),
+        Some("--fmt") => std::process::exit(format_scripts(&arguments[1..]).into()),
+        Some("--check") => std::process::exit(check_scripts(&arguments[1..]).into()),
+        Some("--lsp") => std::process::exit(match lsp::serve(std::io::stdin().lock(), std::io::stdout().lock()) {
+            // Exiting without a shutdown request first is an error
+            Ok(shutdown) => (!shutdown).into(),
+            Err(error) => {
+                eprintln!("\x1b[3mkennsh: The language server stopped:\x1b[0m {}", error);
+                2
+            }
+        }),
+        _ => {}
+    }
+
+    settings::load();
+
+    // kennsh script [arguments...]
+    let mut arguments = arguments.into_iter();
+    if let Some(script) = arguments.next() {
+        let argv: Vec<String> = arguments.collect();
+        std::env::set_var("argv", variable::encode_list("argv", &argv));
+        std::process::exit(run_script(&script).into());
+    }
+    std::env::set_var("argv", variable::encode_list("argv", &[]));
+
+    run_rc();
+
     // Configure readline
-    let mut rl = Editor::<()>::new();
+    let mut rl = Editor::<ShellHelper>::new();
+    rl.set_helper(Some(ShellHelper));
+    rl.bind_sequence(KeyEvent::from(' '), EventHandler::Conditional(Box::new(ExpandAbbreviation)));
 
     let mut last_exit_code: u8 = 0;
 
@@ -149,10 +580,15 @@ fn main() {
         let readline = rl.readline(&prompt(last_exit_code != 0));
         match readline {
             Ok(line) => {
-                let line = line.trim().to_string();
+                let mut line = line.trim().to_string();
+                // An abbreviation typed last is expanded on enter, so that
+                // the history shows what actually ran
+                if let Some((start, expansion)) = alias::abbreviation_at(&line, line.len()) {
+                    line.replace_range(start.., &expansion);
+                }
                 if !line.is_blank() {
                     rl.add_history_entry(line.clone());
-                    if env_util::env_is_true("stderr_red") {
+                    if settings::get().stderr_red {
                         eprint!("\x1b[91m");
                     }
                     match command::handle(line.clone()) {
@@ -162,72 +598,9 @@ fn main() {
                             //     eprintln!("\x1b[3mkennsh: The program exited with the following code:\x1b[m {}", errorcode);
                             // }
                         },
-                        Err(e) => match e {
-                            Error::SyscallError { call_name: f, error: e } => {
-                                // TODO: Once settings are up, print only in verbose flag
-                                eprintln!("\x1b[3mkennsh: While attempting to use the \x1b[m\x1b[4m{}\x1b[m\x1b[3m system call, the following error occured:\x1b[m {}", f, e);
-                                eprintln!("\x1b[3m        This is generally a sign of an internal error; please file a bug report\x1b[0m");
-                            }
-                            Error::RequestExit(errorcode) => std::process::exit(errorcode.unwrap_or_else(|| last_exit_code).into()),
-                            Error::ParseError(pe) => {
-                                let ParseError {
-                                    start_index,
-                                    end_index,
-                                    reason,
-                                } = pe;
-                                eprintln!("\x1b[3mkennsh: Syntax error:\x1b[0m {}", reason);
-                                eprintln!("{}", line);
-                                eprint!("{}", " ".repeat((start_index - 1).max(0)));
-                                eprint!("\x1b[31m");
-                                eprint!("{}", "^".repeat((end_index - start_index).max(1)));
-                                eprintln!("\x1b[0m");
-                            }
-                            Error::CommandNotFound(cmd) => {
-                                last_exit_code = 127;
-                                eprintln!("\x1b[3mkennsh: The command was not found:\x1b[0m {}", cmd)
-                            }
-                            Error::FileNotFound(path) => {
-                                last_exit_code = 125;
-                                eprint!("\x1b[3mkennsh: The file or directory was not found");
-                                if let Some(path) = path {
-                                    eprint!(":\x1b[0m {}", path);
-                                }
-                                eprintln!("\x1b[0m")
-                            }
-                            Error::ExitCodeParseError(exit_code) => {
-                                last_exit_code = 1;
-                                eprintln!("\x1b[3mkennsh: An invalid exit code was given to the exit command:\x1b[0m {}", exit_code)
-                            }
-                            Error::CommandPermissionDenied(cmd) => {
-                                last_exit_code = 126;
-                                eprintln!("\x1b[3mkennsh: Permission was denied to run the following command (is it executable?):\x1b[0m {}", cmd)
-                            }
-                            Error::FilePermissionDenied(file) => {
-                                last_exit_code = 126;
-                                if let Some(file) = file {
-                                    eprintln!("\x1b[3mkennsh: Permission was denied to access the following file:\x1b[0m {}", file)
-                                }
-                                else {
-                                    eprintln!("\x1b[3mkennsh: Permission was denied to access a file\x1b[0m")
-                                }
-                            }
-                            Error::OtherError(message) => {
-                                last_exit_code = 1;
-                                eprintln!("\x1b[3mkennsh: Error:\x1b[0m {}", message)
-                            }
-                            Error::ChdirRequested(path) => {
-                                panic!(format!("chdir to path {} was not handled", path))
-                            }
-                            Error::DynamicLibraryError(error) => {
-                                last_exit_code = 124;
-                                eprintln!("\x1b[3mkennsh: Dynamic Library error:\x1b[0m {}", error)
-                            }
-                            Error::NoStatusChange => {}
-                            Error::EnvRemoveRequested(key) => {
-                                panic!(format!("env key {} removal was not handled", key))
-                            }
-                            Error::EnvChangeRequested(key, value) => {
-                                panic!(format!("env key {} set to {} was not handled", key, value))
+                        Err(e) => {
+                            if let Some(exit_code) = report_error(e, &line) {
+                                last_exit_code = exit_code;
                             }
                         }
                     };
@@ -236,6 +609,9 @@ fn main() {
                     std::env::set_var("status", last_exit_code.to_string());
                     // ANSI Reset
                     print!("\x1b[m");
+                    // Flush now, otherwise the buffered reset would be inherited
+                    // by forked children and end up in subcommand output
+                    let _ = std::io::Write::flush(&mut std::io::stdout());
                 }
             },
             Err(ReadlineError::Eof) => {
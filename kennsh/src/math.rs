@@ -0,0 +1,585 @@
+use std::{convert::TryFrom, fmt::Display};
+use serde::Serialize;
+
+use crate::syntax_parser::{ParseError, Span};
+
+/// An arithmetic expression, as used by `$((...))` and `~math`.
+///
+/// Integers stay integers as long as every operand is one, so `7 / 2` is 3
+/// while `7 / 2.0` is 3.5. Variables can be used by name, with or without a
+/// `$`, and count as 0 when they are not set.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct Expression {
+	source: String,
+	// Serialized expressions only keep their source
+	#[serde(skip)]
+	root: Node,
+}
+
+impl Expression {
+	/// Parses an expression. The indices of any error are relative to source.
+	pub(crate) fn parse(source: &str) -> Result<Self, ParseError> {
+		let tokens = tokenize(source)?;
+		let mut parser = Parser {
+			tokens: &tokens,
+			position: 0,
+			end_index: source.len(),
+		};
+		let root = parser.parse_or()?;
+		if let Some(token) = parser.tokens.get(parser.position) {
+			return Err(ParseError::new(
+				Span::new(token.start_index, token.end_index),
+				"Expected an operator or the end of the expression",
+			));
+		}
+		Ok(Self {
+			source: source.to_owned(),
+			root,
+		})
+	}
+
+	pub(crate) fn source(&self) -> &str {
+		&self.source
+	}
+
+	/// Evaluates the expression. The indices of any error are relative to
+	/// the source of the expression.
+	pub(crate) fn evaluate(&self) -> Result<Number, ParseError> {
+		self.root.evaluate()
+	}
+}
+
+impl Display for Expression {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.source)
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Number {
+	Integer(i64),
+	Float(f64),
+}
+
+impl Number {
+	fn as_float(self) -> f64 {
+		match self {
+			Number::Integer(i) => i as f64,
+			Number::Float(f) => f,
+		}
+	}
+
+	fn is_true(self) -> bool {
+		match self {
+			Number::Integer(i) => i != 0,
+			Number::Float(f) => f != 0.0,
+		}
+	}
+
+	fn from_bool(b: bool) -> Self {
+		Number::Integer(if b { 1 } else { 0 })
+	}
+}
+
+impl Display for Number {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Number::Integer(i) => write!(f, "{}", i),
+			Number::Float(fl) => write!(f, "{}", fl),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+	Number(String),
+	Identifier(String),
+	Operator(&'static str),
+	OpenParenthesis,
+	CloseParenthesis,
+	Comma,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+	kind: TokenKind,
+	start_index: usize,
+	end_index: usize,
+}
+
+// Longest operators first, so that ** is not read as *
+static OPERATORS: [&str; 17] = [
+	"**", "==", "!=", "<=", ">=", "&&", "||",
+	"+", "-", "*", "/", "%", "<", ">", "!", "$", "^",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+	// Every token is ASCII, so bytes can be looked at one by one, as long as
+	// anything else ends up as an error before it is stepped into
+	let chars: Vec<char> = source.as_bytes().iter().map(|b| char::from(*b)).collect();
+	let mut tokens = vec![];
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		let start_index = i;
+		let kind = if c.is_ascii_whitespace() {
+			i += 1;
+			continue;
+		}
+		else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).map_or(false, |c| c.is_ascii_digit())) {
+			while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+				i += 1;
+			}
+			// Exponent, like 1e9 or 2.5E-3
+			if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+				let mut j = i + 1;
+				if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+					j += 1;
+				}
+				if j < chars.len() && chars[j].is_ascii_digit() {
+					i = j;
+					while i < chars.len() && chars[i].is_ascii_digit() {
+						i += 1;
+					}
+				}
+			}
+			TokenKind::Number(source[start_index..i].to_owned())
+		}
+		else if c.is_ascii_alphabetic() || c == '_' {
+			while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+				i += 1;
+			}
+			TokenKind::Identifier(source[start_index..i].to_owned())
+		}
+		else if c == '(' {
+			i += 1;
+			TokenKind::OpenParenthesis
+		}
+		else if c == ')' {
+			i += 1;
+			TokenKind::CloseParenthesis
+		}
+		else if c == ',' {
+			i += 1;
+			TokenKind::Comma
+		}
+		else if let Some(operator) = OPERATORS.iter().find(|op| source[i..].starts_with(*op)) {
+			i += operator.len();
+			TokenKind::Operator(operator)
+		}
+		else {
+			let c = source[i..].chars().next().unwrap_or(c);
+			return Err(ParseError::new(
+				Span::new(i, i + c.len_utf8()),
+				format!("Unexpected character in expression: {}", c),
+			));
+		};
+		tokens.push(Token {
+			kind,
+			start_index,
+			end_index: i,
+		});
+	}
+	Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+	kind: NodeKind,
+	start_index: usize,
+	end_index: usize,
+}
+
+#[derive(Clone, Debug)]
+enum NodeKind {
+	Number(Number),
+	Variable(String),
+	Unary(&'static str, Box<Node>),
+	Binary(Box<Node>, &'static str, Box<Node>),
+	Function(Function, Vec<Node>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Function {
+	Abs,
+	Ceil,
+	Floor,
+	Max,
+	Min,
+	Pow,
+	Round,
+	Sqrt,
+}
+
+impl Function {
+	fn from_name(name: &str) -> Option<Self> {
+		Some(match name {
+			"abs" => Function::Abs,
+			"ceil" => Function::Ceil,
+			"floor" => Function::Floor,
+			"max" => Function::Max,
+			"min" => Function::Min,
+			"pow" => Function::Pow,
+			"round" => Function::Round,
+			"sqrt" => Function::Sqrt,
+			_ => return None,
+		})
+	}
+
+	/// The accepted number of arguments; None for any number above 0
+	fn arity(self) -> Option<usize> {
+		match self {
+			Function::Max | Function::Min => None,
+			Function::Pow => Some(2),
+			_ => Some(1),
+		}
+	}
+}
+
+struct Parser<'a> {
+	tokens: &'a [Token],
+	position: usize,
+	end_index: usize,
+}
+
+impl<'a> Parser<'a> {
+	fn peek_operator(&self, operators: &[&'static str]) -> Option<&'static str> {
+		match self.tokens.get(self.position) {
+			Some(Token { kind: TokenKind::Operator(op), .. }) if operators.contains(op) => Some(op),
+			_ => None,
+		}
+	}
+
+	fn parse_binary(
+		&mut self,
+		operators: &[&'static str],
+		next: fn(&mut Self) -> Result<Node, ParseError>,
+	) -> Result<Node, ParseError> {
+		let mut left = next(self)?;
+		while let Some(operator) = self.peek_operator(operators) {
+			self.position += 1;
+			let right = next(self)?;
+			left = Node {
+				start_index: left.start_index,
+				end_index: right.end_index,
+				kind: NodeKind::Binary(Box::new(left), operator, Box::new(right)),
+			};
+		}
+		Ok(left)
+	}
+
+	fn parse_or(&mut self) -> Result<Node, ParseError> {
+		self.parse_binary(&["||"], Self::parse_and)
+	}
+
+	fn parse_and(&mut self) -> Result<Node, ParseError> {
+		self.parse_binary(&["&&"], Self::parse_equality)
+	}
+
+	fn parse_equality(&mut self) -> Result<Node, ParseError> {
+		self.parse_binary(&["==", "!="], Self::parse_comparison)
+	}
+
+	fn parse_comparison(&mut self) -> Result<Node, ParseError> {
+		self.parse_binary(&["<", "<=", ">", ">="], Self::parse_additive)
+	}
+
+	fn parse_additive(&mut self) -> Result<Node, ParseError> {
+		self.parse_binary(&["+", "-"], Self::parse_multiplicative)
+	}
+
+	fn parse_multiplicative(&mut self) -> Result<Node, ParseError> {
+		self.parse_binary(&["*", "/", "%"], Self::parse_unary)
+	}
+
+	fn parse_unary(&mut self) -> Result<Node, ParseError> {
+		if let Some(operator) = self.peek_operator(&["-", "+", "!"]) {
+			let start_index = self.tokens[self.position].start_index;
+			self.position += 1;
+			let operand = self.parse_unary()?;
+			Ok(Node {
+				start_index,
+				end_index: operand.end_index,
+				kind: NodeKind::Unary(operator, Box::new(operand)),
+			})
+		}
+		else {
+			self.parse_power()
+		}
+	}
+
+	fn parse_power(&mut self) -> Result<Node, ParseError> {
+		let base = self.parse_primary()?;
+		// Right associative: 2 ** 3 ** 2 is 2 ** 9; ^ is accepted as well
+		if self.peek_operator(&["**", "^"]).is_some() {
+			self.position += 1;
+			let exponent = self.parse_unary()?;
+			Ok(Node {
+				start_index: base.start_index,
+				end_index: exponent.end_index,
+				kind: NodeKind::Binary(Box::new(base), "**", Box::new(exponent)),
+			})
+		}
+		else {
+			Ok(base)
+		}
+	}
+
+	fn parse_primary(&mut self) -> Result<Node, ParseError> {
+		let token = match self.tokens.get(self.position) {
+			Some(token) => token.clone(),
+			None => return Err(ParseError::new(
+				Span::new(self.end_index, self.end_index + 1),
+				"Expected a number, variable or ( but found the end of the expression",
+			)),
+		};
+		self.position += 1;
+		match token.kind {
+			TokenKind::Number(text) => {
+				let number = if let Ok(i) = text.parse::<i64>() {
+					Number::Integer(i)
+				}
+				else if let Ok(f) = text.parse::<f64>() {
+					Number::Float(f)
+				}
+				else {
+					return Err(ParseError::new(
+						Span::new(token.start_index, token.end_index),
+						format!("Invalid number: {}", text),
+					));
+				};
+				Ok(Node {
+					kind: NodeKind::Number(number),
+					start_index: token.start_index,
+					end_index: token.end_index,
+				})
+			}
+			TokenKind::Operator("$") => {
+				match self.tokens.get(self.position) {
+					Some(Token { kind: TokenKind::Identifier(name), end_index, .. }) => {
+						self.position += 1;
+						Ok(Node {
+							kind: NodeKind::Variable(name.clone()),
+							start_index: token.start_index,
+							end_index: *end_index,
+						})
+					}
+					_ => Err(ParseError::new(
+						Span::new(token.start_index, token.end_index),
+						"Expected a variable name after $",
+					)),
+				}
+			}
+			TokenKind::Identifier(name) => {
+				if let Some(Token { kind: TokenKind::OpenParenthesis, .. }) = self.tokens.get(self.position) {
+					self.parse_function_call(name, token.start_index, token.end_index)
+				}
+				else {
+					Ok(Node {
+						kind: NodeKind::Variable(name),
+						start_index: token.start_index,
+						end_index: token.end_index,
+					})
+				}
+			}
+			TokenKind::OpenParenthesis => {
+				let inner = self.parse_or()?;
+				self.expect_close_parenthesis(token.start_index)?;
+				Ok(inner)
+			}
+			_ => Err(ParseError::new(
+				Span::new(token.start_index, token.end_index),
+				"Expected a number, variable or (",
+			)),
+		}
+	}
+
+	fn parse_function_call(&mut self, name: String, start_index: usize, name_end_index: usize) -> Result<Node, ParseError> {
+		let function = Function::from_name(&name).ok_or_else(|| ParseError::new(
+			Span::new(start_index, name_end_index),
+			format!("Unknown function {}", name),
+		).with_note("available: abs, ceil, floor, max, min, pow, round, sqrt"))?;
+		let open_index = self.tokens[self.position].start_index;
+		// Skip (
+		self.position += 1;
+		let mut arguments = vec![];
+		if let Some(Token { kind: TokenKind::CloseParenthesis, .. }) = self.tokens.get(self.position) {}
+		else {
+			loop {
+				arguments.push(self.parse_or()?);
+				if let Some(Token { kind: TokenKind::Comma, .. }) = self.tokens.get(self.position) {
+					self.position += 1;
+				}
+				else {
+					break;
+				}
+			}
+		}
+		let end_index = self.expect_close_parenthesis(open_index)?;
+		let arity_ok = match function.arity() {
+			Some(arity) => arguments.len() == arity,
+			None => !arguments.is_empty(),
+		};
+		if !arity_ok {
+			return Err(ParseError::new(
+				Span::new(start_index, end_index),
+				match function.arity() {
+					Some(arity) => format!("{} expects {} argument(s), but {} were given", name, arity, arguments.len()),
+					None => format!("{} expects at least 1 argument", name),
+				},
+			));
+		}
+		Ok(Node {
+			kind: NodeKind::Function(function, arguments),
+			start_index,
+			end_index,
+		})
+	}
+
+	/// Consumes a ), returning the index after it
+	fn expect_close_parenthesis(&mut self, open_index: usize) -> Result<usize, ParseError> {
+		match self.tokens.get(self.position) {
+			Some(Token { kind: TokenKind::CloseParenthesis, end_index, .. }) => {
+				self.position += 1;
+				Ok(*end_index)
+			}
+			_ => Err(ParseError::new(
+				Span::new(open_index, open_index + 1),
+				"This parenthesis is never closed",
+			)),
+		}
+	}
+}
+
+impl Node {
+	fn error(&self, reason: String) -> ParseError {
+		ParseError::new(Span::new(self.start_index, self.end_index), reason)
+	}
+
+	fn evaluate(&self) -> Result<Number, ParseError> {
+		use Number::*;
+		match &self.kind {
+			NodeKind::Number(n) => Ok(*n),
+			NodeKind::Variable(name) => {
+				let value = crate::variable::get_joined(name).unwrap_or_default();
+				let value = value.trim();
+				if value.is_empty() {
+					Ok(Integer(0))
+				}
+				else if let Ok(i) = value.parse::<i64>() {
+					Ok(Integer(i))
+				}
+				else if let Ok(f) = value.parse::<f64>() {
+					Ok(Float(f))
+				}
+				else {
+					Err(self.error(format!("The value of ${} is not a number: {}", name, value)))
+				}
+			}
+			NodeKind::Unary(operator, operand) => {
+				let value = operand.evaluate()?;
+				Ok(match (*operator, value) {
+					("-", Integer(i)) => Integer(i.checked_neg().ok_or_else(|| self.overflow())?),
+					("-", Float(f)) => Float(-f),
+					("!", value) => Number::from_bool(!value.is_true()),
+					(_, value) => value,
+				})
+			}
+			NodeKind::Binary(left, "&&", right) => {
+				// Short circuit
+				Ok(Number::from_bool(left.evaluate()?.is_true() && right.evaluate()?.is_true()))
+			}
+			NodeKind::Binary(left, "||", right) => {
+				Ok(Number::from_bool(left.evaluate()?.is_true() || right.evaluate()?.is_true()))
+			}
+			NodeKind::Binary(left, operator, right) => {
+				let (left, right) = (left.evaluate()?, right.evaluate()?);
+				self.evaluate_binary(operator, left, right)
+			}
+			NodeKind::Function(function, arguments) => {
+				let arguments = arguments
+					.iter()
+					.map(|argument| argument.evaluate())
+					.collect::<Result<Vec<_>, _>>()?;
+				self.evaluate_function(*function, arguments)
+			}
+		}
+	}
+
+	fn overflow(&self) -> ParseError {
+		self.error("Integer overflow; use a decimal point to compute with floats".to_owned())
+	}
+
+	fn evaluate_binary(&self, operator: &str, left: Number, right: Number) -> Result<Number, ParseError> {
+		use Number::*;
+		if let ("/", _, Integer(0)) | ("%", _, Integer(0)) = (operator, left, right) {
+			return Err(self.error("Division by zero".to_owned()));
+		}
+		match (left, right) {
+			(Integer(l), Integer(r)) => {
+				let result = match operator {
+					"+" => l.checked_add(r),
+					"-" => l.checked_sub(r),
+					"*" => l.checked_mul(r),
+					"/" => l.checked_div(r),
+					"%" => l.checked_rem(r),
+					"**" if r < 0 => return Ok(Float((l as f64).powf(r as f64))),
+					"**" => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+					"==" => Some((l == r) as i64),
+					"!=" => Some((l != r) as i64),
+					"<" => Some((l < r) as i64),
+					"<=" => Some((l <= r) as i64),
+					">" => Some((l > r) as i64),
+					">=" => Some((l >= r) as i64),
+					_ => unreachable!("unknown operator {}", operator),
+				};
+				result.map(Integer).ok_or_else(|| self.overflow())
+			}
+			(left, right) => {
+				let (l, r) = (left.as_float(), right.as_float());
+				Ok(match operator {
+					"+" => Float(l + r),
+					"-" => Float(l - r),
+					"*" => Float(l * r),
+					"/" => Float(l / r),
+					"%" => Float(l % r),
+					"**" => Float(l.powf(r)),
+					"==" => Number::from_bool(l == r),
+					"!=" => Number::from_bool(l != r),
+					"<" => Number::from_bool(l < r),
+					"<=" => Number::from_bool(l <= r),
+					">" => Number::from_bool(l > r),
+					">=" => Number::from_bool(l >= r),
+					_ => unreachable!("unknown operator {}", operator),
+				})
+			}
+		}
+	}
+
+	fn evaluate_function(&self, function: Function, arguments: Vec<Number>) -> Result<Number, ParseError> {
+		use Number::*;
+		let rounded = |f: f64| {
+			if f.is_finite() && f.abs() < i64::MAX as f64 { Integer(f as i64) } else { Float(f) }
+		};
+		Ok(match (function, arguments[0]) {
+			(Function::Abs, Integer(i)) => Integer(i.checked_abs().ok_or_else(|| self.overflow())?),
+			(Function::Abs, Float(f)) => Float(f.abs()),
+			(Function::Ceil, value) => rounded(value.as_float().ceil()),
+			(Function::Floor, value) => rounded(value.as_float().floor()),
+			(Function::Round, value) => rounded(value.as_float().round()),
+			(Function::Sqrt, value) => Float(value.as_float().sqrt()),
+			(Function::Pow, base) => self.evaluate_binary("**", base, arguments[1])?,
+			(Function::Max, _) | (Function::Min, _) => {
+				let is_max = matches!(function, Function::Max);
+				let mut best = arguments[0];
+				for argument in &arguments[1..] {
+					let greater = self.evaluate_binary(">", *argument, best)?.is_true();
+					if greater == is_max && self.evaluate_binary("!=", *argument, best)?.is_true() {
+						best = *argument;
+					}
+				}
+				best
+			}
+		})
+	}
+}
@@ -0,0 +1,53 @@
+//! The rc file, which the interactive shell runs before the first prompt.
+//!
+//! It lives at `$XDG_CONFIG_HOME/kennsh/rc.ksh`, or `~/.config/kennsh/rc.ksh`
+//! if `$XDG_CONFIG_HOME` is not set.
+
+use std::path::PathBuf;
+
+use crate::{alias::AliasKind, error::Error};
+
+pub(crate) fn path() -> Option<PathBuf> {
+	Some(config_directory()?.join("rc.ksh"))
+}
+
+/// The directory of the rc file and the config file
+pub(crate) fn config_directory() -> Option<PathBuf> {
+	let config = match std::env::var("XDG_CONFIG_HOME") {
+		Ok(config) if !config.is_empty() => PathBuf::from(config),
+		_ => PathBuf::from(std::env::var("HOME").ok()?).join(".config"),
+	};
+	Some(config.join("kennsh"))
+}
+
+/// Quotes text so that it is a single word with exactly that content
+pub(crate) fn quote(text: &str) -> String {
+	format!("'{}'", text.replace('`', "``").replace('\'', "`'"))
+}
+
+/// Replaces the line defining name in the rc file by definition, or removes
+/// it if there is no definition
+pub(crate) fn save_definition(kind: AliasKind, name: &str, definition: Option<&str>) -> crate::Result<()> {
+	let path = path().ok_or_else(|| Error::OtherError("Can't find the rc file, as $HOME is not set".to_owned()))?;
+	let io_error = |error: std::io::Error| Error::OtherError(format!("{}: {}", path.display(), error));
+
+	let contents = match std::fs::read_to_string(&path) {
+		Ok(contents) => contents,
+		Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+		Err(error) => return Err(io_error(error)),
+	};
+	let mut lines: Vec<&str> = contents
+		.lines()
+		.filter(|line| {
+			let mut words = line.split_whitespace();
+			!(words.next() == Some(kind.builtin()) && words.next() == Some(name))
+		})
+		.collect();
+	lines.extend(definition);
+	let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+
+	if let Some(directory) = path.parent() {
+		std::fs::create_dir_all(directory).map_err(io_error)?;
+	}
+	std::fs::write(&path, contents).map_err(io_error)
+}
@@ -0,0 +1,160 @@
+//! The settings of the shell, loaded from `config.toml` in the directory of
+//! the rc file and changed for the session with `~config`.
+//!
+//! An environment variable named like a setting overrides it, as the
+//! settings used to be set only that way.
+
+use std::{path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{env_util::env_is_true, rc};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Settings {
+	// Draws the prompt as powerline blocks
+	pub use_powerline: bool,
+	// Colors what interactive commands print red, which is meant for stderr
+	pub stderr_red: bool,
+	// The older name of trace, which traces commands but never syscalls
+	pub who_is_running: bool,
+	// Like who_is_running, also tracing the bytes of external command names
+	pub who_is_running_ext: bool,
+	// Prints the syntax tree of each command line
+	pub print_syntax_tree: bool,
+	// Prints what ~server does with its socket
+	pub socket_debug: bool,
+	// Leaves the window title alone
+	pub no_title: bool,
+	// Prints every command before it runs, see trace.rs
+	pub trace: bool,
+	// Also traces the syscalls made by commands
+	pub trace_syscalls: bool,
+	// The file the trace is appended to, or empty for stderr
+	pub trace_file: String,
+	// Keeps the last syscalls for ~syscalls
+	pub record_syscalls: bool,
+	// Passes patterns that match no files on as they are instead of failing
+	pub glob_pass_unmatched: bool,
+}
+
+// None until the config file is loaded
+static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+
+impl Settings {
+	/// Every setting that is switched on or off by its name
+	fn switches_mut(&mut self) -> [(&'static str, &mut bool); 11] {
+		[
+			("use_powerline", &mut self.use_powerline),
+			("stderr_red", &mut self.stderr_red),
+			("who_is_running", &mut self.who_is_running),
+			("who_is_running_ext", &mut self.who_is_running_ext),
+			("print_syntax_tree", &mut self.print_syntax_tree),
+			("socket_debug", &mut self.socket_debug),
+			("no_title", &mut self.no_title),
+			("trace", &mut self.trace),
+			("trace_syscalls", &mut self.trace_syscalls),
+			("record_syscalls", &mut self.record_syscalls),
+			("glob_pass_unmatched", &mut self.glob_pass_unmatched),
+		]
+	}
+
+	/// The settings as a table of values by name
+	pub(crate) fn to_table(&self) -> toml::value::Table {
+		match toml::Value::try_from(self) {
+			Ok(toml::Value::Table(table)) => table,
+			_ => unreachable!("settings are always a table"),
+		}
+	}
+
+	/// The settings with key set to value, written like in the config file
+	pub(crate) fn with(&self, key: &str, value: &str) -> Result<Settings, String> {
+		let mut table = self.to_table();
+		if !table.contains_key(key) {
+			return Err(format!("There is no setting called {}", key));
+		}
+		let parsed = format!("value = {}", value).parse::<toml::Value>().ok().and_then(|parsed| parsed.get("value").cloned());
+		let value = match (parsed, &table[key]) {
+			(Some(parsed), _) => parsed,
+			// Text doesn't have to be quoted
+			(None, toml::Value::String(_)) => toml::Value::String(value.to_owned()),
+			(None, _) => return Err(format!("{} is not a valid value, like true or false", value)),
+		};
+		table.insert(key.to_owned(), value);
+		toml::Value::Table(table).try_into().map_err(|error| format!("{}: {}", key, error))
+	}
+}
+
+pub(crate) fn path() -> Option<PathBuf> {
+	Some(rc::config_directory()?.join("config.toml"))
+}
+
+/// Reads the config file, with the defaults if there is none
+pub(crate) fn read() -> Result<Settings, String> {
+	let path = match path() {
+		Some(path) => path,
+		None => return Ok(Settings::default()),
+	};
+	match std::fs::read_to_string(&path) {
+		Ok(text) => toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error)),
+		Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+		Err(error) => Err(format!("{}: {}", path.display(), error)),
+	}
+}
+
+/// Loads the config file, keeping the defaults if it is invalid
+pub(crate) fn load() {
+	match read() {
+		Ok(settings) => replace(settings),
+		Err(error) => eprintln!("\x1b[3mkennsh: The config file could not be loaded:\x1b[0m {}", error),
+	}
+}
+
+/// The settings as loaded and changed with ~config, without the overrides
+pub(crate) fn configured() -> Settings {
+	SETTINGS.lock().unwrap().clone().unwrap_or_default()
+}
+
+/// The settings in effect, with the environment variables overriding them
+pub(crate) fn get() -> Settings {
+	let mut settings = configured();
+	for (key, value) in settings.switches_mut() {
+		if is_overridden(key) {
+			*value = env_is_true(key);
+		}
+	}
+	if let Some(file) = std::env::var_os("trace_file") {
+		settings.trace_file = file.to_string_lossy().into_owned();
+	}
+	settings
+}
+
+/// Whether an environment variable overrides the setting called key
+pub(crate) fn is_overridden(key: &str) -> bool {
+	std::env::var_os(key).is_some()
+}
+
+pub(crate) fn replace(settings: Settings) {
+	*SETTINGS.lock().unwrap() = Some(settings);
+	crate::trace::refresh(&get());
+}
+
+/// Takes a change of the variable called key into account, which overrides
+/// the setting called like it if there is one
+pub(crate) fn variable_changed(key: &str) {
+	let mut settings = Settings::default();
+	if key == "trace_file" || settings.switches_mut().iter().any(|(name, _)| *name == key) {
+		crate::trace::refresh(&get());
+	}
+}
+
+/// Changes the settings for this process, like for a subcommand
+pub(crate) fn update(change: impl FnOnce(&mut Settings)) {
+	let mut settings = configured();
+	change(&mut settings);
+	replace(settings);
+}
+
+#[cfg(test)]
+mod tests;
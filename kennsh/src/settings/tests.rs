@@ -0,0 +1,36 @@
+use super::*;
+
+#[test]
+fn reads_settings() {
+	let settings: Settings = toml::from_str("who_is_running = true\nno_title = false\n").unwrap();
+	assert_eq!(settings, Settings { who_is_running: true, ..Settings::default() });
+	assert_eq!(toml::from_str::<Settings>("").unwrap(), Settings::default());
+}
+
+#[test]
+fn rejects_invalid_settings() {
+	let error = toml::from_str::<Settings>("who_is_runing = true").unwrap_err().to_string();
+	assert!(error.contains("unknown field `who_is_runing`"), "{}", error);
+	assert!(toml::from_str::<Settings>("no_title = \"yes\"").is_err());
+}
+
+#[test]
+fn changes_settings_by_name() {
+	let settings = Settings::default();
+	assert_eq!(settings.with("trace_file", "/tmp/trace").unwrap().trace_file, "/tmp/trace");
+	assert_eq!(settings.with("trace_file", "\"a b\"").unwrap().trace_file, "a b");
+	assert_eq!(settings.with("socket_debug", "true"), Ok(Settings { socket_debug: true, ..Settings::default() }));
+	assert!(settings.with("socket", "true").unwrap_err().contains("no setting called socket"));
+	assert!(settings.with("socket_debug", "yes").is_err());
+	assert!(settings.with("socket_debug", "1").is_err());
+}
+
+#[test]
+fn lists_every_setting() {
+	let mut settings = Settings::default();
+	let table = settings.to_table();
+	let mut names: Vec<_> = settings.switches_mut().iter().map(|(name, _)| *name).collect();
+	names.push("trace_file");
+	assert_eq!(table.len(), names.len());
+	assert!(names.iter().all(|name| table.contains_key(*name)));
+}
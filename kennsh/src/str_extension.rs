@@ -1,6 +1,5 @@
 pub trait StringExtensions {
 	fn is_blank(&self) -> bool;
-	fn split_at_multiple<'a, Positions: AsRef<[usize]>>(&'a self, positions: &'a Positions) -> SplitAt<'a>;
 	// fn as_utf8<'a>(&'a self) -> &'a dyn Iterator<Item = char>;
 }
 
@@ -9,14 +8,6 @@ impl StringExtensions for String {
         self.as_str().is_blank()
 	}
 
-	fn split_at_multiple<'a, Positions: AsRef<[usize]>>(&'a self, positions: &'a Positions) -> SplitAt<'a> {
-		SplitAt {
-			string: self,
-			positions: positions.as_ref(),
-			current_index: 0,
-		}
-	}
-
 	// fn as_utf8<'a>(&'a self) -> &'a dyn Iterator<Item = char> {
 	// 	&self.as_bytes().into_iter().map(|b| char::from(*b))
 	// }
@@ -32,14 +23,6 @@ impl StringExtensions for &str {
 		true
 	}
 
-	fn split_at_multiple<'a, Positions: AsRef<[usize]>>(&'a self, positions: &'a Positions) -> SplitAt<'a> {
-		SplitAt {
-			string: self,
-			positions: positions.as_ref(),
-			current_index: 0,
-		}
-	}
-
 	// fn as_utf8<'a>(&'a self) -> &'a dyn Iterator<Item = char> {
 	// 	let bytes = self.as_bytes();
 	// 	let it = bytes.into_iter();
@@ -47,28 +30,3 @@ impl StringExtensions for &str {
 	// 	map.into_iter()
 	// }
 }
-
-pub struct SplitAt<'a> {
-	string: &'a str,
-	positions: &'a [usize],
-	current_index: usize,
-}
-
-impl <'a> Iterator for SplitAt<'a> {
-    type Item = &'a str;
-
-    fn next(&mut self) -> Option<Self::Item> {
-		if self.current_index > self.positions.len() {
-			None
-		}
-		else {
-			Some({
-				let previous_position = if self.current_index == 0 { 0 } else { self.positions[self.current_index - 1] + 1 };
-				let current_position = if self.current_index == self.positions.len() { self.string.len() } else { self.positions[self.current_index] };
-				let current_slice = &self.string[previous_position..current_position];
-				self.current_index += 1;
-				current_slice
-			})
-		}
-    }
-}
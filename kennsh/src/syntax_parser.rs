@@ -1,468 +1,965 @@
 use std::fmt::Display;
 use serde::{Deserialize, Serialize};
 
-use crate::str_extension::StringExtensions;
+use crate::math::Expression;
+
+mod lexer;
+#[cfg(test)]
+mod tests;
+use lexer::{RedirectionOperator, Token, TokenKind};
 
 static ESCAPE_CHAR: char = '`';
 static PIPE_CHAR: char = '|';
 static STRING_CHARS: [char; 2] = ['"', '\''];
+// Characters that start an expansion and have to be taken literally when escaped
+static EXPANSION_CHARS: [char; 5] = ['*', '?', '[', '{', '~'];
+
+// Characters that have to be escaped to be taken literally outside of strings,
+// besides whitespace
+static SPECIAL_CHARS: [char; 9] = [';', PIPE_CHAR, '<', '>', '"', '\'', '(', ')', '$'];
+
+// Words that open a block, which has to be closed by end
+static BLOCK_KEYWORDS: [&str; 5] = ["if", "for", "while", "switch", "function"];
+// Words that only have a meaning inside of a block
+static INNER_KEYWORDS: [&str; 3] = ["else", "end", "case"];
+
+/// Parses input, recovering from errors to report all of them at once,
+/// ordered by where they are
+pub(crate) fn parse(input: String) -> Result<SyntaxTree, Vec<ParseError>> {
+	let lexed = lexer::lex(&input, 0);
+	let mut parser = Parser::new(&input, 0, lexed.tokens);
+	parser.errors = lexed.errors;
+	let tree = parser.parse_script();
+	if parser.errors.is_empty() {
+		Ok(tree)
+	}
+	else {
+		let mut errors = parser.errors;
+		errors.sort_by_key(|error| (error.start_index, error.end_index));
+		Err(errors)
+	}
+}
 
-pub(crate) fn parse(input: String) -> Result<SyntaxTree, ParseError> {
-	parse_pipe_chain(0, &input)
+/// The spans of the comments in input, each from its # up to the end of
+/// its line
+pub(crate) fn comments(input: &str) -> Vec<Span> {
+	lexer::lex(input, 0).comments
 }
 
-fn parse_pipe_chain(_start_index: usize, input: &str) -> Result<SyntaxTree, ParseError> {
-	let mut _starting_from = 0;
-	let mut pipe_char_vec = vec![];
+/// Whether input ends inside of a block, a string or a subcommand, or with
+/// an escaped newline, so the line editor should ask for more
+pub(crate) fn is_incomplete(input: &str) -> bool {
+	let trailing_escapes = input.chars().rev().take_while(|c| *c == ESCAPE_CHAR).count();
+	if trailing_escapes % 2 == 1 {
+		return true;
+	}
+	let lexed = lexer::lex(input, 0);
+	if lexed.unclosed {
+		return true;
+	}
+	let tokens = lexed.tokens;
 
-	let mut skip_next = false;	// If backtick, skip the next char, as it is escaped
-	let mut skip_char = None;
-	for (index, c) in input.as_bytes().iter().map(|c| char::from(*c)).enumerate() {
-		if skip_next {
-			skip_next = false;
-		}
-		else if let Some(sc) = skip_char {
-			if c == sc {
-				skip_char = None;
+	let mut depth = 0;
+	let mut subcommand_depth = 0;
+	let mut statement_start = true;
+	for (index, token) in tokens.iter().enumerate() {
+		let single_word = !tokens.get(index + 1).map_or(false, |next| next.joined && next.kind.starts_word());
+		match &token.kind {
+			TokenKind::SubcommandStart { .. } => subcommand_depth += 1,
+			TokenKind::SubcommandEnd => subcommand_depth -= 1,
+			TokenKind::Text(word) if statement_start && subcommand_depth == 0 && single_word => {
+				if BLOCK_KEYWORDS.contains(&word.as_str()) {
+					depth += 1;
+				}
+				else if word == "end" {
+					depth -= 1;
+				}
 			}
+			_ => {}
 		}
-		else if c == ESCAPE_CHAR {
-			skip_next = true;
+		statement_start = matches!(token.kind, TokenKind::Separator);
+	}
+	depth > 0
+}
+
+/// Parses a single command with its redirections, without pipes or
+/// several statements
+pub(crate) fn parse_command(start_index: usize, input: &str) -> Result<CommandSyntax, ParseError> {
+	let lexed = lexer::lex(input, start_index);
+	if let Some(error) = lexed.errors.into_iter().next() {
+		return Err(error);
+	}
+	let mut parser = Parser::new(input, start_index, lexed.tokens);
+	let command = parser.parse_command()?;
+	if !parser.errors.is_empty() {
+		return Err(parser.errors.remove(0));
+	}
+	if parser.peek().is_some() {
+		return Err(ParseError::new(
+			Span::new(parser.here().start, start_index + input.len()),
+			"Expected a single command",
+		));
+	}
+	Ok(command)
+}
+
+/// Parses input as a single word, in which whitespace is taken literally
+pub(crate) fn parse_command_item(start_index: usize, input: &str) -> Result<CommandItem, ParseError> {
+	let lexed = lexer::lex_word(input, start_index);
+	if let Some(error) = lexed.errors.into_iter().next() {
+		return Err(error);
+	}
+	if lexed.tokens.is_empty() {
+		return Ok(CommandItem::new(CommandItemKind::Combination(vec![]), Span::new(start_index, start_index)));
+	}
+	let mut parser = Parser::new(input, start_index, lexed.tokens);
+	let item = parser.parse_word()?;
+	if !parser.errors.is_empty() {
+		return Err(parser.errors.remove(0));
+	}
+	if parser.peek().is_some() {
+		return Err(ParseError::new(
+			Span::new(parser.here().start, start_index + input.len()),
+			"Expected a single word",
+		));
+	}
+	Ok(item)
+}
+
+/// A redirection that has not been applied to its command yet
+struct Redirection {
+	file_descriptor: Option<i32>,
+	operator: RedirectionOperator,
+	target: String,
+	span: Span,
+}
+
+/// Wraps command into its redirections, the first one innermost
+fn wrap_redirections(command: CommandSyntax, redirections: Vec<Redirection>) -> CommandSyntax {
+	redirections.into_iter().fold(command, |command, redirection| {
+		let span = command.span.to(redirection.span);
+		let command = Box::new(command);
+		let kind = match redirection.operator {
+			RedirectionOperator::Input => CommandSyntaxKind::InputRedirection {
+				command,
+				file_descriptor: redirection.file_descriptor,
+				filename: redirection.target,
+			},
+			RedirectionOperator::Output(kind) => CommandSyntaxKind::OutputRedirection {
+				command,
+				file_descriptor: redirection.file_descriptor,
+				destination: redirection.target,
+				kind,
+			},
+		};
+		CommandSyntax::new(kind, span)
+	})
+}
+
+fn is_variable_name(name: &str) -> bool {
+	!name.is_empty()
+		&& !name.starts_with(|c: char| c.is_ascii_digit())
+		&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_function_name(name: &str) -> bool {
+	!name.is_empty()
+		&& !BLOCK_KEYWORDS.contains(&name)
+		&& !INNER_KEYWORDS.contains(&name)
+		&& name.chars().all(|c| c.is_alphanumeric() || ['_', '-', '.', '~', '+'].contains(&c))
+}
+
+/// A recursive descent parser over the tokens of the lexer
+struct Parser<'a> {
+	// The input, which starts at offset in the input spans refer to
+	source: &'a str,
+	offset: usize,
+	tokens: Vec<Token>,
+	position: usize,
+	// Number of loops around the current statement, for break and continue
+	loop_depth: usize,
+	// Number of function definitions around the current statement, for return
+	function_depth: usize,
+	// Errors found so far, parsing goes on after them
+	errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+	fn new(source: &'a str, offset: usize, tokens: Vec<Token>) -> Self {
+		Parser {
+			source,
+			offset,
+			tokens,
+			position: 0,
+			loop_depth: 0,
+			function_depth: 0,
+			errors: vec![],
 		}
-		// Skip pipe character if part of the >| sequence
-		else if c == '>' {
-			skip_next = true;
+	}
+
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position)
+	}
+
+	fn peek_kind(&self) -> Option<&TokenKind> {
+		self.peek().map(|token| &token.kind)
+	}
+
+	fn advance(&mut self) -> Token {
+		let token = self.tokens[self.position].clone();
+		self.position += 1;
+		token
+	}
+
+	/// The input covered by span
+	fn text(&self, span: Span) -> &'a str {
+		&self.source[span.start - self.offset..span.end - self.offset]
+	}
+
+	/// An empty span where the next token starts, or at the end of the input
+	fn here(&self) -> Span {
+		let start = self.peek().map_or(self.offset + self.source.len(), |token| token.span.start);
+		Span::new(start, start)
+	}
+
+	/// The span of the next token, or a single character at the end of the
+	/// statement
+	fn next_span(&self) -> Span {
+		match self.peek() {
+			Some(token) if !self.at_statement_end() => token.span,
+			_ => Span::new(self.here().start, self.here().start + 1),
 		}
-		else if STRING_CHARS.contains(&c) {
-			skip_char = Some(c);
+	}
+
+	/// The span from the next token up to the end of the statement
+	fn rest_of_statement(&self) -> Span {
+		let start = self.here().start;
+		let mut end = start;
+		let mut depth = 0;
+		for token in &self.tokens[self.position..] {
+			match token.kind {
+				TokenKind::SubcommandStart { .. } => depth += 1,
+				TokenKind::SubcommandEnd | TokenKind::Separator if depth == 0 => break,
+				TokenKind::SubcommandEnd => depth -= 1,
+				_ => {}
+			}
+			end = token.span.end;
 		}
-		else if c == PIPE_CHAR {
-			pipe_char_vec.push(index);
+		Span::new(start, end)
+	}
+
+	fn at_statement_end(&self) -> bool {
+		matches!(self.peek_kind(), None | Some(TokenKind::Separator) | Some(TokenKind::SubcommandEnd))
+	}
+
+	fn at_word(&self) -> bool {
+		self.peek_kind().map_or(false, TokenKind::starts_word)
+	}
+
+	/// Whether the token at position continues the word before it
+	fn continues_word(&self, position: usize) -> bool {
+		self.tokens.get(position).map_or(false, |token| token.joined && token.kind.starts_word())
+	}
+
+	/// The next word, if it is made of nothing but unquoted text
+	fn plain_word(&self) -> Option<(String, Span)> {
+		match self.peek() {
+			Some(Token { kind: TokenKind::Text(text), span, .. }) if !self.continues_word(self.position + 1) => {
+				Some((text.clone(), *span))
+			}
+			_ => None,
 		}
-	};
+	}
 
-	if pipe_char_vec.is_empty() {
-		parse_command(0, input).map(|cs| {
-			SyntaxTree::Command(cs)
-		})
+	fn keyword(&self) -> Option<(&'static str, Span)> {
+		let (word, span) = self.plain_word()?;
+		BLOCK_KEYWORDS.iter()
+			.chain(INNER_KEYWORDS.iter())
+			.find(|keyword| **keyword == word)
+			.map(|keyword| (*keyword, span))
 	}
-	else {
-		let mut length = 0;
-		let mut syntaxes = vec![];
-		for command in input.split_at_multiple(&pipe_char_vec) {
-			let trimmed = command.trim();
-			let start_index = length + command.chars().take_while(|c| c.is_whitespace()).collect::<Vec<_>>().len();
 
-			let syntax = parse_command(start_index, trimmed)?;
-			syntaxes.push(syntax);
+	/// A sequence of trees, spanning all of them
+	fn sequence(&self, trees: Vec<SyntaxTree>) -> SyntaxTree {
+		let span = match (trees.first(), trees.last()) {
+			(Some(first), Some(last)) => first.span.to(last.span),
+			_ => self.here(),
+		};
+		SyntaxTree::new(SyntaxTreeKind::Sequence(trees), span)
+	}
 
-			length += command.len() + 1;
+	fn single_or_sequence(&self, mut trees: Vec<SyntaxTree>) -> SyntaxTree {
+		if trees.len() == 1 {
+			trees.remove(0)
+		}
+		else {
+			self.sequence(trees)
 		}
-		Ok(SyntaxTree::PipeChain(syntaxes))
 	}
-}
 
-fn parse_command(start_index: usize, input: &str) -> Result<CommandSyntax, ParseError> {
-	if input.is_empty() {
-		return Err(ParseError {
-			start_index,
-			end_index: start_index + 1,
-			reason: "An empty command was given".to_owned(),
-		});
+	fn parse_script(&mut self) -> SyntaxTree {
+		let (trees, _) = self.parse_sequence(&[]);
+		self.single_or_sequence(trees)
 	}
 
-	let splitted = {
-		let mut result = vec![];
-		let mut starting_index = 0;
-		let mut buffer = String::new();
-		let mut skip_next_char = false;
-		let mut skip_char: Option<char> = None;
-		for (index, c) in input.bytes().into_iter().map(|c| char::from(c)).enumerate() {
-			if skip_next_char {
-				skip_next_char = false;
-				buffer += &c.to_string();
+	/// Parses statements up to one starting with any of the terminators,
+	/// returning them together with the terminating keyword. If the input or
+	/// the subcommand ends first, there is no terminating keyword. Statements
+	/// with errors are left out after their errors have been recorded.
+	fn parse_sequence(&mut self, terminators: &[&str]) -> (Vec<SyntaxTree>, Option<(&'static str, Span)>) {
+		let mut trees = vec![];
+		loop {
+			while let Some(TokenKind::Separator) = self.peek_kind() {
+				self.position += 1;
+			}
+			if self.at_statement_end() {
+				return (trees, None);
 			}
-			else if let Some(sc) = skip_char {
-				if sc == c || (sc == '(' && c == ')') {
-					skip_char = None;
+			match self.keyword() {
+				Some((keyword, span)) if terminators.contains(&keyword) => {
+					self.position += 1;
+					return (trees, Some((keyword, span)));
 				}
-				buffer += &c.to_string();
+				_ => {}
 			}
-			else if c == ESCAPE_CHAR {
-				skip_next_char = true;
-				buffer += &c.to_string();
+			match self.parse_statement() {
+				Ok(tree) => trees.push(tree),
+				Err(error) => self.recover(error),
 			}
-			else if STRING_CHARS.contains(&c) || c == '(' {
-				// Incorporate strings and subcommands
-				skip_char = Some(c);
-				buffer += &c.to_string();
+		}
+	}
+
+	fn parse_statement(&mut self) -> Result<SyntaxTree, ParseError> {
+		if let Some((keyword, span)) = self.keyword() {
+			self.position += 1;
+			let error = ParseError::new(span, format!("Found {} outside of a block it belongs to", keyword));
+			return match keyword {
+				"if" => Ok(self.parse_if(span)),
+				"for" => Ok(self.parse_for(span)),
+				"while" => Ok(self.parse_while(span)),
+				"switch" => Ok(self.parse_switch(span)),
+				"function" => Ok(self.parse_function(span)),
+				"end" => Err(error.with_help("remove it, as every block before it is already closed")),
+				"else" => Err(error.with_note("else can only be used inside of an if block")),
+				_ => Err(error.with_note("case can only be used inside of a switch block")),
+			};
+		}
+		match self.plain_word() {
+			Some((word, span)) if self.loop_depth == 0 && (word == "break" || word == "continue") => {
+				let error = ParseError::new(span, format!("{} can only be used inside of a loop", word));
+				if self.function_depth > 0 {
+					return Err(error.with_note("a function body can't control the loops around its definition"));
+				}
+				Err(error)
 			}
-			else if c == ' ' {
-				// Separate words by space
-				result.push((starting_index, buffer));
-				buffer = String::new();
-				starting_index = index + 1;
+			Some((word, span)) if self.function_depth == 0 && word == "return" => {
+				Err(ParseError::new(span, "return can only be used inside of a function"))
 			}
-			else {
-				buffer += &c.to_string();
+			_ => self.parse_pipeline(),
+		}
+	}
+
+	/// Records error and skips the rest of the statement it was found in, so
+	/// parsing can go on with the next one
+	fn recover(&mut self, error: ParseError) {
+		self.errors.push(error);
+		let mut depth = 0;
+		while let Some(kind) = self.peek_kind() {
+			match kind {
+				TokenKind::Separator | TokenKind::SubcommandEnd if depth == 0 => break,
+				TokenKind::SubcommandStart { .. } => depth += 1,
+				TokenKind::SubcommandEnd => depth -= 1,
+				_ => {}
 			}
+			self.position += 1;
 		}
-		if !buffer.is_empty() {
-			result.push((starting_index, buffer));
+	}
+
+	/// The value of result, or a default after recovering from its error
+	fn recovered<T: Default>(&mut self, result: Result<T, ParseError>) -> T {
+		result.unwrap_or_else(|error| {
+			self.recover(error);
+			T::default()
+		})
+	}
+
+	fn parse_pipeline(&mut self) -> Result<SyntaxTree, ParseError> {
+		let mut commands = vec![self.parse_command()?];
+		while let Some(TokenKind::Pipe) = self.peek_kind() {
+			self.position += 1;
+			commands.push(self.parse_command()?);
 		}
-		result
-	};
+		let span = commands[0].span.to(commands[commands.len() - 1].span);
+		if commands.len() == 1 {
+			Ok(SyntaxTree::new(SyntaxTreeKind::Command(commands.remove(0)), span))
+		}
+		else {
+			Ok(SyntaxTree::new(SyntaxTreeKind::PipeChain(commands), span))
+		}
+	}
 
-	let mut command_parts = vec![];
-	let mut command_syntax = CommandSyntax::Command(vec![]);
-	// Process input and stuff
-	let mut iter = splitted.into_iter();
-	'item_loop: while let Some((index, item)) = iter.next() {
-		let mut skip_next_char = false;
-		let mut skip_char = None;
-		let utf8_item = item
-			.as_bytes()
-			.into_iter()
-    		.map(|u| char::from(*u))
-			.collect::<Vec<_>>();
-		for (i, c) in utf8_item.into_iter().enumerate() {
-			if skip_next_char {
-				skip_next_char = false
-			}
-			else if c == ESCAPE_CHAR {
-				skip_next_char = true
-			}
-			else if let Some(sc) = skip_char {
-				if c == sc {
-					skip_char = None;
-				}
-			}
-			else if STRING_CHARS.contains(&c) {
-				skip_char = Some(c);
-			}
-			else if c == '<' {
-				let fd_part = &item[..i];
-				let file_part = &item[i+1..];
-				let fd = match fd_part {
-					"" => None,
-					"0" => Some(0),
-					_ => return Err(ParseError {
-						start_index: index,
-						end_index: index + i,
-						reason: "Only 0 or the default file descriptor (empty) are supported".to_owned(), 
-					}),
-				};
-				// Check if filename is in the next item
-				let filename = if file_part.is_empty() {
-					if let Some(x) = iter.next() {
-						x
-					}
-					else {
-						return Err(ParseError {
-							start_index: index + i + 1,
-							end_index: index + i + 2,
-							reason: "No file for redirection was given".to_owned(),
-						})
-					}
-				} else { (i+1, file_part.to_owned()) };
-				// Check if filename is a file descriptor
-				// In case of input piping, don't accept any file descriptor yet
-				// TODO: Implement file descriptor management
-				if filename.1.chars().nth(0).unwrap() == '&' {
-					return Err(ParseError {
-						start_index: filename.0,
-						end_index: filename.0 + filename.1.len(),
-						reason: "Only files are accepted at the moment (perhaps escape & with `?)".to_owned(),
-					})
-				}
-				// Change the syntax to an input redirection one
-				command_syntax = CommandSyntax::InputRedirection {
-					command: Box::from(command_syntax),
-					file_descriptor: fd,
-					filename: filename.1.to_owned(),
-				};
-				continue 'item_loop;
-			}
-			else if c == '>' {
-				let fd_part = &item[..i];
-				// eprintln!("fd_part: {}; len {}", fd_part, fd_part.len());
-				let file_part = item[i+1..].to_owned();
-				let mut file_part = file_part.trim();
-				// eprintln!("file_part: {}; len {}", file_part, file_part.len());
-				let fd = match fd_part {
-					"" => None,
-					"1" => Some(1),
-					"2" => Some(2),
-					_ => return Err(ParseError {
-						start_index: index,
-						end_index: index + i,
-						reason: "Only 1, 2 or the default file descriptor (empty) are supported".to_owned(), 
-					}),
-				};
-				// Check the kind of the redirection
-				let kind = if file_part.is_empty() {
-					OutputRedirectionKind::Create
+	fn parse_command(&mut self) -> Result<CommandSyntax, ParseError> {
+		let start = self.here();
+		let mut items = vec![];
+		let mut redirections = vec![];
+		loop {
+			match self.peek_kind() {
+				None | Some(TokenKind::Separator) | Some(TokenKind::SubcommandEnd) | Some(TokenKind::Pipe) => break,
+				Some(TokenKind::Redirection { .. }) => redirections.push(self.parse_redirection()?),
+				Some(_) => items.push(self.parse_word()?),
+			}
+		}
+		if items.is_empty() && redirections.is_empty() {
+			return Err(ParseError::new(Span::new(start.start, start.start + 1), "An empty command was given"));
+		}
+		let span = match (items.first(), items.last()) {
+			(Some(first), Some(last)) => first.span.to(last.span),
+			_ => start,
+		};
+		Ok(wrap_redirections(CommandSyntax::new(CommandSyntaxKind::Command(items), span), redirections))
+	}
+
+	fn parse_redirection(&mut self) -> Result<Redirection, ParseError> {
+		let token = self.advance();
+		let (file_descriptor, operator) = match token.kind {
+			TokenKind::Redirection { file_descriptor, operator } => (file_descriptor, operator),
+			_ => unreachable!("parse_redirection is only called at a redirection"),
+		};
+		let file_descriptor_span = Span::new(
+			token.span.start,
+			token.span.start + file_descriptor.as_ref().map_or(0, String::len),
+		);
+		let file_descriptor = match (operator, file_descriptor.as_deref()) {
+			(_, None) => None,
+			(RedirectionOperator::Input, Some("0")) => Some(0),
+			(RedirectionOperator::Input, Some(_)) => return Err(ParseError::new(
+				file_descriptor_span,
+				"Only 0 or the default file descriptor (empty) are supported",
+			)),
+			(RedirectionOperator::Output(_), Some("1")) => Some(1),
+			(RedirectionOperator::Output(_), Some("2")) => Some(2),
+			(RedirectionOperator::Output(_), Some(_)) => return Err(ParseError::new(
+				file_descriptor_span,
+				"Only 1, 2 or the default file descriptor (empty) are supported",
+			)),
+		};
+
+		if !self.at_word() {
+			return Err(ParseError::new(
+				Span::new(token.span.end, token.span.end + 1),
+				"No file for redirection was given",
+			));
+		}
+		// The target is evaluated when the redirection is opened
+		let target = self.parse_word()?;
+		let target_text = self.text(target.span).to_owned();
+		// Redirecting into another file descriptor: input redirections don't
+		// accept any yet, output redirections only from 1 to 2 or from 2 to 1
+		// TODO: Implement file descriptor management
+		if let Some(target_file_descriptor) = target_text.strip_prefix('&') {
+			match operator {
+				RedirectionOperator::Input => return Err(ParseError::new(
+					target.span,
+					"Only files are accepted at the moment (perhaps escape & with `?)",
+				)),
+				RedirectionOperator::Output(_) => match target_file_descriptor.parse::<i32>() {
+					Ok(1) | Ok(2) => {}
+					Ok(_) => return Err(ParseError::new(
+						target.span,
+						"For now, only file descriptors 1 (stdout) and 2 (stderr) are supported",
+					)),
+					Err(_) => return Err(ParseError::new(
+						Span::new(target.span.start + 1, target.span.end),
+						"Could not convert into an integer file descriptor",
+					)),
+				},
+			}
+		}
+		Ok(Redirection {
+			file_descriptor,
+			operator,
+			target: target_text,
+			span: token.span.to(target.span),
+		})
+	}
+
+	/// Parses the tokens of a single word into a combination of its parts
+	fn parse_word(&mut self) -> Result<CommandItem, ParseError> {
+		let start = self.here().start;
+		let mut end;
+		let mut parts = vec![];
+		loop {
+			let token = self.advance();
+			let mut span = token.span;
+			let kind = match token.kind {
+				TokenKind::Text(text) => CommandItemKind::RawString(text),
+				TokenKind::EscapedChar(c) => CommandItemKind::String(c.to_string()),
+				TokenKind::Variable(name) => CommandItemKind::ShellVariable(name),
+				TokenKind::IndexedVariable { name, index } => {
+					let (index, _) = parse_list_index(span.start + 1 + name.len(), &index)?;
+					CommandItemKind::IndexedVariable { name, index }
 				}
-				else {
-					match file_part.chars().nth(0).unwrap() {
-						'|' => {
-							file_part = &file_part[1..];
-							OutputRedirectionKind::Overwrite
-						},
-						'>' => {
-							file_part = &file_part[1..];
-							OutputRedirectionKind::Append
-						},
-						_ => OutputRedirectionKind::Create,
-					}
-				};
-				// Check if filename is in the next item
-				let filename = if file_part.is_empty() {
-					if let Some(x) = iter.next() {
-						// eprintln!("iter.next(): {}; len {}", x.1, x.1.len());
-						(x.0, x.1.to_owned())
-					}
-					else {
-						return Err(ParseError {
-							start_index: index + i + 1,
-							end_index: index + i + 2,
-							reason: "No file for redirection was given".to_owned(),
-						})
-					}
-				} else { (i+1, file_part.to_owned()) };
-				// Check if redirecting into another file descriptor
-				// In case of output piping, accept only piping from 1 to 2
-				// or from 2 to 1
-				// TODO: Implement file descriptor management
-				if filename.1.chars().nth(0).unwrap() == '&' {
-					match filename.1[1..].parse::<i32>() {
-						Ok(1) | Ok(2) => {},
-						Ok(_) => {
-							return Err(ParseError {
-								start_index: filename.0,
-								end_index: filename.0 + filename.1.len(),
-								reason: "For now, only file descriptors 1 (stdout) and 2 (stderr) are supported".to_owned(),			
-							});
-						},
-						Err(_) => {
-							return Err(ParseError {
-								start_index: filename.0 + 1,
-								end_index: filename.0 + filename.1.len(),
-								reason: "Could not convert into an integer file descriptor".to_owned(),
-							});
-						}
+				TokenKind::VariableExpansion(content) => parse_variable_expansion(span.start + 2, &content)?,
+				TokenKind::ArithmeticExpansion(source) => parse_arithmetic_expansion(span.start + 2, &source)?,
+				TokenKind::BraceExpansion(items) => self.parse_brace_expansion(span, items)?,
+				TokenKind::Quote(_) => {
+					let (mut string, string_span) = self.parse_string(span)?;
+					end = string_span.end;
+					parts.append(&mut string);
+					if !self.continues_word(self.position) {
+						break;
 					}
+					continue;
+				}
+				TokenKind::SubcommandStart { .. } => {
+					let (tree, subcommand_span) = self.parse_subcommand(span)?;
+					span = subcommand_span;
+					CommandItemKind::Subcommand(Box::new(tree))
 				}
-				
-				command_syntax = CommandSyntax::OutputRedirection {
-					command: Box::from(command_syntax),
-					file_descriptor: fd,
-					destination: filename.1.to_owned(),
-					kind,
-				};
-				continue 'item_loop;
+				kind => unreachable!("{:?} can't start a word", kind),
+			};
+			end = span.end;
+			parts.push(CommandItem::new(kind, span));
+			if !self.continues_word(self.position) {
+				break;
 			}
 		}
-		command_parts.push(parse_command_item(index, &item)?);
+		let span = Span::new(start, end);
+		let mut item = CommandItem::new(CommandItemKind::Combination(parts), span).normalize();
+		item.span = span;
+		Ok(item)
 	}
-	command_syntax = command_syntax.inject_and_replace(command_parts);
-	Ok(command_syntax)
-}
 
-fn parse_command_item(start_index: usize, input: &str) -> Result<CommandItem, ParseError> {
-	let mut result = vec![];
+	/// Parses the inside of a string whose opening quote has been read,
+	/// returning its parts together with its span including the quotes
+	fn parse_string(&mut self, opening: Span) -> Result<(Vec<CommandItem>, Span), ParseError> {
+		let mut parts = vec![];
+		loop {
+			let token = self.advance();
+			match token.kind {
+				TokenKind::QuotedText(text) => parts.push(CommandItem::new(CommandItemKind::String(text), token.span)),
+				TokenKind::SubcommandStart { .. } => {
+					let (tree, span) = self.parse_subcommand(token.span)?;
+					parts.push(CommandItem::new(CommandItemKind::QuotedSubcommand(Box::new(tree)), span));
+				}
+				TokenKind::ArithmeticExpansion(source) => {
+					let kind = parse_arithmetic_expansion(token.span.start + 2, &source)?;
+					parts.push(CommandItem::new(kind, token.span));
+				}
+				TokenKind::Quote(_) => {
+					let span = opening.to(token.span);
+					match parts.len() {
+						0 => parts.push(CommandItem::new(CommandItemKind::String(String::new()), span)),
+						1 => parts[0].span = span,
+						_ => {}
+					}
+					return Ok((parts, span));
+				}
+				kind => unreachable!("{:?} can't be inside of a string", kind),
+			}
+		}
+	}
 
-	// asdab"test"$status(echo meow)
-	// |    |     |      ^ subcommand
-	// |    |     ^ environment variable
-	// |    ^ string
-	// ^ raw string
+	/// Parses a subcommand whose opening parenthesis has been read, returning
+	/// it together with its span including the parentheses
+	fn parse_subcommand(&mut self, opening: Span) -> Result<(SyntaxTree, Span), ParseError> {
+		// A subcommand runs on its own, outside of loops and functions
+		let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+		let function_depth = std::mem::replace(&mut self.function_depth, 0);
+		let errors = self.errors.len();
+		let (trees, _) = self.parse_sequence(&[]);
+		self.loop_depth = loop_depth;
+		self.function_depth = function_depth;
 
-	enum CurrentlyFilling {
-		RawString,
-		String(char),
-		Subcommand,
-		ShellVariable,
+		// The lexer makes sure that every subcommand is closed, with an empty
+		// closing token and an error if the input ends first
+		let closing = self.advance();
+		let span = opening.to(closing.span);
+		if trees.is_empty() && self.errors.len() == errors && !closing.span.is_empty() {
+			return Err(ParseError::new(span, "An empty command was given"));
+		}
+		Ok((self.single_or_sequence(trees), span))
 	}
 
-	let mut currently_filling = CurrentlyFilling::RawString;
-	let mut subcommand_recursivity_count = 0;
-	let mut buffer = String::new();
-
-	let char_iter = input
-		.as_bytes()
-		.into_iter()
-		.map(|b| char::from(*b));
+	fn parse_brace_expansion(&self, span: Span, items: Vec<Span>) -> Result<CommandItemKind, ParseError> {
+		// The lexer only makes a single item out of valid ranges
+		if let [range] = items[..] {
+			return parse_brace_range(self.text(range))
+				.map(CommandItemKind::BraceExpansion)
+				.ok_or_else(|| ParseError::new(span, "Expected a range like 1..10 or a..e"));
+		}
+		let items = items.into_iter()
+			.map(|item| parse_command_item(item.start, self.text(item)))
+			.collect::<Result<_, _>>()?;
+		Ok(CommandItemKind::BraceExpansion(BraceExpansion::List(items)))
+	}
 
-	let mut next_char_escaped = false;
+	fn parse_condition(&mut self, keyword: &str, span: Span) -> SyntaxTree {
+		if self.at_statement_end() {
+			self.errors.push(ParseError::new(span, format!("Expected a command after {}", keyword)));
+			return self.sequence(vec![]);
+		}
+		match self.parse_pipeline() {
+			Ok(condition) => condition,
+			Err(error) => {
+				self.recover(error);
+				self.sequence(vec![])
+			}
+		}
+	}
 
-	for (i, c) in char_iter.enumerate() {
-		if next_char_escaped {
-			let new_c = match c {
-				'n' => '\n',
-				'r' => '\r',
-				't' => '\t',
-				c => c,
-			};
-			buffer += &new_c.to_string();
-			next_char_escaped = false;
-		}
-		else if c == ESCAPE_CHAR {
-			next_char_escaped = true;
-		}
-		else if let CurrentlyFilling::Subcommand = currently_filling {
-			if c == ')' {
-				subcommand_recursivity_count -= 1;
-				if subcommand_recursivity_count == 0 {
-					result.push(
-						CommandItem::Subcommand(
-							Box::new(
-								parse_command(start_index + i - buffer.len(), &buffer)?
-							)
-						)
-					);
-					buffer = String::new();
-					currently_filling = CurrentlyFilling::RawString;
-				}
-				else {
-					buffer += &c.to_string();
+	/// Makes sure that the block opened at opening was closed by terminator,
+	/// applying the redirections following end to the whole block
+	fn close_block(&mut self, keyword: &str, opening: Span, terminator: Option<(&'static str, Span)>, kind: SyntaxTreeKind) -> SyntaxTree {
+		let end = match terminator {
+			Some((_, end)) => end,
+			None => {
+				let mut error = ParseError::new(opening, format!("This {} is never closed with end", keyword))
+					.with_help("add end after the last statement of the block");
+				if let Some(TokenKind::SubcommandEnd) = self.peek_kind() {
+					error = error.with_label(self.peek().unwrap().span, "the subcommand around it ends here");
 				}
+				self.errors.push(error);
+				return SyntaxTree::new(kind, opening);
 			}
-			else if c == '(' {
-				subcommand_recursivity_count += 1;
-				buffer += &c.to_string();
-			}
-			else {
-				buffer += &c.to_string();
+		};
+		let block = SyntaxTree::new(kind, opening.to(end));
+		if self.at_statement_end() {
+			return block;
+		}
+
+		let rest = self.rest_of_statement();
+		let mut redirections = vec![];
+		while !self.at_statement_end() {
+			let redirection = match self.peek_kind() {
+				Some(TokenKind::Redirection { .. }) => self.parse_redirection(),
+				_ => Err(ParseError::new(rest, "Only redirections like > file can follow end")),
+			};
+			match redirection {
+				Ok(redirection) => redirections.push(redirection),
+				Err(error) => self.recover(error),
 			}
 		}
-		else if STRING_CHARS.contains(&c) {
-			if let CurrentlyFilling::String(sc) = currently_filling {
-				if c == sc {
-					result.push(CommandItem::String(buffer));
-					buffer = String::new();
-					currently_filling = CurrentlyFilling::RawString;
-				}
-				else {
-					buffer += &c.to_string();
-				}
+		let command = CommandSyntax::new(CommandSyntaxKind::Command(vec![]), Span::new(end.end, end.end));
+		let redirection = wrap_redirections(command, redirections);
+		let span = block.span.to(redirection.span);
+		SyntaxTree::new(SyntaxTreeKind::RedirectedBlock {
+			block: Box::new(block),
+			redirection,
+		}, span)
+	}
+
+	fn parse_for(&mut self, for_span: Span) -> SyntaxTree {
+		let header = self.parse_for_header();
+		let (variable, items) = self.recovered(header);
+		let (body, terminator) = self.parse_loop_body();
+		let body = Box::new(self.sequence(body));
+		self.close_block("for", for_span, terminator, SyntaxTreeKind::For { variable, items, body })
+	}
+
+	/// Parses the variable and the words after for
+	fn parse_for_header(&mut self) -> Result<(String, Vec<CommandItem>), ParseError> {
+		let variable = match self.plain_word() {
+			Some((name, _)) if is_variable_name(&name) => name,
+			_ => return Err(ParseError::new(self.next_span(), "Expected a variable name after for")),
+		};
+		self.position += 1;
+		match self.plain_word() {
+			Some((word, _)) if word == "in" => self.position += 1,
+			_ => return Err(ParseError::new(self.next_span(), format!("Expected in after for {}", variable))),
+		}
+		let items = self.parse_words("the words of a for loop")?;
+		Ok((variable, items))
+	}
+
+	/// Parses the rest of a statement made only of words, like the ones
+	/// after for x in
+	fn parse_words(&mut self, description: &str) -> Result<Vec<CommandItem>, ParseError> {
+		let mut items = vec![];
+		while !self.at_statement_end() {
+			if !self.at_word() {
+				return Err(ParseError::new(
+					self.next_span(),
+					format!("Redirections and pipes are not allowed in {}", description),
+				));
 			}
-			else {
-				// No string started, start a new one
-				match currently_filling {
-					CurrentlyFilling::RawString => {
-						result.push(CommandItem::RawString(buffer));
-						buffer = String::new();
+			items.push(self.parse_word()?);
+		}
+		Ok(items)
+	}
+
+	/// Parses at least one word up to the end of the statement
+	fn parse_required_words(&mut self, keyword: &str, span: Span, description: &str) -> Vec<CommandItem> {
+		let words = self.parse_words(&format!("the {} of a {}", description, keyword))
+			.and_then(|words| match words.is_empty() {
+				true => Err(ParseError::new(span, format!("Expected a {} after {}", description, keyword))),
+				false => Ok(words),
+			});
+		self.recovered(words)
+	}
+
+	fn parse_switch(&mut self, switch_span: Span) -> SyntaxTree {
+		let value = self.parse_required_words("switch", switch_span, "value");
+		let (before_cases, mut terminator) = self.parse_sequence(&["case", "end"]);
+		if let (Some(first), Some(last)) = (before_cases.first(), before_cases.last()) {
+			self.errors.push(
+				ParseError::new(first.span.to(last.span), "Expected case right after switch")
+					.with_label(switch_span, "the switch starts here")
+					.with_help("put these statements into a case, or before the switch"),
+			);
+		}
+		let mut cases = vec![];
+		while let Some(("case", case_span)) = terminator {
+			let patterns = self.parse_required_words("case", case_span, "pattern");
+			let (body, next_terminator) = self.parse_sequence(&["case", "end"]);
+			cases.push((patterns, self.sequence(body)));
+			terminator = next_terminator;
+		}
+		self.close_block("switch", switch_span, terminator, SyntaxTreeKind::Switch { value, cases })
+	}
+
+	fn parse_loop_body(&mut self) -> (Vec<SyntaxTree>, Option<(&'static str, Span)>) {
+		self.loop_depth += 1;
+		let result = self.parse_sequence(&["end"]);
+		self.loop_depth -= 1;
+		result
+	}
+
+	fn parse_while(&mut self, while_span: Span) -> SyntaxTree {
+		let condition = Box::new(self.parse_condition("while", while_span));
+		let (body, terminator) = self.parse_loop_body();
+		let body = Box::new(self.sequence(body));
+		self.close_block("while", while_span, terminator, SyntaxTreeKind::While { condition, body })
+	}
+
+	fn parse_function(&mut self, function_span: Span) -> SyntaxTree {
+		let header = self.parse_function_header();
+		let name = self.recovered(header);
+
+		// Loops around the definition can't be broken out of from the body
+		let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+		self.function_depth += 1;
+		let (body, terminator) = self.parse_sequence(&["end"]);
+		self.function_depth -= 1;
+		self.loop_depth = loop_depth;
+
+		if terminator.is_some() && !self.at_statement_end() {
+			let error = ParseError::new(
+				self.rest_of_statement(),
+				"Redirections can't follow the end of a function; redirect its calls instead",
+			);
+			self.recover(error);
+		}
+		let body = Box::new(self.sequence(body));
+		self.close_block("function", function_span, terminator, SyntaxTreeKind::Function { name, body })
+	}
+
+	/// Parses the name after function, which has to end the line
+	fn parse_function_header(&mut self) -> Result<String, ParseError> {
+		let name = match self.plain_word() {
+			Some((name, _)) if is_function_name(&name) => name,
+			_ => return Err(ParseError::new(self.next_span(), "Expected a function name after function")),
+		};
+		self.position += 1;
+		if !self.at_statement_end() {
+			return Err(ParseError::new(
+				self.rest_of_statement(),
+				format!("Expected the end of the line after function {}", name),
+			));
+		}
+		Ok(name)
+	}
+
+	fn parse_if(&mut self, if_span: Span) -> SyntaxTree {
+		let mut branches = vec![];
+		let mut condition = self.parse_condition("if", if_span);
+		loop {
+			let (body, terminator) = self.parse_sequence(&["else", "end"]);
+			branches.push((condition, self.sequence(body)));
+			match terminator {
+				Some(("else", _)) => {
+					if let Some(("if", span)) = self.keyword() {
+						// else if
+						self.position += 1;
+						condition = self.parse_condition("if", span);
+						continue;
 					}
-					CurrentlyFilling::String(_) => {},
-					CurrentlyFilling::ShellVariable => {
-						result.push(CommandItem::ShellVariable(buffer));
-						buffer = String::new();
-					},
-					CurrentlyFilling::Subcommand => {
-						return Err(ParseError {
-							start_index: start_index + i,
-							end_index: start_index + i + i,
-							reason: "Found \" without closing parenthesis from the subcommand".to_owned(),
-						});
+					else if !self.at_statement_end() {
+						let error = ParseError::new(self.rest_of_statement(), "Expected if or the end of the line after else");
+						self.recover(error);
 					}
-				};
-				currently_filling = CurrentlyFilling::String(c);
-			}
-		}
-		else if let CurrentlyFilling::String(_) = currently_filling {
-			buffer += &c.to_string();
-		}
-		else if c == '(' {
-			subcommand_recursivity_count += 1;
-			match currently_filling {
-				CurrentlyFilling::Subcommand => {
-					return Err(ParseError {
-						start_index: start_index + i,
-						end_index: start_index + i + 1,
-						reason: "Subcommands within subcommands are not yet supported".to_owned(),
-					})
-				},
-				CurrentlyFilling::String(_) => {
-					panic!("syntax_parser.rs, parse_command_item, Impossible");
-				},
-				CurrentlyFilling::RawString => {
-					result.push(CommandItem::RawString(buffer));
-					buffer = String::new();
-					currently_filling = CurrentlyFilling::Subcommand;
-				},
-				CurrentlyFilling::ShellVariable => {
-					result.push(CommandItem::ShellVariable(buffer));
-					buffer = String::new();
-					currently_filling = CurrentlyFilling::Subcommand;
-				}
-			}
-		}
-		else if c == ')' {
-			if let CurrentlyFilling::Subcommand = currently_filling {
-				result.push(
-					CommandItem::Subcommand(
-						Box::new(
-							parse_command(start_index + i - buffer.len(), &buffer)?
-						)
-					)
-				);
-				buffer = String::new();
-				currently_filling = CurrentlyFilling::RawString;
-			}
-			else {
-				return Err(ParseError {
-					start_index: start_index + i,
-					end_index: start_index + i + i,
-					reason: "Found closing parenthesis without a previous open parenthesis".to_owned(),
-				});
-			}
-		}
-		else if c == '$' {
-			match currently_filling {
-				CurrentlyFilling::Subcommand => {
-					return Err(ParseError {
-						start_index: start_index + i,
-						end_index: start_index + i + 1,
-						reason: "Subcommands within subcommands are not yet supported".to_owned(),
-					})
-				},
-				CurrentlyFilling::String(_) => {
-					panic!("syntax_parser.rs, parse_command_item, Impossible");
-				},
-				CurrentlyFilling::RawString => {
-					result.push(CommandItem::RawString(buffer));
-					buffer = String::new();
-					currently_filling = CurrentlyFilling::ShellVariable;
-				},
-				CurrentlyFilling::ShellVariable => {
-					result.push(CommandItem::ShellVariable(buffer));
-					buffer = String::new();
-					currently_filling = CurrentlyFilling::ShellVariable;
+					let (body, terminator) = self.parse_sequence(&["end"]);
+					let otherwise = Some(Box::new(self.sequence(body)));
+					return self.close_block("if", if_span, terminator, SyntaxTreeKind::If { branches, otherwise });
+				}
+				terminator => {
+					return self.close_block("if", if_span, terminator, SyntaxTreeKind::If { branches, otherwise: None });
 				}
 			}
 		}
-		else {
-			buffer += &c.to_string();
-		}
+	}
+}
+
+/// Parses the content of a `${...}` variable expansion, which starts at
+/// content_index
+fn parse_variable_expansion(content_index: usize, content: &str) -> Result<CommandItemKind, ParseError> {
+	let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+	// ${#name}
+	if content.len() > 1 && content.starts_with('#') && content[1..].chars().all(is_name_char) {
+		return Ok(CommandItemKind::VariableExpansion {
+			name: content[1..].to_owned(),
+			modifier: VariableModifier::Length,
+		});
 	}
 
-	if !buffer.is_empty() {
-		match currently_filling {
-			CurrentlyFilling::RawString => result.push(CommandItem::RawString(buffer)),
-			CurrentlyFilling::String(_) => result.push(CommandItem::String(buffer)),
-			CurrentlyFilling::ShellVariable => result.push(CommandItem::ShellVariable(buffer)),
-			CurrentlyFilling::Subcommand => {
-				return Err(ParseError {
-					start_index: input.len(),
-					end_index: input.len(),
-					reason: "Found end of string instead of ) while reading subcommand".to_owned(),
-				})
+	let name_length = content.chars().take_while(|c| is_name_char(*c)).count();
+	if name_length == 0 {
+		return Err(ParseError::new(
+			Span::new(content_index, content_index + 1),
+			"Expected a variable name after ${",
+		));
+	}
+	let name = content[..name_length].to_owned();
+	let rest = &content[name_length..];
+	let rest_index = content_index + name_length;
+
+	let word = |offset: usize| parse_command_item(rest_index + offset, &rest[offset..]).map(Box::new);
+	let modifier = if rest.is_empty() {
+		return Ok(CommandItemKind::ShellVariable(name));
+	}
+	else if rest.starts_with(":-") {
+		VariableModifier::Default(word(2)?)
+	}
+	else if rest.starts_with(":+") {
+		VariableModifier::Alternative(word(2)?)
+	}
+	else if rest.starts_with(":?") {
+		VariableModifier::ErrorIfUnset(word(2)?)
+	}
+	else if rest.starts_with("##") {
+		VariableModifier::RemovePrefix { pattern: word(2)?, longest: true }
+	}
+	else if rest.starts_with('#') {
+		VariableModifier::RemovePrefix { pattern: word(1)?, longest: false }
+	}
+	else if rest.starts_with("%%") {
+		VariableModifier::RemoveSuffix { pattern: word(2)?, longest: true }
+	}
+	else if rest.starts_with('%') {
+		VariableModifier::RemoveSuffix { pattern: word(1)?, longest: false }
+	}
+	else if rest.starts_with('/') {
+		let all = rest.starts_with("//");
+		let pattern_start = if all { 2 } else { 1 };
+		let pattern_index = rest_index + pattern_start;
+		let (pattern, replacement) = match lexer::find_closing(&rest[pattern_start..], pattern_index, '/', None, false) {
+			Some((separator, _)) => {
+				let separator = pattern_start + separator;
+				(
+					parse_command_item(pattern_index, &rest[pattern_start..separator])?,
+					word(separator + 1)?,
+				)
 			}
+			None => (
+				parse_command_item(pattern_index, &rest[pattern_start..])?,
+				Box::new(CommandItem::new(
+					CommandItemKind::Combination(vec![]),
+					Span::new(rest_index + rest.len(), rest_index + rest.len()),
+				)),
+			),
+		};
+		VariableModifier::Substitute { pattern: Box::new(pattern), replacement, all }
+	}
+	else if rest.starts_with(':') {
+		// ${name:offset} and ${name:offset:length}
+		let mut numbers = rest[1..].splitn(2, ':').map(|n| n.trim().parse::<i64>());
+		match (numbers.next(), numbers.next()) {
+			(Some(Ok(offset)), None) => VariableModifier::Substring { offset, length: None },
+			(Some(Ok(offset)), Some(Ok(length))) => VariableModifier::Substring { offset, length: Some(length) },
+			_ => return Err(ParseError::new(
+				Span::new(rest_index + 1, rest_index + rest.len()),
+				"Expected an integer offset and optionally :length",
+			)),
 		}
 	}
+	else {
+		return Err(ParseError::new(
+			Span::new(rest_index, rest_index + rest.len()),
+			"Unknown variable modifier; expected one of :- :+ :? # ## % %% / // :offset",
+		));
+	};
+
+	Ok(CommandItemKind::VariableExpansion { name, modifier })
+}
+
+
+/// Parses a list index like `[2]`, `[-1]` or `[2..-1]` at the start of input,
+/// returning it together with its length
+pub(crate) fn parse_list_index(start_index: usize, input: &str) -> Result<(ListIndex, usize), ParseError> {
+	let end = match input.find(']') {
+		Some(end) => end,
+		None => return Err(ParseError::new(
+			Span::new(start_index, start_index + input.len()),
+			"Found end of word instead of ] while reading list index",
+		)),
+	};
+	let content = &input[1..end];
+	let invalid = || ParseError::new(
+		Span::new(start_index + 1, start_index + end),
+		"Expected an index like 2 or -1, or a range like 2..-1, ..3 or 2..",
+	);
+	let parse_bound = |bound: &str| -> Result<Option<i64>, ParseError> {
+		match bound.trim() {
+			"" => Ok(None),
+			bound => match bound.parse::<i64>() {
+				Ok(0) | Err(_) => Err(invalid()),
+				Ok(index) => Ok(Some(index)),
+			},
+		}
+	};
+	let index = match content.find("..") {
+		Some(separator) => ListIndex::Range {
+			start: parse_bound(&content[..separator])?,
+			end: parse_bound(&content[separator + 2..])?,
+		},
+		None => ListIndex::Single(parse_bound(content)?.ok_or_else(invalid)?),
+	};
+	Ok((index, end + 1))
+}
 
-	Ok(CommandItem::Combination(result).normalize())
+/// Parses the expression of a `$((...))` arithmetic expansion, which is
+/// everything inside of the outer parentheses and starts at start_index
+fn parse_arithmetic_expansion(start_index: usize, source: &str) -> Result<CommandItemKind, ParseError> {
+	let expression = Expression::parse(source).map_err(|error| error.offset(start_index))?;
+	Ok(CommandItemKind::ArithmeticExpansion(expression))
+}
+
+/// Parses the inside of a range expansion: `1..10`, `a..e` or `1..10..2`
+fn parse_brace_range(input: &str) -> Option<BraceExpansion> {
+	let parts: Vec<&str> = input.split("..").collect();
+	let step = match parts.len() {
+		2 => 1,
+		3 => parts[2].parse::<i64>().ok()?.checked_abs()?.max(1),
+		_ => return None,
+	};
+
+	if let (Ok(start), Ok(end)) = (parts[0].parse::<i64>(), parts[1].parse::<i64>()) {
+		// {01..10} pads all numbers to the same width
+		let zero_padded = parts[..2].iter().any(|part| {
+			let digits = part.trim_start_matches('-');
+			digits.len() > 1 && digits.starts_with('0')
+		});
+		let width = if zero_padded { parts[0].len().max(parts[1].len()) } else { 0 };
+		Some(BraceExpansion::NumericRange { start, end, step, width })
+	}
+	else {
+		let mut start = parts[0].chars();
+		let mut end = parts[1].chars();
+		match (start.next(), start.next(), end.next(), end.next()) {
+			(Some(start), None, Some(end), None) => Some(BraceExpansion::CharacterRange { start, end, step }),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -470,34 +967,250 @@ pub(crate) struct ParseError {
 	pub start_index: usize,
 	pub end_index: usize,
 	pub reason: String,
+	// Other places the error relates to, like where a string was opened
+	pub labels: Vec<Label>,
+	pub notes: Vec<String>,
+	pub help: Option<String>,
+}
+
+/// A secondary place in the input an error points to, with what it is
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Label {
+	pub start_index: usize,
+	pub end_index: usize,
+	pub message: String,
+}
+
+impl ParseError {
+	pub(crate) fn new(span: Span, reason: impl Into<String>) -> Self {
+		ParseError {
+			start_index: span.start,
+			end_index: span.end,
+			reason: reason.into(),
+			labels: vec![],
+			notes: vec![],
+			help: None,
+		}
+	}
+
+	pub(crate) fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+		self.labels.push(Label {
+			start_index: span.start,
+			end_index: span.end,
+			message: message.into(),
+		});
+		self
+	}
+
+	pub(crate) fn with_note(mut self, note: impl Into<String>) -> Self {
+		self.notes.push(note.into());
+		self
+	}
+
+	pub(crate) fn with_help(mut self, help: impl Into<String>) -> Self {
+		self.help = Some(help.into());
+		self
+	}
+
+	/// Moves the error and its labels by offset, for errors found in a part
+	/// of the input parsed on its own
+	pub(crate) fn offset(mut self, offset: usize) -> Self {
+		self.start_index += offset;
+		self.end_index += offset;
+		for label in &mut self.labels {
+			label.start_index += offset;
+			label.end_index += offset;
+		}
+		self
+	}
+}
+
+/// A range of bytes in the parsed input
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Span {
+	pub start: usize,
+	pub end: usize,
+}
+
+impl Span {
+	pub(crate) fn new(start: usize, end: usize) -> Self {
+		Span { start, end }
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+
+	/// The smallest span covering both spans
+	pub(crate) fn to(self, other: Span) -> Self {
+		Span::new(self.start.min(other.start), self.end.max(other.end))
+	}
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct SyntaxTree {
+	pub kind: SyntaxTreeKind,
+	pub span: Span,
 }
 
-#[derive(Clone, Debug)]
-pub(crate) enum SyntaxTree {
+#[derive(Serialize, Clone, Debug)]
+pub(crate) enum SyntaxTreeKind {
 	Command(CommandSyntax),
 	PipeChain(Vec<CommandSyntax>),
+	// Statements on separate lines or separated by ;
+	Sequence(Vec<SyntaxTree>),
+	// Runs the body of the first branch whose condition exits with 0, or
+	// otherwise if there is none
+	If {
+		branches: Vec<(SyntaxTree, SyntaxTree)>,
+		otherwise: Option<Box<SyntaxTree>>,
+	},
+	// Runs the body once for every word the items expand to
+	For {
+		variable: String,
+		items: Vec<CommandItem>,
+		body: Box<SyntaxTree>,
+	},
+	// Runs the body as long as the condition exits with 0
+	While {
+		condition: Box<SyntaxTree>,
+		body: Box<SyntaxTree>,
+	},
+	// Runs the body of the first case with a pattern matching the value
+	Switch {
+		value: Vec<CommandItem>,
+		cases: Vec<(Vec<CommandItem>, SyntaxTree)>,
+	},
+	// Defines a function, whose body runs when it is called like a command
+	Function {
+		name: String,
+		body: Box<SyntaxTree>,
+	},
+	// A block with redirections after its end, like end > file; the
+	// redirections wrap an empty command
+	RedirectedBlock {
+		block: Box<SyntaxTree>,
+		redirection: CommandSyntax,
+	},
+}
+
+/// Writes items separated by spaces
+fn write_words(f: &mut std::fmt::Formatter<'_>, items: &[CommandItem]) -> std::fmt::Result {
+	for (index, item) in items.iter().enumerate() {
+		if index != 0 {
+			write!(f, " ")?;
+		}
+		write!(f, "{}", item)?;
+	}
+	Ok(())
 }
 
 impl Display for SyntaxTree {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            SyntaxTree::Command(cmd) => write!(f, "{}", cmd),
-            SyntaxTree::PipeChain(chain) => {
-				let mut chain: Vec<String> = chain.iter().flat_map(|elem| {
-					vec![elem.to_string(), " | ".to_string()]
-				}).collect();
-				chain.remove(chain.len() - 1);
-				let chain = chain.iter().fold("".to_string(), |a, e| {
-					a + e
-				});
-				write!(f, "{}", chain)
+        match &self.kind {
+            SyntaxTreeKind::Command(cmd) => write!(f, "{}", cmd),
+            SyntaxTreeKind::PipeChain(chain) => {
+				for (index, command) in chain.iter().enumerate() {
+					if index != 0 {
+						write!(f, " | ")?;
+					}
+					write!(f, "{}", command)?;
+				}
+				Ok(())
+			}
+            SyntaxTreeKind::Sequence(statements) => {
+				for (index, statement) in statements.iter().enumerate() {
+					if index != 0 {
+						write!(f, "; ")?;
+					}
+					write!(f, "{}", statement)?;
+				}
+				Ok(())
+			}
+            SyntaxTreeKind::If { branches, otherwise } => {
+				for (index, (condition, body)) in branches.iter().enumerate() {
+					if index != 0 {
+						write!(f, "else ")?;
+					}
+					write!(f, "if {}; ", condition)?;
+					if !body.is_empty() {
+						write!(f, "{}; ", body)?;
+					}
+				}
+				if let Some(otherwise) = otherwise {
+					write!(f, "else; ")?;
+					if !otherwise.is_empty() {
+						write!(f, "{}; ", otherwise)?;
+					}
+				}
+				write!(f, "end")
+			}
+            SyntaxTreeKind::For { variable, items, body } => {
+				write!(f, "for {} in", variable)?;
+				for item in items {
+					write!(f, " {}", item)?;
+				}
+				write!(f, "; ")?;
+				if !body.is_empty() {
+					write!(f, "{}; ", body)?;
+				}
+				write!(f, "end")
+			}
+            SyntaxTreeKind::While { condition, body } => {
+				write!(f, "while {}; ", condition)?;
+				if !body.is_empty() {
+					write!(f, "{}; ", body)?;
+				}
+				write!(f, "end")
+			}
+            SyntaxTreeKind::Switch { value, cases } => {
+				write!(f, "switch ")?;
+				write_words(f, value)?;
+				write!(f, "; ")?;
+				for (patterns, body) in cases {
+					write!(f, "case ")?;
+					write_words(f, patterns)?;
+					write!(f, "; ")?;
+					if !body.is_empty() {
+						write!(f, "{}; ", body)?;
+					}
+				}
+				write!(f, "end")
+			}
+            SyntaxTreeKind::Function { name, body } => {
+				write!(f, "function {}; ", name)?;
+				if !body.is_empty() {
+					write!(f, "{}; ", body)?;
+				}
+				write!(f, "end")
+			}
+            SyntaxTreeKind::RedirectedBlock { block, redirection } => {
+				// The redirection starts with its empty command, so with a space
+				write!(f, "{}{}", block, redirection)
 			}
         }
     }
 }
 
-#[derive(Clone, Debug)]
-pub(crate) enum CommandSyntax {
+impl SyntaxTree {
+	pub(crate) fn new(kind: SyntaxTreeKind, span: Span) -> Self {
+		SyntaxTree { kind, span }
+	}
+
+	/// Whether this is a sequence without statements, like an empty block
+	pub(crate) fn is_empty(&self) -> bool {
+		matches!(&self.kind, SyntaxTreeKind::Sequence(statements) if statements.is_empty())
+	}
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct CommandSyntax {
+	pub kind: CommandSyntaxKind,
+	pub span: Span,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) enum CommandSyntaxKind {
 	InputRedirection {
 		command: Box<CommandSyntax>,
 		// This will always be None for now
@@ -515,57 +1228,58 @@ pub(crate) enum CommandSyntax {
 }
 
 impl CommandSyntax {
-	fn inject_and_replace(self, new_command: Vec<CommandItem>) -> Self {
-		match self {
-		    Self::InputRedirection { command, file_descriptor, filename } => {
-				Self::InputRedirection {
-					command: Box::from(command.inject_and_replace(new_command)),
-					file_descriptor,
-					filename,
-				}
-			}
-		    Self::OutputRedirection { command, file_descriptor, destination, kind } => {
-				Self::OutputRedirection {
-					command: Box::from(command.inject_and_replace(new_command)),
-					file_descriptor,
-					destination,
-					kind,
+	pub(crate) fn new(kind: CommandSyntaxKind, span: Span) -> Self {
+		CommandSyntax { kind, span }
+	}
+}
+
+impl CommandSyntax {
+	/// Writes the command inside of redirections, which are written after it.
+	/// As it may not have started the statement, a first word that would
+	/// be taken as a keyword there is quoted.
+	fn write_redirected(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			CommandSyntaxKind::Command(cmd) => match cmd.split_first() {
+				Some((CommandItem { kind: CommandItemKind::RawString(word), .. }, rest))
+					if BLOCK_KEYWORDS.contains(&word.as_str())
+						|| INNER_KEYWORDS.contains(&word.as_str())
+						|| ["break", "continue", "return"].contains(&word.as_str()) =>
+				{
+					write!(f, "\"{}\"", word)?;
+					for item in rest {
+						write!(f, " {}", item)?;
+					}
+					Ok(())
 				}
-			}
-		    Self::Command(_old_command) => Self::Command(new_command),
+				_ => write_words(f, cmd),
+			},
+			_ => write!(f, "{}", self),
 		}
 	}
 }
 
 impl Display for CommandSyntax {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CommandSyntax::InputRedirection { command, file_descriptor, filename } => {
-				write!(f, "{} {}< {}", *command, file_descriptor.map(|fd| fd.to_string()).unwrap_or("".to_owned()), filename)
+        match &self.kind {
+            CommandSyntaxKind::InputRedirection { command, file_descriptor, filename } => {
+				command.write_redirected(f)?;
+				write!(f, " {}<{}", file_descriptor.map(|fd| fd.to_string()).unwrap_or("".to_owned()), filename)
 			}
-            CommandSyntax::OutputRedirection { command, file_descriptor, destination, kind } => {
-				write!(f, "{} ", *command)?;
+            CommandSyntaxKind::OutputRedirection { command, file_descriptor, destination, kind } => {
+				command.write_redirected(f)?;
+				write!(f, " ")?;
 				if let Some(fd) = file_descriptor {
 					write!(f, "{}", fd)?;
 				}
 				write!(f, "{}", kind)?;
 				write!(f, "{}", destination)
 			}
-            CommandSyntax::Command(cmd) => {
-				let mut cmd: Vec<_> = cmd.into_iter().flat_map(|ci| {
-					vec![ci.to_string(), " ".to_string()]
-				}).collect();
-				cmd.remove(cmd.len() - 1);
-				let cmd = cmd.into_iter().fold("".to_string(), |a, s| {
-					a + &s
-				});
-				write!(f, "{}", cmd)
-			}
+            CommandSyntaxKind::Command(cmd) => write_words(f, cmd),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Serialize, Copy, Clone, Debug, PartialEq)]
 pub(crate) enum OutputRedirectionKind {
 	Create,
 	Append,
@@ -588,30 +1302,47 @@ impl Display for OutputRedirectionKind {
     }
 }
 
-#[derive(Clone, Debug)]
-pub(crate) enum CommandItem {
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct CommandItem {
+	pub kind: CommandItemKind,
+	pub span: Span,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) enum CommandItemKind {
 	String(String),
 	RawString(String),
 	ShellVariable(String),
-	Subcommand(Box<CommandSyntax>),
+	IndexedVariable {
+		name: String,
+		index: ListIndex,
+	},
+	Subcommand(Box<SyntaxTree>),
+	QuotedSubcommand(Box<SyntaxTree>),
+	BraceExpansion(BraceExpansion),
+	ArithmeticExpansion(Expression),
+	VariableExpansion {
+		name: String,
+		modifier: VariableModifier,
+	},
 	Combination(Vec<CommandItem>),
 }
 
 impl CommandItem {
+	pub(crate) fn new(kind: CommandItemKind, span: Span) -> Self {
+		CommandItem { kind, span }
+	}
+
 	pub(crate) fn flatten(self) -> Self {
-		match self {
-			Self::Combination(items) => {
+		match self.kind {
+			CommandItemKind::Combination(items) => {
 				let mut new_items = vec![];
 				for item in items {
-					match item {
-						Self::Combination(mut inner_items) => {
+					match item.kind {
+						CommandItemKind::Combination(mut inner_items) => {
 							new_items.append(&mut inner_items)
 						}
-						Self::RawString(s) => {
-							if !s.is_empty() {
-								new_items.push(Self::RawString(s))
-							}
-						}
+						CommandItemKind::RawString(ref s) if s.is_empty() => {}
 						_ => new_items.push(item),
 					}
 				}
@@ -622,37 +1353,270 @@ impl CommandItem {
 					new_items.remove(0)
 				}
 				else {
-					Self::Combination(new_items)
+					CommandItem::new(CommandItemKind::Combination(new_items), self.span)
 				}
 			}
-			_ => self,
+			kind => CommandItem::new(kind, self.span),
 		}
 	}
 
 	pub(crate) fn normalize(mut self) -> Self {
 		self = self.flatten();
-		match self {
-			CommandItem::Combination(mut c) if c.len() == 1 => c.remove(0),
-			_ => self,
+		match self.kind {
+			CommandItemKind::Combination(mut c) if c.len() == 1 => c.remove(0),
+			kind => CommandItem::new(kind, self.span),
+		}
+	}
+}
+
+/// Escapes the characters of text that would have a meaning in the input,
+/// so that it parses back into the same text
+fn escape(text: &str, quoted: bool, extra: &[char]) -> String {
+	// Only braces matched within the text are kept as they are: a lone } would
+	// close a ${...} around the text and a lone { would wait for one after it
+	let mut matched = vec![false; text.chars().count()];
+	let mut open = vec![];
+	for (index, c) in text.chars().enumerate() {
+		match c {
+			'{' => open.push(index),
+			'}' => if let Some(start) = open.pop() {
+				matched[start] = true;
+				matched[index] = true;
+			},
+			_ => {}
+		}
+	}
+
+	let mut escaped = String::with_capacity(text.len());
+	// Commas and dots between braces would make a brace expansion
+	let mut brace_depth = 0;
+	// Whether c starts a part of the word, where # would start a comment
+	let mut starts_part = true;
+	for (index, c) in text.chars().enumerate() {
+		if !quoted && c == '{' && !matched[index] {
+			// An escaped { is taken literally only inside of a string
+			escaped.push_str("\"{\"");
+			starts_part = true;
+			continue;
+		}
+		let special = if quoted {
+			c == '"' || c == '$'
+		}
+		else if c == '}' && !matched[index] {
+			true
+		}
+		else if c == '{' || c == '}' {
+			brace_depth = if c == '{' { brace_depth + 1 } else { brace_depth - 1 };
+			false
+		}
+		else {
+			c.is_whitespace()
+				|| SPECIAL_CHARS.contains(&c)
+				|| (brace_depth > 0 && (c == ',' || c == '.'))
+				|| (starts_part && c == '#')
+		};
+		starts_part = false;
+		match c {
+			'\n' => escaped.push_str("`n"),
+			'\r' => escaped.push_str("`r"),
+			'\t' => escaped.push_str("`t"),
+			c if special || c == ESCAPE_CHAR || extra.contains(&c) => {
+				escaped.push(ESCAPE_CHAR);
+				escaped.push(c);
+			}
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+impl CommandItem {
+	/// Writes the item as part of a brace expansion list, in which commas
+	/// separate the items
+	fn write_list_item(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			CommandItemKind::RawString(s) => write!(f, "{}", escape(s, false, &[','])),
+			CommandItemKind::Combination(items) => {
+				for item in items {
+					item.write_list_item(f)?;
+				}
+				Ok(())
+			}
+			_ => write!(f, "{}", self),
 		}
 	}
 }
 
 impl Display for CommandItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-			CommandItem::String(s) => write!(f, "\"{}\"", s),
-            CommandItem::ShellVariable(var_name) => {
+        match &self.kind {
+			CommandItemKind::String(s) => write!(f, "\"{}\"", escape(s, true, &[])),
+            CommandItemKind::ShellVariable(var_name) => {
 				write!(f, "${}", var_name)
 			},
-            CommandItem::Combination(items) => {
-				for item in items {
-					write!(f, "{}", item)?;
+            CommandItemKind::Combination(items) => {
+				for (index, item) in items.iter().enumerate() {
+					// $name followed by what would continue the name
+					let continues_name = items.get(index + 1).map_or(false, |next| {
+						next.to_string().starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '[')
+					});
+					match &item.kind {
+						CommandItemKind::ShellVariable(name) if continues_name => write!(f, "${{{}}}", name)?,
+						_ => write!(f, "{}", item)?,
+					}
+				}
+				Ok(())
+			}
+            CommandItemKind::IndexedVariable { name, index } => write!(f, "${}[{}]", name, index),
+            CommandItemKind::Subcommand(s) => write!(f, "({})", s),
+            CommandItemKind::QuotedSubcommand(s) => write!(f, "\"$({})\"", s),
+            CommandItemKind::BraceExpansion(expansion) => write!(f, "{}", expansion),
+            CommandItemKind::ArithmeticExpansion(expression) => write!(f, "$({})", expression),
+            CommandItemKind::VariableExpansion { name, modifier } => {
+				match modifier {
+					VariableModifier::Default(word) => write!(f, "${{{}:-{}}}", name, word),
+					VariableModifier::Alternative(word) => write!(f, "${{{}:+{}}}", name, word),
+					VariableModifier::ErrorIfUnset(word) => write!(f, "${{{}:?{}}}", name, word),
+					VariableModifier::Length => write!(f, "${{#{}}}", name),
+					VariableModifier::RemovePrefix { pattern, longest } => {
+						write!(f, "${{{}{}{}}}", name, if *longest { "##" } else { "#" }, pattern)
+					}
+					VariableModifier::RemoveSuffix { pattern, longest } => {
+						write!(f, "${{{}{}{}}}", name, if *longest { "%%" } else { "%" }, pattern)
+					}
+					VariableModifier::Substitute { pattern, replacement, all } => {
+						let pattern = pattern.to_string();
+						// ${name//} would substitute all
+						if pattern.is_empty() && !*all {
+							return write!(f, "${{{}/}}", name);
+						}
+						write!(f, "${{{}{}{}/{}}}", name, if *all { "//" } else { "/" }, pattern, replacement)
+					}
+					VariableModifier::Substring { offset, length } => {
+						// The space keeps a negative offset from looking like :-
+						write!(f, "${{{}: {}", name, offset)?;
+						if let Some(length) = length {
+							write!(f, ":{}", length)?;
+						}
+						write!(f, "}}")
+					}
+				}
+			}
+            CommandItemKind::RawString(s) => write!(f, "{}", escape(s, false, &[])),
+        }
+    }
+}
+
+/// A 1-based index into a list variable; negative indices count from the end
+#[derive(Serialize, Clone, Copy, Debug)]
+pub(crate) enum ListIndex {
+	// $xs[2]
+	Single(i64),
+	// $xs[2..-1]; a missing start or end means the first or last element
+	Range {
+		start: Option<i64>,
+		end: Option<i64>,
+	},
+}
+
+impl Display for ListIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListIndex::Single(index) => write!(f, "{}", index),
+            ListIndex::Range { start, end } => {
+				if let Some(start) = start {
+					write!(f, "{}", start)?;
+				}
+				write!(f, "..")?;
+				if let Some(end) = end {
+					write!(f, "{}", end)?;
 				}
 				Ok(())
 			}
-            CommandItem::Subcommand(s) => write!(f, "({})", s),
-            CommandItem::RawString(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) enum VariableModifier {
+	// ${name:-word}: word if name is unset or empty
+	Default(Box<CommandItem>),
+	// ${name:+word}: word if name is set and not empty
+	Alternative(Box<CommandItem>),
+	// ${name:?message}: error with message if name is unset or empty
+	ErrorIfUnset(Box<CommandItem>),
+	// ${#name}: number of characters
+	Length,
+	// ${name#pattern}, ${name##pattern}
+	RemovePrefix {
+		pattern: Box<CommandItem>,
+		longest: bool,
+	},
+	// ${name%pattern}, ${name%%pattern}
+	RemoveSuffix {
+		pattern: Box<CommandItem>,
+		longest: bool,
+	},
+	// ${name/pattern/replacement}, ${name//pattern/replacement}
+	Substitute {
+		pattern: Box<CommandItem>,
+		replacement: Box<CommandItem>,
+		all: bool,
+	},
+	// ${name:offset}, ${name:offset:length}
+	Substring {
+		offset: i64,
+		length: Option<i64>,
+	},
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) enum BraceExpansion {
+	// {a,b,c}
+	List(Vec<CommandItem>),
+	// {1..10}, {10..1..2}, {01..10}
+	NumericRange {
+		start: i64,
+		end: i64,
+		step: i64,
+		// Minimum width, zero padded; 0 means no padding
+		width: usize,
+	},
+	// {a..z}, {a..z..2}
+	CharacterRange {
+		start: char,
+		end: char,
+		step: i64,
+	},
+}
+
+impl Display for BraceExpansion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BraceExpansion::List(items) => {
+				write!(f, "{{")?;
+				for (index, item) in items.iter().enumerate() {
+					if index != 0 {
+						write!(f, ",")?;
+					}
+					item.write_list_item(f)?;
+				}
+				write!(f, "}}")
+			}
+            BraceExpansion::NumericRange { start, end, step, width } => {
+				write!(f, "{{{:0width$}..{:0width$}", start, end, width = width)?;
+				if *step != 1 {
+					write!(f, "..{}", step)?;
+				}
+				write!(f, "}}")
+			}
+            BraceExpansion::CharacterRange { start, end, step } => {
+				write!(f, "{{{}..{}", start, end)?;
+				if *step != 1 {
+					write!(f, "..{}", step)?;
+				}
+				write!(f, "}}")
+			}
         }
     }
 }
@@ -0,0 +1,593 @@
+//! Splits input into tokens that know which bytes of the input they came from.
+//!
+//! This is the only place that knows about quotes, escapes, comments and line
+//! continuations. It keeps track of the strings and subcommands it is inside
+//! of, so the parser only ever sees them properly opened and closed.
+
+use std::{cell::RefCell, collections::HashMap, iter::Peekable, rc::Rc, str::CharIndices};
+
+use super::{ESCAPE_CHAR, EXPANSION_CHARS, OutputRedirectionKind, PIPE_CHAR, ParseError, STRING_CHARS, Span, parse_brace_range};
+
+#[derive(Clone, Debug)]
+pub(super) struct Token {
+	pub(super) kind: TokenKind,
+	pub(super) span: Span,
+	// Whether the token directly follows the previous one without whitespace,
+	// so that both belong to the same word
+	pub(super) joined: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum TokenKind {
+	// Unquoted text, with its escapes resolved
+	Text(String),
+	// A wildcard, brace or tilde made literal by the escape character
+	EscapedChar(char),
+	// An opening or closing ' or "
+	Quote(char),
+	// Text inside of quotes, with its escapes resolved
+	QuotedText(String),
+	// $name
+	Variable(String),
+	// $name[index], with the index including its brackets
+	IndexedVariable {
+		name: String,
+		index: String,
+	},
+	// ${...}, with the text between the braces
+	VariableExpansion(String),
+	// $((...)), with the expression inside of the outer parentheses
+	ArithmeticExpansion(String),
+	// {a,b} or {1..3}, with the spans of the items between the commas
+	BraceExpansion(Vec<Span>),
+	// ( or $(; inside of a double quoted string its output is not split
+	SubcommandStart {
+		quoted: bool,
+	},
+	SubcommandEnd,
+	Pipe,
+	// ; or a newline
+	Separator,
+	// <, >, >> or >|, with the digits right before it
+	Redirection {
+		file_descriptor: Option<String>,
+		operator: RedirectionOperator,
+	},
+}
+
+impl TokenKind {
+	/// Whether a word can start with this token
+	pub(super) fn starts_word(&self) -> bool {
+		!matches!(
+			self,
+			TokenKind::QuotedText(_) | TokenKind::SubcommandEnd | TokenKind::Pipe | TokenKind::Separator | TokenKind::Redirection { .. }
+		)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum RedirectionOperator {
+	Input,
+	Output(OutputRedirectionKind),
+}
+
+/// The tokens of some input, together with the problems found in it. Strings
+/// and subcommands the input ends inside of are closed at its end, so the
+/// parser can go on and find more problems.
+pub(super) struct Lexed {
+	pub(super) tokens: Vec<Token>,
+	pub(super) errors: Vec<ParseError>,
+	// The spans of the comments, which are left out of the tokens
+	pub(super) comments: Vec<Span>,
+	// Whether the input ended inside of a string, subcommand or expansion, so
+	// more input could still make it valid
+	pub(super) unclosed: bool,
+}
+
+/// Lexes input, which starts at offset in the input spans refer to
+pub(super) fn lex(input: &str, offset: usize) -> Lexed {
+	Lexer::new(input, offset, false).run().into()
+}
+
+/// Lexes input as a single word, in which whitespace and the characters
+/// separating commands are taken literally
+pub(super) fn lex_word(input: &str, offset: usize) -> Lexed {
+	Lexer::new(input, offset, true).run().into()
+}
+
+impl From<Lexer<'_>> for Lexed {
+	fn from(lexer: Lexer<'_>) -> Self {
+		Lexed {
+			tokens: lexer.tokens,
+			errors: lexer.errors,
+			comments: lexer.comments,
+			unclosed: lexer.unclosed,
+		}
+	}
+}
+
+/// Finds the first closing character that is not escaped, quoted, inside of
+/// an expansion or a subcommand, or closing a literal `{`, together with the
+/// separators before it. If within_word is set, whitespace ends the search,
+/// just like any problem in the input.
+pub(super) fn find_closing(
+	input: &str,
+	offset: usize,
+	closing: char,
+	separator: Option<char>,
+	within_word: bool,
+) -> Option<(usize, Vec<usize>)> {
+	scan_for_closing(input, offset, closing, separator, within_word, Rc::default())
+}
+
+fn scan_for_closing(
+	input: &str,
+	offset: usize,
+	closing: char,
+	separator: Option<char>,
+	within_word: bool,
+	brace_expansions: BraceExpansions,
+) -> Option<(usize, Vec<usize>)> {
+	let mut lexer = Lexer::new(input, offset, !within_word);
+	lexer.brace_expansions = brace_expansions;
+	lexer.scan = Some(Scan {
+		closing,
+		separator,
+		within_word,
+		brace_depth: 0,
+		separators: vec![],
+		found: None,
+		failed: false,
+	});
+	let lexer = lexer.run();
+	let scan = lexer.scan.expect("the scan is never taken");
+	if scan.failed || !lexer.errors.is_empty() {
+		return None;
+	}
+	scan.found.map(|found| (found, scan.separators))
+}
+
+/// A string or subcommand the lexer is inside of
+enum Frame {
+	Quote {
+		quote: char,
+		start: usize,
+	},
+	Subcommand {
+		start: usize,
+	},
+}
+
+/// The brace expansions found so far by where they start in the whole input,
+/// shared with the scans started from a lexer. Without it, every { in a
+/// nested scan would be searched again by each scan around it.
+type BraceExpansions = Rc<RefCell<HashMap<usize, Option<(Vec<Span>, usize)>>>>;
+
+/// The state of a search started by find_closing
+struct Scan {
+	closing: char,
+	separator: Option<char>,
+	within_word: bool,
+	// Number of literal { waiting for their }
+	brace_depth: usize,
+	separators: Vec<usize>,
+	found: Option<usize>,
+	failed: bool,
+}
+
+struct Lexer<'a> {
+	input: &'a str,
+	offset: usize,
+	chars: Peekable<CharIndices<'a>>,
+	tokens: Vec<Token>,
+	// Text not yet turned into a token, with where it started and whether it
+	// is joined to the previous token
+	text: Option<(String, usize, bool)>,
+	// Whether the next token continues the current word
+	joined: bool,
+	frames: Vec<Frame>,
+	single_word: bool,
+	scan: Option<Scan>,
+	brace_expansions: BraceExpansions,
+	errors: Vec<ParseError>,
+	comments: Vec<Span>,
+	unclosed: bool,
+}
+
+impl<'a> Lexer<'a> {
+	fn new(input: &'a str, offset: usize, single_word: bool) -> Self {
+		Lexer {
+			input,
+			offset,
+			chars: input.char_indices().peekable(),
+			tokens: vec![],
+			text: None,
+			joined: false,
+			frames: vec![],
+			single_word,
+			scan: None,
+			brace_expansions: Rc::default(),
+			errors: vec![],
+			comments: vec![],
+			unclosed: false,
+		}
+	}
+
+	fn run(mut self) -> Self {
+		while let Some((index, c)) = self.chars.next() {
+			match self.frames.last() {
+				Some(Frame::Quote { quote, .. }) => {
+					let quote = *quote;
+					self.lex_quoted(index, c, quote);
+				}
+				_ => self.lex_unquoted(index, c),
+			}
+			if let Some(Scan { found: Some(_), .. } | Scan { failed: true, .. }) = self.scan {
+				return self;
+			}
+		}
+
+		// Close what is still open, innermost first
+		let end = self.input.len();
+		self.flush(end);
+		while let Some(frame) = self.frames.pop() {
+			self.unclosed = true;
+			match frame {
+				Frame::Quote { quote, start } => {
+					let error = self.error(end, end, format!("Found end of input instead of a closing {}", quote))
+						.with_label(self.span(start, start + 1), "string opened here")
+						.with_help(format!("close the string with {}, or escape the quote with `{} to use it literally", quote, quote));
+					self.errors.push(error);
+					self.push(TokenKind::Quote(quote), end, end);
+				}
+				Frame::Subcommand { start } => {
+					let error = self.error(end, end, "Found end of input instead of ) while reading subcommand")
+						.with_label(self.span(start, start + 1), "subcommand opened here");
+					self.errors.push(error);
+					self.push(TokenKind::SubcommandEnd, end, end);
+				}
+			}
+		}
+		self
+	}
+
+	fn lex_unquoted(&mut self, index: usize, c: char) {
+		let at_top = self.frames.is_empty();
+		if let (Some(scan), true) = (&mut self.scan, at_top) {
+			if c == scan.closing && scan.brace_depth == 0 {
+				scan.found = Some(index);
+				return;
+			}
+			else if Some(c) == scan.separator && scan.brace_depth == 0 {
+				scan.separators.push(index);
+				return;
+			}
+			else if scan.within_word && (c.is_whitespace() || [';', PIPE_CHAR, '<', '>'].contains(&c)) {
+				scan.failed = true;
+				return;
+			}
+			else if c == '}' && scan.brace_depth > 0 {
+				scan.brace_depth -= 1;
+			}
+		}
+
+		// In a single word, only subcommands separate commands
+		let separating = !(self.single_word && at_top);
+		if c == ESCAPE_CHAR {
+			self.escape(index, true);
+		}
+		else if separating && (c == '\n' || c == ';') {
+			self.flush(index);
+			self.push(TokenKind::Separator, index, index + 1);
+		}
+		else if separating && c.is_whitespace() {
+			self.flush(index);
+			self.joined = false;
+		}
+		else if separating && self.scan.is_none() && self.text.is_none() && c == '#' && self.input[..index].chars().next_back().map_or(true, |p| p.is_whitespace() || p == ';' || p == PIPE_CHAR) {
+			// A comment runs until the end of the line
+			self.flush(index);
+			let mut end = index + 1;
+			while let Some((comment_index, comment_char)) = self.chars.next_if(|(_, c)| *c != '\n') {
+				end = comment_index + comment_char.len_utf8();
+			}
+			self.comments.push(self.span(index, end));
+		}
+		else if separating && c == PIPE_CHAR {
+			self.flush(index);
+			self.push(TokenKind::Pipe, index, index + 1);
+		}
+		else if separating && (c == '<' || c == '>') {
+			self.redirection(index, c);
+		}
+		else if STRING_CHARS.contains(&c) {
+			self.flush(index);
+			self.push(TokenKind::Quote(c), index, index + 1);
+			self.frames.push(Frame::Quote { quote: c, start: index });
+		}
+		else if c == '(' {
+			self.flush(index);
+			self.open_subcommand(index, index + 1, false);
+		}
+		else if c == ')' {
+			self.flush(index);
+			if let Some(Frame::Subcommand { .. }) = self.frames.last() {
+				self.frames.pop();
+				self.push(TokenKind::SubcommandEnd, index, index + 1);
+			}
+			else {
+				let error = self.error(index, index + 1, "Found closing parenthesis without a previous open parenthesis")
+					.with_help("escape it with `) to use it literally");
+				self.errors.push(error);
+			}
+		}
+		else if c == '$' {
+			self.dollar(index, false);
+		}
+		else if c == '{' {
+			match self.brace_expansion(index) {
+				Some((items, end)) => {
+					self.flush(index);
+					self.push(TokenKind::BraceExpansion(items), index, end);
+					self.skip_to(end);
+				}
+				None => {
+					if let (Some(scan), true) = (&mut self.scan, at_top) {
+						scan.brace_depth += 1;
+					}
+					self.append(index, c);
+				}
+			}
+		}
+		else {
+			self.append(index, c);
+		}
+	}
+
+	fn lex_quoted(&mut self, index: usize, c: char, quote: char) {
+		if c == ESCAPE_CHAR {
+			self.escape(index, false);
+		}
+		else if c == quote {
+			self.flush(index);
+			self.frames.pop();
+			self.push(TokenKind::Quote(c), index, index + 1);
+		}
+		else if c == '$' && quote == '"' {
+			self.dollar(index, true);
+		}
+		else {
+			self.append(index, c);
+		}
+	}
+
+	/// Handles the character after an escape character at index
+	fn escape(&mut self, index: usize, unquoted: bool) {
+		match self.chars.next() {
+			// A trailing escape character is ignored, and an escaped newline
+			// joins both lines
+			None | Some((_, '\n')) => {}
+			Some((next_index, next)) => {
+				let c = match next {
+					'n' => '\n',
+					'r' => '\r',
+					't' => '\t',
+					c => c,
+				};
+				if unquoted && EXPANSION_CHARS.contains(&c) {
+					self.flush(index);
+					self.push(TokenKind::EscapedChar(c), index, next_index + next.len_utf8());
+				}
+				else {
+					self.append(index, c);
+				}
+			}
+		}
+	}
+
+	fn redirection(&mut self, index: usize, c: char) {
+		// Digits starting the word are the file descriptor, like in 2>
+		let (file_descriptor, start) = match self.text.take() {
+			Some((text, start, false)) if text.chars().all(|c| c.is_ascii_digit()) && self.input[start..index] == text => {
+				(Some(text), start)
+			}
+			text => {
+				self.text = text;
+				self.flush(index);
+				(None, index)
+			}
+		};
+		let operator = if c == '<' {
+			RedirectionOperator::Input
+		}
+		else if self.chars.next_if(|(_, c)| *c == '>').is_some() {
+			RedirectionOperator::Output(OutputRedirectionKind::Append)
+		}
+		else if self.chars.next_if(|(_, c)| *c == PIPE_CHAR).is_some() {
+			RedirectionOperator::Output(OutputRedirectionKind::Overwrite)
+		}
+		else {
+			RedirectionOperator::Output(OutputRedirectionKind::Create)
+		};
+		let end = self.chars.peek().map_or(self.input.len(), |(i, _)| *i);
+		self.push(TokenKind::Redirection { file_descriptor, operator }, start, end);
+	}
+
+	/// Handles a $ at index, which starts an expansion or a subcommand
+	fn dollar(&mut self, index: usize, quoted: bool) {
+		let rest = &self.input[index..];
+		if rest.starts_with("$((") {
+			// The expression is everything inside of the outer parentheses, so
+			// $((1)+(2)) works as well
+			let mut depth = 0;
+			let close = rest.char_indices().skip(1).find(|(_, c)| {
+				match c {
+					'(' => depth += 1,
+					')' => depth -= 1,
+					_ => {}
+				}
+				depth == 0
+			});
+			let close = match close {
+				Some((close, _)) if rest[..close].ends_with(')') => index + close,
+				_ => return self.unclosed_expansion(index, 3, "arithmetic expansion", "))"),
+			};
+			self.flush(index);
+			self.push(TokenKind::ArithmeticExpansion(self.input[index + 2..close].to_owned()), index, close + 1);
+			self.skip_to(close + 1);
+		}
+		else if rest.starts_with("$(") {
+			self.flush(index);
+			self.chars.next();
+			self.open_subcommand(index, index + 2, quoted);
+		}
+		else if quoted {
+			self.append(index, '$');
+		}
+		else if rest.starts_with("${") {
+			let content = index + 2;
+			let close = match scan_for_closing(&self.input[content..], self.offset + content, '}', None, false, Rc::clone(&self.brace_expansions)) {
+				Some((close, _)) => content + close,
+				None => return self.unclosed_expansion(index, 2, "variable", "}"),
+			};
+			self.flush(index);
+			self.push(TokenKind::VariableExpansion(self.input[content..close].to_owned()), index, close + 1);
+			self.skip_to(close + 1);
+		}
+		else {
+			let name_length = rest[1..]
+				.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+				.unwrap_or(rest.len() - 1);
+			if name_length == 0 {
+				// A lone $ is taken literally
+				self.append(index, '$');
+				return;
+			}
+			let name = rest[1..1 + name_length].to_owned();
+			let name_end = index + 1 + name_length;
+			self.flush(index);
+			let has_index = self.input[name_end..].starts_with('[');
+			match self.input[name_end..].find(']').filter(|_| has_index) {
+				Some(close) => {
+					let end = name_end + close + 1;
+					let index_text = self.input[name_end..end].to_owned();
+					self.push(TokenKind::IndexedVariable { name, index: index_text }, index, end);
+					self.skip_to(end);
+				}
+				None => {
+					if has_index {
+						let error = self.error(name_end, self.input.len(), "Found end of word instead of ] while reading list index")
+							.with_label(self.span(index, name_end), "the index belongs to this variable")
+							.with_help("escape the [ with `[ to use it literally");
+						self.errors.push(error);
+					}
+					self.push(TokenKind::Variable(name), index, name_end);
+					self.skip_to(name_end);
+				}
+			}
+		}
+	}
+
+	/// Reports an expansion whose closing never comes, skipping the rest of
+	/// the input, which it would have run until
+	fn unclosed_expansion(&mut self, index: usize, opening_length: usize, description: &str, closing: &str) {
+		let end = self.input.len();
+		let error = self.error(end, end, format!("Found end of input instead of {} while reading {}", closing, description))
+			.with_label(self.span(index, index + opening_length), format!("{} opened here", description));
+		self.errors.push(error);
+		self.unclosed = true;
+		self.flush(index);
+		self.skip_to(end);
+	}
+
+	fn open_subcommand(&mut self, start: usize, end: usize, quoted: bool) {
+		self.push(TokenKind::SubcommandStart { quoted }, start, end);
+		self.frames.push(Frame::Subcommand { start });
+	}
+
+	/// Finds the items of a brace expansion starting at index, and where it
+	/// ends. Braces that don't form an expansion, such as `{}` or `{word}`,
+	/// are taken literally.
+	fn brace_expansion(&self, index: usize) -> Option<(Vec<Span>, usize)> {
+		let start = self.offset + index;
+		if let Some(found) = self.brace_expansions.borrow().get(&start) {
+			return found.clone().map(|(items, end)| (items, end - self.offset));
+		}
+		let found = self.find_brace_expansion(index);
+		let absolute = found.clone().map(|(items, end)| (items, self.offset + end));
+		self.brace_expansions.borrow_mut().insert(start, absolute);
+		found
+	}
+
+	fn find_brace_expansion(&self, index: usize) -> Option<(Vec<Span>, usize)> {
+		let content = index + 1;
+		let (close, commas) = scan_for_closing(
+			&self.input[content..],
+			self.offset + content,
+			'}',
+			Some(','),
+			true,
+			Rc::clone(&self.brace_expansions),
+		)?;
+		let close = content + close;
+		let span = |start, end| self.span(start, end);
+		if commas.is_empty() {
+			parse_brace_range(&self.input[content..close])?;
+			return Some((vec![span(content, close)], close + 1));
+		}
+		let mut items = vec![];
+		let mut item_start = content;
+		for comma in commas.into_iter().map(|comma| content + comma).chain(std::iter::once(close)) {
+			items.push(span(item_start, comma));
+			item_start = comma + 1;
+		}
+		Some((items, close + 1))
+	}
+
+	fn append(&mut self, index: usize, c: char) {
+		let joined = self.joined;
+		self.text.get_or_insert_with(|| (String::new(), index, joined)).0.push(c);
+	}
+
+	/// Turns the text collected up to end into a token
+	fn flush(&mut self, end: usize) {
+		if let Some((text, start, joined)) = self.text.take() {
+			let kind = match self.frames.last() {
+				Some(Frame::Quote { .. }) => TokenKind::QuotedText(text),
+				_ => TokenKind::Text(text),
+			};
+			self.tokens.push(Token {
+				kind,
+				span: self.span(start, end),
+				joined,
+			});
+			self.joined = true;
+		}
+	}
+
+	fn push(&mut self, kind: TokenKind, start: usize, end: usize) {
+		// Words end at separators, and start anew inside of subcommands
+		let ends_word = matches!(
+			kind,
+			TokenKind::Pipe | TokenKind::Separator | TokenKind::Redirection { .. } | TokenKind::SubcommandStart { .. }
+		);
+		self.tokens.push(Token {
+			kind,
+			span: self.span(start, end),
+			joined: self.joined,
+		});
+		self.joined = !ends_word;
+	}
+
+	fn skip_to(&mut self, end: usize) {
+		while self.chars.next_if(|(i, _)| *i < end).is_some() {}
+	}
+
+	fn span(&self, start: usize, end: usize) -> Span {
+		Span::new(self.offset + start, self.offset + end)
+	}
+
+	fn error(&self, start: usize, end: usize, reason: impl Into<String>) -> ParseError {
+		ParseError::new(self.span(start, end), reason)
+	}
+}
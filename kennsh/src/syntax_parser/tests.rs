@@ -0,0 +1,214 @@
+use super::*;
+
+fn parse_ok(input: &str) -> SyntaxTree {
+	match parse(input.to_owned()) {
+		Ok(tree) => tree,
+		Err(errors) => {
+			let reasons: Vec<_> = errors.iter().map(|error| error.reason.as_str()).collect();
+			panic!("{:?} didn't parse: {:?}", input, reasons)
+		}
+	}
+}
+
+/// Parses input, prints it and parses the printed text again, which has to
+/// print the same
+fn round_trip(input: &str) -> String {
+	let printed = parse_ok(input).to_string();
+	let reprinted = parse_ok(&printed).to_string();
+	assert_eq!(printed, reprinted, "printing {:?} is not stable", input);
+	printed
+}
+
+fn raw(s: &str) -> CommandItem {
+	CommandItem::new(CommandItemKind::RawString(s.to_owned()), Span::default())
+}
+
+fn combination(items: Vec<CommandItem>) -> CommandItem {
+	CommandItem::new(CommandItemKind::Combination(items), Span::default())
+}
+
+// Inputs with what they print as
+static PRINTED: &[(&str, &str)] = &[
+	("echo hi", "echo hi"),
+	("  echo   a\tb  ", "echo a b"),
+	("echo 'a b'", "echo \"a b\""),
+	("echo \"a b\"c", "echo \"a b\"c"),
+	("echo a` b", "echo a` b"),
+	("echo `*.rs", "echo \"*\".rs"),
+	("echo \"say `\"hi`\"\"", "echo \"say `\"hi`\"\""),
+	("echo '$x'", "echo \"`$x\""),
+	("echo a`;b a`|b", "echo a`;b a`|b"),
+	("echo `#not-a-comment a#b", "echo `#not-a-comment a#b"),
+	("echo a # comment", "echo a"),
+	("echo x`ny", "echo x`ny"),
+	("echo ``", "echo ``"),
+	("echo $HOME/foo", "echo $HOME/foo"),
+	("echo ${x}y", "echo ${x}y"),
+	("echo $x[2] $x[2..-1] $x[..3]", "echo $x[2] $x[2..-1] $x[..3]"),
+	("echo ${x:-a b}", "echo ${x:-a` b}"),
+	("echo ${#x} ${x##*/} ${x%.rs} ${x//a/b}", "echo ${#x} ${x##*/} ${x%.rs} ${x//a/b}"),
+	("echo ${x:1:2}", "echo ${x: 1:2}"),
+	("echo $((1 + 2 * 3))", "echo $((1 + 2 * 3))"),
+	("echo {a,b`,c} {1..3} {a..e}", "echo {a,b`,c} {1..3} {a..e}"),
+	("echo {} a`{b `{a,b}", "echo {} a\"{\"b \"{\"a,b`}"),
+	("echo ${x:-a`}b} ${x/}", "echo ${x:-a`}b} ${x/}"),
+	("> out if", "\"if\" >out"),
+	("echo a`|#b", "echo a`|#b"),
+	("echo (echo x | cat)", "echo (echo x | cat)"),
+	("echo \"a $(echo b) c\"", "echo \"a \"\"$(echo b)\"\" c\""),
+	("a | b | c; d\ne", "a | b | c; d; e"),
+	("cat < in", "cat <in"),
+	("echo > out", "echo >out"),
+	("echo 2>> err", "echo 2>>err"),
+	("echo >| out 2>&1", "echo >|out 2>&1"),
+	("if a; b; else if c; d; else; e; end", "if a; b; else if c; d; else; e; end"),
+	("if a\nend > f", "if a; end >f"),
+	("for x in a (b) \"c\"; echo $x; end", "for x in a (b) \"c\"; echo $x; end"),
+	("while true; break; end", "while true; break; end"),
+	("switch $x; case a b; echo; case '*'; end", "switch $x; case a b; echo; case \"*\"; end"),
+	("function f; return 1; end", "function f; return 1; end"),
+];
+
+#[test]
+fn prints_parsed_input() {
+	for (input, expected) in PRINTED {
+		assert_eq!(&round_trip(input), expected, "printing {:?}", input);
+	}
+}
+
+#[test]
+fn round_trips_words() {
+	let words = [
+		"plain", "a b", "tab\there", "new\nline", "quote\"d", "single'd", "`", "$", "$x", "(x)",
+		"a;b", "a|b", "<>", "#", "}", "{a,b}", "*", "~", "", "日本語",
+	];
+	for word in &words {
+		// A word holding the text in a string and in raw text next to it
+		let item = combination(vec![
+			CommandItem::new(CommandItemKind::String(word.to_string()), Span::default()),
+			raw(word),
+			raw("end"),
+		]);
+		let input = format!("echo {}", item);
+		let printed = round_trip(&input);
+		let tree = parse_ok(&printed);
+		match tree.kind {
+			SyntaxTreeKind::Command(CommandSyntax { kind: CommandSyntaxKind::Command(items), .. }) => {
+				assert_eq!(items.len(), 2, "{:?} split into several words in {:?}", word, printed);
+			}
+			kind => panic!("{:?} parsed into {:?}", printed, kind),
+		}
+	}
+}
+
+#[test]
+fn rejects_invalid_input() {
+	let inputs = [
+		"echo \"unclosed",
+		"echo (unclosed",
+		"echo )",
+		"echo ${x",
+		"echo $((1 +))",
+		"echo ()",
+		"echo |",
+		"| cat",
+		"end",
+		"else",
+		"if true",
+		"for 1 in x; end",
+		"function; end",
+		"break",
+		"return",
+		"switch; end",
+		"echo > ",
+		"echo 3> x",
+		"echo 1<x",
+		"echo >&x",
+		"if true; end echo",
+	];
+	for input in &inputs {
+		assert!(parse(input.to_string()).is_err(), "{:?} parsed", input);
+	}
+}
+
+#[test]
+fn reports_every_error() {
+	let errors = parse("echo )\necho ok\nend\necho \"x".to_owned()).unwrap_err();
+	let reasons: Vec<_> = errors.iter().map(|error| error.start_index).collect();
+	assert_eq!(reasons, [5, 15, 26]);
+	let unclosed = &errors[2];
+	assert_eq!(unclosed.labels.len(), 1);
+	assert_eq!(unclosed.labels[0].start_index, 24);
+	assert!(unclosed.help.is_some());
+}
+
+#[test]
+fn never_panics_on_truncated_input() {
+	let mut inputs: Vec<String> = PRINTED.iter().map(|(input, _)| input.to_string()).collect();
+	inputs.push("echo $x[1 ${x/a ${x:-(a} $((max(1, (2) \"$( {a,{b,c}} `".to_owned());
+	inputs.push("if a; for x in (while b; switch c; case d; function e; end) > f".to_owned());
+	for input in &inputs {
+		for (index, _) in input.char_indices() {
+			let _ = parse(input[..index].to_owned());
+			let _ = parse(input[index..].to_owned());
+			let _ = is_incomplete(&input[..index]);
+		}
+	}
+}
+
+// Each { starts a search for its }, which must not start the searches of the
+// braces inside of it again
+#[test]
+fn lexes_nested_braces_in_linear_searches() {
+	for braces in ["{", "{a,", "{{a,b}", "{a,{b}"] {
+		let input = format!("echo {}", braces.repeat(50));
+		parse_ok(&input);
+		parse_ok(&format!("{}{}", input, "}".repeat(50)));
+	}
+}
+
+#[test]
+fn flattens_nested_combinations() {
+	let item = combination(vec![
+		raw("a"),
+		combination(vec![raw("b"), combination(vec![raw("c")])]),
+		raw(""),
+	]).flatten();
+	match item.kind {
+		CommandItemKind::Combination(items) => {
+			let parts: Vec<_> = items.iter().map(ToString::to_string).collect();
+			assert_eq!(parts, ["a", "b", "c"]);
+		}
+		kind => panic!("expected a combination, found {:?}", kind),
+	}
+}
+
+#[test]
+fn normalizes_single_items() {
+	let item = combination(vec![combination(vec![raw("a")]), raw("")]).normalize();
+	assert!(matches!(item.kind, CommandItemKind::RawString(ref s) if s == "a"), "{:?}", item);
+
+	let empty = combination(vec![]).normalize();
+	assert!(matches!(empty.kind, CommandItemKind::Combination(ref items) if items.is_empty()));
+}
+
+
+#[test]
+fn serializes_spans() {
+	let json = serde_json::to_value(parse_ok("echo $x")).unwrap();
+	let items = &json["kind"]["Command"]["kind"]["Command"];
+	assert_eq!(items[0]["kind"]["RawString"], "echo");
+	assert_eq!(items[1]["kind"]["ShellVariable"], "x");
+	assert_eq!(items[1]["span"], serde_json::json!({ "start": 5, "end": 7 }));
+}
+
+#[test]
+fn parses_brace_ranges() {
+	assert!(matches!(parse_brace_range("5..1..-2"), Some(BraceExpansion::NumericRange { start: 5, end: 1, step: 2, width: 0 })));
+	assert!(matches!(parse_brace_range("01..10"), Some(BraceExpansion::NumericRange { width: 2, .. })));
+	assert!(matches!(parse_brace_range("a..e..0"), Some(BraceExpansion::CharacterRange { start: 'a', end: 'e', step: 1 })));
+	// Its step has no absolute value in i64
+	assert!(parse_brace_range("1..2..-9223372036854775808").is_none());
+	assert!(parse_brace_range("1..2..3..4").is_none());
+	assert!(parse_brace_range("ab..c").is_none());
+}
@@ -0,0 +1,123 @@
+use std::ffi::{CStr, CString};
+
+use crate::glob::GlobWord;
+
+/// Performs tilde expansion on a word.
+///
+/// An unquoted `~` at the start of the word, up to the first `/`, is replaced:
+/// - `~` by $HOME, or the home directory of the current user
+/// - `~user` by the home directory of user, from the passwd database
+/// - `~+` by the current directory
+/// - `~-` by the previous directory
+///
+/// In assignments (`NAME=value`), the same happens at the start of the value
+/// and after every `:` in it, so `PATH=~/bin:~/.cargo/bin` works.
+/// Prefixes that can't be resolved, like unknown users, are left alone.
+pub(crate) fn expand(word: &mut GlobWord) {
+	let chars = word.chars();
+	let prefix_starts = match assignment_value_start(chars) {
+		Some(value_start) => {
+			let mut starts = vec![value_start];
+			for (index, (c, active)) in chars.iter().enumerate().skip(value_start) {
+				if *c == ':' && *active {
+					starts.push(index + 1);
+				}
+			}
+			starts
+		}
+		None => vec![0],
+	};
+	let in_assignment = prefix_starts[0] != 0;
+
+	// Expand from the end, so that earlier indices stay valid
+	for start in prefix_starts.into_iter().rev() {
+		expand_at(word, start, in_assignment);
+	}
+}
+
+/// Returns the index right after the = of an assignment word
+fn assignment_value_start(chars: &[(char, bool)]) -> Option<usize> {
+	let equals = chars.iter().position(|(c, active)| *c == '=' && *active)?;
+	let name = &chars[..equals];
+	let is_name = !name.is_empty()
+		&& !name[0].0.is_ascii_digit()
+		&& name.iter().all(|(c, active)| *active && (c.is_ascii_alphanumeric() || *c == '_'));
+	if is_name { Some(equals + 1) } else { None }
+}
+
+fn expand_at(word: &mut GlobWord, start: usize, in_assignment: bool) {
+	let chars = word.chars();
+	if chars.get(start) != Some(&('~', true)) {
+		return;
+	}
+	let end = chars[start..]
+		.iter()
+		.position(|(c, _)| *c == '/' || (in_assignment && *c == ':'))
+		.map_or(chars.len(), |length| start + length);
+	let prefix = &chars[start + 1..end];
+	// A quoted character anywhere in the prefix disables the expansion
+	if prefix.iter().any(|(_, active)| !*active) {
+		return;
+	}
+	let prefix: String = prefix.iter().map(|(c, _)| c).collect();
+
+	let replacement = match prefix.as_str() {
+		"" => home_directory(),
+		"+" => std::env::var("PWD").ok().or_else(|| c_wrapper::cwd::getcwd().ok()),
+		"-" => std::env::var("OLDPWD").ok(),
+		user => user_home_directory(user),
+	};
+	if let Some(replacement) = replacement {
+		word.replace_range(start..end, &GlobWord::quoted(&replacement));
+	}
+}
+
+fn home_directory() -> Option<String> {
+	std::env::var("HOME").ok().or_else(|| {
+		// SAFETY: getuid can't fail
+		let uid = unsafe { libc::getuid() };
+		lookup_home_directory(|passwd, buffer, length, result| unsafe {
+			libc::getpwuid_r(uid, passwd, buffer, length, result)
+		})
+	})
+}
+
+/// The home directory of user in the passwd database, which goes through NSS
+/// and so also knows the users of LDAP, sssd or systemd-homed
+fn user_home_directory(user: &str) -> Option<String> {
+	let user = CString::new(user).ok()?;
+	lookup_home_directory(|passwd, buffer, length, result| unsafe {
+		libc::getpwnam_r(user.as_ptr(), passwd, buffer, length, result)
+	})
+}
+
+/// Looks up a passwd entry with getpwnam_r or getpwuid_r, given as lookup,
+/// growing the buffer for its strings until the entry fits
+fn lookup_home_directory<Lookup>(lookup: Lookup) -> Option<String>
+where Lookup: Fn(*mut libc::passwd, *mut libc::c_char, libc::size_t, *mut *mut libc::passwd) -> libc::c_int {
+	// SAFETY: passwd is plain data, which the lookup fills in
+	let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+	let mut result = std::ptr::null_mut();
+	let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+	loop {
+		let error = lookup(&mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result);
+		if error == libc::ERANGE && buffer.len() < MAX_PASSWD_BUFFER {
+			let length = buffer.len() * 2;
+			buffer.resize(length, 0);
+		}
+		else if error != 0 || result.is_null() || passwd.pw_dir.is_null() {
+			return None;
+		}
+		else {
+			// SAFETY: pw_dir points into buffer, which is still alive
+			let home = unsafe { CStr::from_ptr(passwd.pw_dir) };
+			return Some(home.to_string_lossy().into_owned());
+		}
+	}
+}
+
+// Bigger entries than this are taken as a broken database
+const MAX_PASSWD_BUFFER: usize = 1 << 20;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,30 @@
+use super::*;
+
+fn expanded(text: &str) -> String {
+	let mut word = GlobWord::unquoted(text);
+	expand(&mut word);
+	word.text()
+}
+
+#[test]
+fn looks_up_home_directories() {
+	assert_eq!(user_home_directory("root").as_deref(), Some("/root"));
+	assert_eq!(user_home_directory("kennsh_no_such_user"), None);
+	assert_eq!(user_home_directory("a\0b"), None);
+	assert_eq!(expanded("~root/a"), "/root/a");
+}
+
+#[test]
+fn leaves_unresolved_prefixes_alone() {
+	assert_eq!(expanded("~kennsh_no_such_user/a"), "~kennsh_no_such_user/a");
+	assert_eq!(expanded("a~"), "a~");
+	let mut quoted = GlobWord::quoted("~");
+	expand(&mut quoted);
+	assert_eq!(quoted.text(), "~");
+}
+
+#[test]
+fn expands_in_assignments() {
+	let home = home_directory().unwrap_or_else(|| "~".to_owned());
+	assert_eq!(expanded("PATH=~/bin:~/.cargo/bin"), format!("PATH={}/bin:{}/.cargo/bin", home, home));
+}
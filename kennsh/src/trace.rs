@@ -0,0 +1,298 @@
+//! Traces what the shell runs, like `set -x` in other shells: every command
+//! after expansion, with how deeply it is nested and what runs it, and if
+//! asked for, the syscalls made through `syscall!` with their results and how
+//! long they took.
+//!
+//! It is switched on with `~trace` or the `trace` setting, and is written to
+//! stderr or appended to the file in the `trace_file` setting.
+//!
+//! Syscalls can also be recorded, with the `record_syscalls` setting, in a
+//! buffer of the last ones made that `~syscalls` prints.
+
+use std::{cell::{Cell, RefCell}, collections::VecDeque, fmt::{Debug, Display}, io::Write, path::PathBuf, sync::atomic::{AtomicU8, AtomicUsize, Ordering}, time::{Duration, Instant}};
+
+use c_wrapper::fork::ForkResult;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::{self, Settings};
+
+// How many function calls and subcommands the running command is inside of
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+// What is traced or recorded as flags, kept from the settings in effect so
+// that commands and syscalls don't have to look them up
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+const COMMANDS_TRACED: u8 = 1;
+const SYSCALLS_TRACED: u8 = 2;
+const SYSCALLS_RECORDED: u8 = 4;
+const NAME_BYTES_TRACED: u8 = 8;
+
+/// Updates what is traced to the settings in effect, whenever they or the
+/// variables overriding them change
+pub(crate) fn refresh(settings: &Settings) {
+	let mut active = 0;
+	if settings.trace || settings.who_is_running || settings.who_is_running_ext {
+		active |= COMMANDS_TRACED;
+	}
+	if settings.who_is_running_ext {
+		active |= NAME_BYTES_TRACED;
+	}
+	if settings.trace && settings.trace_syscalls {
+		active |= SYSCALLS_TRACED;
+	}
+	if settings.record_syscalls {
+		active |= SYSCALLS_RECORDED;
+	}
+	ACTIVE.store(active, Ordering::Relaxed);
+}
+
+fn is_active(flag: u8) -> bool {
+	ACTIVE.load(Ordering::Relaxed) & flag != 0
+}
+
+/// What runs a command
+pub(crate) enum Runner {
+	Builtin,
+	Function,
+	// The file found in $PATH, if there is one
+	External(Option<PathBuf>),
+}
+
+impl Display for Runner {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Runner::Builtin => write!(f, "builtin"),
+			Runner::Function => write!(f, "function"),
+			Runner::External(Some(path)) => write!(f, "external {}", path.display()),
+			Runner::External(None) => write!(f, "external, not found in $PATH"),
+		}
+	}
+}
+
+/// Goes one level deeper, when a function call or a subcommand starts
+pub(crate) fn enter() {
+	DEPTH.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn leave() {
+	DEPTH.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Traces a command about to run, finding out what runs it only if it is
+/// traced
+pub(crate) fn command(words: &[String], runner: impl FnOnce() -> Runner) {
+	if !is_active(COMMANDS_TRACED) {
+		return;
+	}
+	let settings = settings::get();
+	let runner = runner();
+	let quoted_words: Vec<String> = words.iter().map(|word| quoted(word)).collect();
+	write(&settings, &format!("{} {}  # {}", prefix(), quoted_words.join(" "), runner));
+	// Shows what can't be seen in the name, like control characters
+	if let (Runner::External(_), true, Some(name)) = (&runner, is_active(NAME_BYTES_TRACED), words.first()) {
+		write(&settings, &format!("{} name bytes {}", prefix(), name_bytes(name)));
+	}
+}
+
+/// The bytes of name in hex
+fn name_bytes(name: &str) -> String {
+	let bytes: Vec<String> = name.bytes().map(|byte| format!("{:02x}", byte)).collect();
+	bytes.join(" ")
+}
+
+/// A syscall being made through `syscall!`, traced and recorded when it
+/// finishes
+pub(crate) struct Syscall {
+	name: &'static str,
+	arguments: Vec<String>,
+	start: Instant,
+	traced: bool,
+	recorded: bool,
+}
+
+impl Syscall {
+	/// Starts timing the syscall called name, or None if it is neither
+	/// traced nor recorded. arguments describes what it is called with.
+	pub(crate) fn start(name: &'static str, arguments: impl FnOnce() -> Vec<String>) -> Option<Syscall> {
+		let traced = is_active(SYSCALLS_TRACED);
+		let recorded = is_active(SYSCALLS_RECORDED);
+		if !traced && !recorded {
+			return None;
+		}
+		Some(Syscall {
+			name,
+			arguments: arguments(),
+			start: Instant::now(),
+			traced,
+			recorded,
+		})
+	}
+
+	/// Finishes the syscall with its result, described like its arguments
+	pub(crate) fn finish(self, result: Result<String, String>) {
+		let record = SyscallRecord {
+			// stringify! spaces out paths like wait :: waitpid
+			name: self.name.replace(' ', ""),
+			arguments: self.arguments,
+			result,
+			elapsed: self.start.elapsed(),
+		};
+		if self.traced {
+			write(&settings::get(), &format!("{} syscall {}", prefix(), record));
+		}
+		if self.recorded {
+			keep(record);
+		}
+	}
+}
+
+/// A finished syscall
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct SyscallRecord {
+	pub name: String,
+	pub arguments: Vec<String>,
+	pub result: Result<String, String>,
+	pub elapsed: Duration,
+}
+
+impl Display for SyscallRecord {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let result = match &self.result {
+			Ok(value) => value,
+			Err(error) => error,
+		};
+		write!(f, "{}({}) -> {} in {:?}", self.name, self.arguments.join(", "), result, self.elapsed)
+	}
+}
+
+const RECENT_SYSCALLS_KEPT: usize = 256;
+
+thread_local! {
+	// The last syscalls made, when record_syscalls is on. Forked commands
+	// start with a copy of the syscalls of the shell, and send the ones they
+	// make back to it with their result.
+	static RECENT_SYSCALLS: RefCell<VecDeque<SyscallRecord>> = RefCell::new(VecDeque::with_capacity(RECENT_SYSCALLS_KEPT));
+	// How many syscalls were ever recorded, to tell which ones are new
+	static RECORDED_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+fn keep(record: SyscallRecord) {
+	RECENT_SYSCALLS.with(|recent| {
+		let mut recent = recent.borrow_mut();
+		if recent.len() == RECENT_SYSCALLS_KEPT {
+			recent.pop_front();
+		}
+		recent.push_back(record);
+	});
+	RECORDED_COUNT.with(|count| count.set(count.get() + 1));
+}
+
+/// How many syscalls were recorded so far, for [syscalls_recorded_since]
+pub(crate) fn recorded_count() -> usize {
+	RECORDED_COUNT.with(Cell::get)
+}
+
+/// The syscalls recorded after recorded_count() was count, as far as they
+/// are still kept
+pub(crate) fn syscalls_recorded_since(count: usize) -> Vec<SyscallRecord> {
+	recent_syscalls(recorded_count().saturating_sub(count))
+}
+
+/// Records the syscalls a forked command made, as if they were made here
+pub(crate) fn keep_syscalls(records: Vec<SyscallRecord>) {
+	for record in records {
+		keep(record);
+	}
+}
+
+/// The last count syscalls recorded, from the oldest
+pub(crate) fn recent_syscalls(count: usize) -> Vec<SyscallRecord> {
+	RECENT_SYSCALLS.with(|recent| {
+		let recent = recent.borrow();
+		recent.iter().skip(recent.len().saturating_sub(count)).cloned().collect()
+	})
+}
+
+/// Wraps a value given to or returned by a syscall, to describe it with
+/// Debug if it has it, as not everything from c_wrapper does. Other values
+/// are described by their type.
+/// `(&Described(&value)).describe()` picks [DescribeDebug] over
+/// [DescribeOther] when both traits are in scope.
+pub(crate) struct Described<'a, T: ?Sized>(pub &'a T);
+
+pub(crate) trait DescribeDebug {
+	fn describe(&self) -> String;
+}
+
+impl<T: Debug + ?Sized> DescribeDebug for Described<'_, T> {
+	fn describe(&self) -> String {
+		format!("{:?}", self.0)
+	}
+}
+
+pub(crate) trait DescribeOther {
+	fn describe(&self) -> String;
+}
+
+impl<T: ?Sized> DescribeOther for &Described<'_, T> {
+	fn describe(&self) -> String {
+		type_name::<T>()
+	}
+}
+
+// Which side of the fork a record was made on matters more than the type
+impl Described<'_, ForkResult> {
+	pub(crate) fn describe(&self) -> String {
+		match self.0 {
+			ForkResult::Child => "Child".to_owned(),
+			ForkResult::Parent(pid) => format!("Parent({:?})", pid),
+		}
+	}
+}
+
+/// The name of T without the paths of the types in it, like
+/// `&mut FileDescriptor`
+fn type_name<T: ?Sized>() -> String {
+	let name = std::any::type_name::<T>();
+	let mut result = String::new();
+	for (index, part) in name.split("::").enumerate() {
+		// Each part but the last ends with the module a type is in
+		let is_module = index < name.matches("::").count();
+		let kept = if is_module { part.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_') } else { part };
+		result.push_str(kept);
+	}
+	result
+}
+
+/// A + for every level, like `set -x` does
+fn prefix() -> String {
+	"+".repeat(DEPTH.load(Ordering::Relaxed) + 1)
+}
+
+/// word, quoted if it wouldn't be read back as one word
+fn quoted(word: &str) -> String {
+	let plain = !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || "\"'`$;|<>(){}#*?~".contains(c));
+	if plain {
+		word.to_owned()
+	}
+	else {
+		crate::rc::quote(word)
+	}
+}
+
+fn write(settings: &Settings, line: &str) {
+	if settings.trace_file.is_empty() {
+		eprintln!("{}", line);
+		return;
+	}
+	let file = std::fs::OpenOptions::new().create(true).append(true).open(&settings.trace_file);
+	match file {
+		Ok(mut file) => {
+			let _ = writeln!(file, "{}", line);
+		}
+		Err(error) => eprintln!("\x1b[3mkennsh: The trace file could not be opened:\x1b[0m {}: {}", settings.trace_file, error),
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,106 @@
+use super::*;
+
+#[test]
+fn quotes_words_that_need_it() {
+	assert_eq!(quoted("echo"), "echo");
+	assert_eq!(quoted("/usr/bin/ls"), "/usr/bin/ls");
+	assert_eq!(quoted("a b"), crate::rc::quote("a b"));
+	assert_eq!(quoted(""), crate::rc::quote(""));
+	assert_eq!(quoted("$x"), crate::rc::quote("$x"));
+}
+
+#[test]
+fn describes_runners() {
+	assert_eq!(Runner::Builtin.to_string(), "builtin");
+	assert_eq!(Runner::Function.to_string(), "function");
+	assert_eq!(Runner::External(Some("/bin/ls".into())).to_string(), "external /bin/ls");
+	assert_eq!(Runner::External(None).to_string(), "external, not found in $PATH");
+}
+
+#[test]
+fn appends_to_the_trace_file() {
+	let path = std::env::temp_dir().join(format!("kennsh_trace_test_{}", std::process::id()));
+	let settings = Settings { trace_file: path.to_string_lossy().into_owned(), ..Settings::default() };
+	write(&settings, "+ first");
+	write(&settings, "+ second");
+	let text = std::fs::read_to_string(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(text, "+ first\n+ second\n");
+}
+
+#[test]
+fn keeps_the_last_syscalls() {
+	for index in 0..RECENT_SYSCALLS_KEPT + 3 {
+		let syscall = Syscall {
+			name: "wait :: waitpid",
+			arguments: vec![index.to_string()],
+			start: Instant::now(),
+			traced: false,
+			recorded: true,
+		};
+		syscall.finish(if index % 2 == 0 { Ok("()".to_owned()) } else { Err("NotFound".to_owned()) });
+	}
+	let recent = recent_syscalls(2);
+	assert_eq!(recent.len(), 2);
+	assert_eq!(recent[0].name, "wait::waitpid");
+	assert_eq!(recent[0].arguments, [(RECENT_SYSCALLS_KEPT + 1).to_string()]);
+	assert_eq!(recent[1].result, Ok("()".to_owned()));
+	assert_eq!(recent_syscalls(usize::MAX).len(), RECENT_SYSCALLS_KEPT);
+	assert_eq!(recent_syscalls(usize::MAX)[0].arguments, ["3"]);
+}
+
+#[test]
+fn describes_values_without_debug() {
+	struct Opaque;
+	assert_eq!((&Described(&"a\nb")).describe(), "\"a\\nb\"");
+	assert_eq!((&Described(&Opaque)).describe(), "Opaque");
+	let record = SyscallRecord {
+		name: "chdir".to_owned(),
+		arguments: vec!["\"/tmp\"".to_owned(), "Opaque".to_owned()],
+		result: Err("NotFound".to_owned()),
+		elapsed: Duration::from_micros(3),
+	};
+	assert_eq!(record.to_string(), "chdir(\"/tmp\", Opaque) -> NotFound in 3µs");
+}
+
+#[test]
+fn starts_syscalls_only_when_asked_to() {
+	let start = || Syscall::start("chdir", || vec![]).map(|syscall| (syscall.traced, syscall.recorded));
+	refresh(&Settings { trace: true, ..Settings::default() });
+	assert_eq!(start(), None);
+	refresh(&Settings { trace: true, trace_syscalls: true, ..Settings::default() });
+	assert_eq!(start(), Some((true, false)));
+	refresh(&Settings { trace_syscalls: true, record_syscalls: true, ..Settings::default() });
+	assert_eq!(start(), Some((false, true)));
+	refresh(&Settings::default());
+	assert_eq!(start(), None);
+}
+
+#[test]
+fn shows_the_bytes_of_names() {
+	assert_eq!(name_bytes("ls"), "6c 73");
+	assert_eq!(name_bytes("l\u{7}s"), "6c 07 73");
+	assert_eq!(name_bytes(""), "");
+}
+
+#[test]
+fn describes_values_by_their_type_without_debug() {
+	struct Opaque;
+	assert_eq!((&Described(&vec![Opaque])).describe(), "Vec<Opaque>");
+	assert_eq!((&Described(&&mut Opaque)).describe(), "&mut Opaque");
+	assert_eq!((&Described(&ForkResult::Child)).describe(), "Child");
+}
+
+#[test]
+fn sends_syscalls_recorded_since_a_count() {
+	let count = recorded_count();
+	let record = SyscallRecord {
+		name: "pipe".to_owned(),
+		arguments: vec![],
+		result: Ok("Pipe".to_owned()),
+		elapsed: Duration::from_micros(1),
+	};
+	keep_syscalls(vec![record.clone(), record.clone()]);
+	assert_eq!(syscalls_recorded_since(count), [record.clone(), record]);
+	assert_eq!(syscalls_recorded_since(recorded_count()), []);
+}
@@ -0,0 +1,121 @@
+//! Shell variables, which are stored in the environment so that they survive
+//! the fork of every command.
+//!
+//! A variable is either a plain string or a list of strings. Lists are stored
+//! as their elements, each preceded by an ASCII record separator, so the
+//! empty list is a lone separator. Path variables, whose name ends in PATH,
+//! are always lists, and are stored joined by `:` as other programs expect.
+//! Other programs see the rest of the lists joined by spaces, see
+//! [export_lists].
+
+use crate::syntax_parser::ListIndex;
+
+static LIST_SEPARATOR: char = '\x1e';
+
+pub(crate) fn is_path_variable(name: &str) -> bool {
+	name.ends_with("PATH")
+}
+
+/// Encodes a list of elements into the value stored in the environment
+pub(crate) fn encode_list(name: &str, elements: &[String]) -> String {
+	if is_path_variable(name) {
+		elements.join(":")
+	}
+	else {
+		elements.iter().map(|element| LIST_SEPARATOR.to_string() + element).collect()
+	}
+}
+
+fn is_list_value(name: &str, value: &str) -> bool {
+	is_path_variable(name) || value.starts_with(LIST_SEPARATOR)
+}
+
+fn decode(name: &str, value: &str) -> Vec<String> {
+	if is_path_variable(name) {
+		if value.is_empty() { vec![] } else { value.split(':').map(str::to_owned).collect() }
+	}
+	else if let Some(elements) = value.strip_prefix(LIST_SEPARATOR) {
+		elements.split(LIST_SEPARATOR).map(str::to_owned).collect()
+	}
+	else {
+		vec![value.to_owned()]
+	}
+}
+
+/// The value programs run by the shell see for a variable, where the
+/// elements of lists are joined by spaces
+pub(crate) fn exported_value(name: &str, value: &str) -> String {
+	if !is_path_variable(name) && is_list_value(name, value) {
+		decode(name, value).join(" ")
+	}
+	else {
+		value.to_owned()
+	}
+}
+
+/// Replaces the lists in the environment by their exported values, in the
+/// child that is about to run another program
+pub(crate) fn export_lists() {
+	for (name, value) in std::env::vars_os() {
+		if let (Some(name), Some(value)) = (name.to_str(), value.to_str()) {
+			if value.starts_with(LIST_SEPARATOR) {
+				std::env::set_var(name, exported_value(name, value));
+			}
+		}
+	}
+}
+
+/// Whether name is set to a list rather than a plain string
+pub(crate) fn is_list(name: &str) -> bool {
+	std::env::var(name).map_or(false, |value| is_list_value(name, &value))
+}
+
+/// Returns the elements of a variable; a plain string is a list of one
+pub(crate) fn get_list(name: &str) -> Option<Vec<String>> {
+	let value = std::env::var(name).ok()?;
+	if value.len() == 1 && value.starts_with(LIST_SEPARATOR) {
+		return Some(vec![]);
+	}
+	Some(decode(name, &value))
+}
+
+/// Returns the value of a variable as a single string, joining the elements
+/// of lists with spaces
+pub(crate) fn get_joined(name: &str) -> Option<String> {
+	get_list(name).map(|elements| elements.join(" "))
+}
+
+/// Resolves a 1-based index, where negative indices count from the end, to
+/// a 0-based one, which may lie outside of the list
+fn resolve_index(index: i64, count: usize) -> i64 {
+	if index < 0 { count as i64 + index } else { index - 1 }
+}
+
+/// Returns the 0-based positions selected by an index, in order.
+///
+/// Missing range ends default to the first and last element, and a range
+/// whose start lies after its end selects the elements in reverse.
+/// Indices outside of the list select nothing.
+pub(crate) fn positions(count: usize, index: &ListIndex) -> Vec<usize> {
+	let (first, last) = match *index {
+		ListIndex::Single(i) => (resolve_index(i, count), resolve_index(i, count)),
+		ListIndex::Range { start, end } => (
+			resolve_index(start.unwrap_or(1), count),
+			resolve_index(end.unwrap_or(-1), count),
+		),
+	};
+	let (low, high) = (first.min(last).max(0), first.max(last).min(count as i64 - 1));
+	if low > high {
+		return vec![];
+	}
+	let positions = (low as usize)..=(high as usize);
+	if first <= last { positions.collect() } else { positions.rev().collect() }
+}
+
+/// Selects elements by an index
+pub(crate) fn slice(elements: &[String], index: &ListIndex) -> Vec<String> {
+	positions(elements.len(), index).into_iter().map(|i| elements[i].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests;
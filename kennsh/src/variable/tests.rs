@@ -0,0 +1,32 @@
+use super::*;
+
+fn list(elements: &[&str]) -> Vec<String> {
+	elements.iter().map(|element| element.to_string()).collect()
+}
+
+#[test]
+fn encodes_lists() {
+	let elements = list(&["a b", "", "c"]);
+	let encoded = encode_list("xs", &elements);
+	assert_eq!(encoded, "\x1ea b\x1e\x1ec");
+	assert_eq!(decode("xs", &encoded), elements);
+	assert_eq!(encode_list("MANPATH", &list(&["/a", "/b"])), "/a:/b");
+	assert_eq!(decode("MANPATH", ""), Vec::<String>::new());
+}
+
+#[test]
+fn exports_lists_joined() {
+	assert_eq!(exported_value("argv", "\x1ea\x1eb c"), "a b c");
+	assert_eq!(exported_value("xs", "\x1e"), "");
+	assert_eq!(exported_value("x", "plain"), "plain");
+	assert_eq!(exported_value("PATH", "/bin:/usr/bin"), "/bin:/usr/bin");
+}
+
+#[test]
+fn selects_positions() {
+	assert_eq!(positions(4, &ListIndex::Single(-1)), [3]);
+	assert_eq!(positions(4, &ListIndex::Range { start: Some(3), end: Some(2) }), [2, 1]);
+	assert_eq!(positions(4, &ListIndex::Range { start: None, end: Some(2) }), [0, 1]);
+	assert!(positions(4, &ListIndex::Single(5)).is_empty());
+	assert!(positions(0, &ListIndex::Range { start: None, end: None }).is_empty());
+}
@@ -1,14 +1,20 @@
 mod no_wrap;
 mod match_error;
+mod retry;
 
 extern crate proc_macro;
-use quote::{ToTokens, quote, quote_spanned};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{Arm, Expr, ExprPath, Ident, braced, parenthesized, parse::{Parse, ParseStream, Parser}, punctuated::Punctuated};
 
 struct Syscall();
 
 impl Syscall {
 	fn parse(stream: ParseStream<'_>) -> syn::Result<proc_macro2::TokenStream> {
+		// retry is only an option if the syscall comes after it
+		let retry = stream.peek(crate::retry::retry) && stream.peek2(Ident);
+		if retry {
+			stream.parse::<crate::retry::retry>()?;
+		}
 		let syscall_fn: ExprPath = stream.parse()?;
 		let mut syscall_params = None;
 		if stream.peek(syn::token::Paren) {
@@ -28,20 +34,23 @@ impl Syscall {
 				quote!{ __ok },
 				quote!{ Ok(__ok) },
 				([] as [&str; 0]).iter(),
+				retry,
 			))
 		}
 		else if stream.peek(syn::Token![;]) {
 			Self::parse_arrow_2(
 				&stream,
 				syscall_fn, 
-				syscall_params.into_iter()
+				syscall_params,
+				retry,
 			)
 		}
 		else if stream.peek(syn::Token![match]) {
 			Self::parse_match_2(
 				&stream,
 				syscall_fn, 
-				syscall_params.into_iter()
+				syscall_params,
+				retry,
 			)
 		}
 		else {
@@ -52,13 +61,13 @@ impl Syscall {
 		}
 	}
 
-	fn parse_arrow_2<TT1, TT2Item, TT2>(
+	fn parse_arrow_2<TT1, TT2>(
 		stream: &ParseStream<'_>,
 		syscall_fn: TT1, 
-		syscall_params: TT2) -> syn::Result<proc_macro2::TokenStream>
+		syscall_params: TT2,
+		retry: bool) -> syn::Result<proc_macro2::TokenStream>
 		where TT1: ToTokens,
-			TT2Item: ToTokens,
-			TT2: IntoIterator<Item= TT2Item> {
+			TT2: IntoIterator<Item= Expr> {
 		stream.parse::<syn::Token!(;)>()?;
 		let ok_expression = if !stream.peek(crate::match_error::match_error) {
 			let (ok_name, nowrap) = if stream.peek2(syn::Token![=>]) {
@@ -93,18 +102,7 @@ impl Syscall {
 		else {
 			(quote!(__ok), quote!(Ok(__ok)))
 		};
-		let custom_error_arms = if let Ok(_) = stream.parse::<crate::match_error::match_error>() {
-			let tmp;
-			braced!(tmp in stream);
-			let mut arms: Vec<Arm> = vec![];
-			while !tmp.is_empty() {
-				let parsed = tmp.parse()?;
-				arms.push(parsed);
-			}
-			arms
-		} else {
-			vec![]
-		};
+		let custom_error_arms = Self::parse_error_arms(stream)?;
 		
 		Ok(gen_arrow(
 			syscall_fn, 
@@ -112,16 +110,17 @@ impl Syscall {
 			ok_expression.0, 
 			ok_expression.1, 
 			custom_error_arms.into_iter(),
+			retry,
 		))
 	}
 
-	fn parse_match_2<TT1, TT2Item, TT2>(
+	fn parse_match_2<TT1, TT2>(
 		stream: &ParseStream<'_>,
 		syscall_fn: TT1, 
-		syscall_params: TT2) -> syn::Result<proc_macro2::TokenStream>
+		syscall_params: TT2,
+		retry: bool) -> syn::Result<proc_macro2::TokenStream>
 		where TT1: ToTokens,
-			TT2Item: ToTokens,
-			TT2: IntoIterator<Item= TT2Item> {
+			TT2: IntoIterator<Item= Expr> {
 		stream.parse::<syn::Token![match]>()?;
 		let nowrap = match stream.parse::<crate::no_wrap::no_wrap>() {
 			Ok(_) => true,
@@ -151,18 +150,7 @@ impl Syscall {
 				#( #match_block_contents )*
 			}
 		};
-		let custom_error_arms = if let Ok(_) = stream.parse::<crate::match_error::match_error>() {
-			let tmp;
-			braced!(tmp in stream);
-			let mut arms: Vec<Arm> = vec![];
-			while !tmp.is_empty() {
-				let parsed = tmp.parse()?;
-				arms.push(parsed);
-			}
-			arms
-		} else {
-		    vec![]
-		};
+		let custom_error_arms = Self::parse_error_arms(stream)?;
 
 		Ok(
 			gen_arrow(
@@ -177,40 +165,121 @@ impl Syscall {
 					}
 				},
 				custom_error_arms.into_iter(),
+				retry,
 			)
 		)
 	}
+	/// Parses the arms after match_error, either in braces or made by a
+	/// profile like file(path), if there is a match_error
+	fn parse_error_arms(stream: &ParseStream<'_>) -> syn::Result<Vec<proc_macro2::TokenStream>> {
+		if stream.parse::<crate::match_error::match_error>().is_err() {
+			return Ok(vec![]);
+		}
+		if stream.peek(syn::token::Brace) {
+			let tmp;
+			braced!(tmp in stream);
+			let mut arms = vec![];
+			while !tmp.is_empty() {
+				let arm: Arm = tmp.parse()?;
+				arms.push(if arm.comma.is_some() { quote! { #arm } } else { quote! { #arm, } });
+			}
+			Ok(arms)
+		}
+		else {
+			let name: Ident = stream.parse()?;
+			let tmp;
+			parenthesized!(tmp in stream);
+			let argument: Expr = tmp.parse()?;
+			Ok(vec![crate::match_error::profile(&name, &argument)?])
+		}
+	}
 }
 
-fn gen_arrow<TT1, TmpTT1, TT2, TT3, TT4, TmpTT2, TT5>(
+fn gen_arrow<TT1, TT2, TT3, TT4, TmpTT2, TT5>(
 	syscall_fn: TT1, 
 	syscall_params: TT2, 
 	ok_name: TT3, 
 	expression: TT4,
-	custom_error_arms: TT5) -> proc_macro2::TokenStream 
+	custom_error_arms: TT5,
+	retry: bool) -> proc_macro2::TokenStream 
 	where TT1: ToTokens,
-		TmpTT1: ToTokens,
-		TT2: Iterator<Item=TmpTT1>,
+		TT2: Iterator<Item=Expr>,
 		TT3: ToTokens,
 		TT4: ToTokens,
 		TmpTT2: ToTokens,
 		TT5: Iterator<Item=TmpTT2> {
-	quote! {
-		match #syscall_fn (
-			#( #syscall_params ),*
-		) {
-			Ok(#ok_name) => #expression,
-			Err(err) => Err(match err { 
-				#( #custom_error_arms )*
-				err => crate::error::Error::SyscallError {
-					call_name: stringify!(#syscall_fn).to_string(),
-					error: err,
+	// The arguments are bound to locals before the call, so that the trace
+	// module of the crate using the macro can describe them
+	let syscall_params: Vec<Expr> = syscall_params.collect();
+	let argument_names: Vec<Ident> = (0..syscall_params.len()).map(|index| format_ident!("__argument_{}", index)).collect();
+	// References are described by what they point to, which is what has Debug
+	let described_arguments = syscall_params.iter().zip(&argument_names).map(|(param, name)| match param {
+		Expr::Reference(_) => quote!( crate::trace::Described(&*#name) ),
+		_ => quote!( crate::trace::Described(&#name) ),
+	});
+	let call = quote! {
+		#( let #argument_names = #syscall_params; )*
+		let __syscall = crate::trace::Syscall::start(stringify!(#syscall_fn), || vec![
+			#( (&#described_arguments).describe() ),*
+		]);
+		let __result = #syscall_fn (
+			#( #argument_names ),*
+		);
+		if let Some(syscall) = __syscall {
+			syscall.finish(match &__result {
+				Ok(value) => Ok((&crate::trace::Described(value)).describe()),
+				Err(error) => Err((&crate::trace::Described(error)).describe()),
+			});
+		}
+	};
+	// Interrupted calls are made again, with the arguments evaluated again,
+	// as the call may have taken them by value
+	let call = if retry {
+		quote! {
+			let __result = loop {
+				#call
+				match __result {
+					Err(c_wrapper::c_error::CError::Interrupted) => continue,
+					result => break result,
 				}
-			})
+			};
+		}
+	}
+	else {
+		call
+	};
+	quote! {
+		{
+			use crate::trace::{DescribeDebug as _, DescribeOther as _};
+			#call
+			match __result {
+				Ok(#ok_name) => #expression,
+				Err(err) => Err(match err { 
+					#( #custom_error_arms )*
+					err => crate::error::Error::SyscallError {
+						call_name: stringify!(#syscall_fn).to_string(),
+						error: err,
+					}
+				})
+			}
 		}
 	}
 }
 
+/// Makes a syscall, wrapping its error in `crate::error::Error::SyscallError`
+///
+/// - `syscall!(f(args))` is the result of the call
+/// - `syscall!(f(args); expr)` maps the value, called `it` in expr or `name`
+///   in `; name => expr`, and `no_wrap` before expr leaves it out of `Ok`
+/// - `syscall!(f(args) match { arms })` matches on the value
+/// - `match_error { arms }` after either maps some errors differently, and
+///   `match_error file(path)` maps them with the file profile, to
+///   `FileNotFound`, `FilePermissionDenied` and `IsADirectory` about path
+/// - `retry` before f makes the call again while it is interrupted, which
+///   evaluates the arguments again for every attempt
+///
+/// Calls go through `crate::trace`, which describes the arguments with Debug
+/// if they have it, so their types have to be known where the macro is used.
 #[proc_macro]
 pub fn syscall(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	// match None.ok_or(
@@ -225,3 +294,6 @@ pub fn syscall(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
 		Err(err) => proc_macro::TokenStream::from(err.to_compile_error())
 	}
 }
+
+#[cfg(test)]
+mod tests;
@@ -1 +1,17 @@
+use quote::quote;
+use syn::{Expr, Ident};
+
 syn::custom_keyword!(match_error);
+
+/// The arms of the error profile called name, which map the errors of a
+/// syscall about argument to the errors of the shell
+pub(crate) fn profile(name: &Ident, argument: &Expr) -> syn::Result<proc_macro2::TokenStream> {
+	match name.to_string().as_str() {
+		"file" => Ok(quote! {
+			c_wrapper::c_error::CError::NotFound => crate::error::Error::FileNotFound(Some(::std::string::ToString::to_string(&#argument))),
+			c_wrapper::c_error::CError::PermissionDenied => crate::error::Error::FilePermissionDenied(Some(::std::string::ToString::to_string(&#argument))),
+			c_wrapper::c_error::CError::IsADirectory => crate::error::Error::IsADirectory(Some(::std::string::ToString::to_string(&#argument))),
+		}),
+		_ => Err(syn::Error::new(name.span(), format!("There is no error profile called {}, expected file(PATH)", name))),
+	}
+}
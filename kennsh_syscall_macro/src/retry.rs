@@ -0,0 +1 @@
+syn::custom_keyword!(retry);
@@ -0,0 +1,37 @@
+use quote::quote;
+
+use super::*;
+
+fn expand(input: proc_macro2::TokenStream) -> syn::Result<String> {
+	Syscall::parse.parse2(input).map(|expansion| expansion.to_string())
+}
+
+#[test]
+fn retries_only_when_asked() {
+	assert!(expand(quote!(retry wait::waitpid(pid))).unwrap().contains("Interrupted"));
+	assert!(!expand(quote!(wait::waitpid(pid))).unwrap().contains("Interrupted"));
+	// A syscall called retry
+	let expansion = expand(quote!(retry)).unwrap();
+	assert!(expansion.contains("retry ()") && !expansion.contains("loop"));
+}
+
+#[test]
+fn rejects_unknown_profiles() {
+	let error = expand(quote!(open(path); match_error directory(path))).unwrap_err();
+	assert_eq!(error.to_string(), "There is no error profile called directory, expected file(PATH)");
+	assert!(expand(quote!(open(path); match_error file)).is_err());
+}
+
+#[test]
+fn evaluates_arguments_once_without_retry() {
+	let expansion = expand(quote!(open(make_path(), 1))).unwrap();
+	assert_eq!(expansion.matches("make_path ()").count(), 1);
+	assert!(expansion.contains("open (__argument_0 , __argument_1)"));
+}
+
+#[test]
+fn evaluates_arguments_in_every_attempt_with_retry() {
+	let expansion = expand(quote!(retry open(make_path(), 1))).unwrap();
+	let attempt = &expansion[expansion.find("loop {").unwrap()..];
+	assert!(attempt.contains("let __argument_0 = make_path () ;"));
+}
@@ -0,0 +1,207 @@
+//! Compiles every form of syscall! against stand-ins for what it uses from
+//! the crate calling it, and checks what the expansions do
+
+use std::cell::{Cell, RefCell};
+
+use kennsh_syscall_macro::syscall;
+
+mod c_wrapper {
+	pub mod c_error {
+		#[derive(Debug, PartialEq)]
+		pub enum CError {
+			NotFound,
+			PermissionDenied,
+			IsADirectory,
+			Interrupted,
+			Other(i32),
+		}
+	}
+}
+
+mod error {
+	// Named like the errors of kennsh, which the macro expands to
+	#[allow(clippy::enum_variant_names)]
+	#[derive(Debug, PartialEq)]
+	pub enum Error {
+		SyscallError { call_name: String, error: crate::c_wrapper::c_error::CError },
+		FileNotFound(Option<String>),
+		FilePermissionDenied(Option<String>),
+		IsADirectory(Option<String>),
+		OtherError(String),
+	}
+}
+
+mod trace {
+	use std::{cell::RefCell, fmt::Debug};
+
+	// A syscall finished, with its arguments and result
+	pub type Finished = (&'static str, Vec<String>, Result<String, String>);
+
+	thread_local! {
+		pub static FINISHED: RefCell<Vec<Finished>> = const { RefCell::new(Vec::new()) };
+	}
+
+	pub struct Syscall {
+		name: &'static str,
+		arguments: Vec<String>,
+	}
+
+	impl Syscall {
+		pub fn start(name: &'static str, arguments: impl FnOnce() -> Vec<String>) -> Option<Syscall> {
+			Some(Syscall { name, arguments: arguments() })
+		}
+
+		pub fn finish(self, result: Result<String, String>) {
+			FINISHED.with(|finished| finished.borrow_mut().push((self.name, self.arguments, result)));
+		}
+	}
+
+	pub struct Described<'a, T: ?Sized>(pub &'a T);
+
+	pub trait DescribeDebug {
+		fn describe(&self) -> String;
+	}
+
+	impl<T: Debug + ?Sized> DescribeDebug for Described<'_, T> {
+		fn describe(&self) -> String {
+			format!("{:?}", self.0)
+		}
+	}
+
+	pub trait DescribeOther {
+		fn describe(&self) -> String;
+	}
+
+	impl<T: ?Sized> DescribeOther for &Described<'_, T> {
+		fn describe(&self) -> String {
+			"_".to_owned()
+		}
+	}
+}
+
+use c_wrapper::c_error::CError;
+use error::Error;
+
+fn add(a: i32, b: i32) -> Result<i32, CError> {
+	Ok(a + b)
+}
+
+fn fail(error: CError) -> Result<(), CError> {
+	Err(error)
+}
+
+fn open(_path: &str) -> Result<(), CError> {
+	Err(CError::IsADirectory)
+}
+
+// Fails with Interrupted until it was called times times
+fn interrupted(calls: &Cell<u32>, times: u32) -> Result<u32, CError> {
+	calls.set(calls.get() + 1);
+	if calls.get() < times { Err(CError::Interrupted) } else { Ok(calls.get()) }
+}
+
+fn retry() -> Result<u8, CError> {
+	Ok(7)
+}
+
+struct Opaque;
+
+fn take(_opaque: &mut Opaque, _other: Opaque, _bytes: &[u8]) -> Result<(), CError> {
+	Ok(())
+}
+
+fn finished() -> Vec<trace::Finished> {
+	trace::FINISHED.with(|finished| finished.borrow_mut().drain(..).collect())
+}
+
+#[test]
+fn wraps_values_and_errors() {
+	assert_eq!(syscall!(add(1, 2)), Ok::<_, Error>(3));
+	assert_eq!(syscall!(add(1, 2); it * 2), Ok::<_, Error>(6));
+	assert_eq!(syscall!(add(1, 2); sum => sum + 1), Ok::<_, Error>(4));
+	assert_eq!(syscall!(add(1, 2); no_wrap Ok::<_, Error>(it > 2)), Ok(true));
+	assert_eq!(syscall!(retry), Ok::<_, Error>(7));
+	assert_eq!(syscall!(fail(CError::Other(5))), Err(Error::SyscallError { call_name: "fail".to_owned(), error: CError::Other(5) }));
+}
+
+#[test]
+fn matches_values() {
+	let described: Result<&str, Error> = syscall!(add(1, 2) match {
+		3 => "three",
+		_ => "something else"
+	});
+	assert_eq!(described, Ok("three"));
+	let matched: Result<u8, Error> = syscall!(add(1, 1) match no_wrap {
+		2 => Ok(2),
+		_ => Err(Error::OtherError("not 2".to_owned())),
+	});
+	assert_eq!(matched, Ok(2));
+}
+
+#[test]
+fn maps_errors() {
+	let mapped: Result<(), Error> = syscall!(fail(CError::NotFound); match_error {
+		CError::NotFound => Error::OtherError("gone".to_owned())
+	});
+	assert_eq!(mapped, Err(Error::OtherError("gone".to_owned())));
+	let unmapped: Result<(), Error> = syscall!(fail(CError::PermissionDenied); match_error {
+		CError::NotFound => Error::OtherError("gone".to_owned()),
+	});
+	assert_eq!(unmapped, Err(Error::SyscallError { call_name: "fail".to_owned(), error: CError::PermissionDenied }));
+}
+
+#[test]
+fn maps_errors_with_the_file_profile() {
+	let path = "/a".to_owned();
+	assert_eq!(syscall!(fail(CError::NotFound); match_error file(path)), Err(Error::FileNotFound(Some("/a".to_owned()))));
+	assert_eq!(syscall!(fail(CError::PermissionDenied); match_error file(&path)), Err(Error::FilePermissionDenied(Some("/a".to_owned()))));
+	assert_eq!(syscall!(open(&path); match_error file(path)), Err(Error::IsADirectory(Some("/a".to_owned()))));
+	assert_eq!(syscall!(fail(CError::Other(1)); match_error file("/b")), Err(Error::SyscallError { call_name: "fail".to_owned(), error: CError::Other(1) }));
+	let matched: Result<bool, Error> = syscall!(add(1, 1) match { 2 => true, _ => false } match_error file(path));
+	assert_eq!(matched, Ok(true));
+}
+
+#[test]
+fn retries_interrupted_calls() {
+	let calls = Cell::new(0);
+	assert_eq!(syscall!(retry interrupted(&calls, 3)), Ok::<_, Error>(3));
+	assert_eq!(calls.get(), 3);
+
+	let calls = Cell::new(0);
+	assert_eq!(syscall!(interrupted(&calls, 3)), Err(Error::SyscallError { call_name: "interrupted".to_owned(), error: CError::Interrupted }));
+	assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn evaluates_arguments_once_per_attempt() {
+	let calls = Cell::new(0);
+	let evaluated = RefCell::new(0);
+	let argument = || {
+		*evaluated.borrow_mut() += 1;
+		4
+	};
+	assert_eq!(syscall!(retry interrupted(&calls, argument())), Ok::<_, Error>(4));
+	assert_eq!(*evaluated.borrow(), 4);
+
+	let evaluated = RefCell::new(0);
+	let argument = || {
+		*evaluated.borrow_mut() += 1;
+		4
+	};
+	assert_eq!(syscall!(add(argument(), 1)), Ok::<_, Error>(5));
+	assert_eq!(*evaluated.borrow(), 1);
+}
+
+#[test]
+fn traces_calls() {
+	finished();
+	let _ = syscall!(add(1, 2));
+	let _ = syscall!(fail(CError::NotFound));
+	let mut opaque = Opaque;
+	let _ = syscall!(take(&mut opaque, Opaque, &[1, 2][..]));
+	assert_eq!(finished(), [
+		("add", vec!["1".to_owned(), "2".to_owned()], Ok("3".to_owned())),
+		("fail", vec!["NotFound".to_owned()], Err("NotFound".to_owned())),
+		("take", vec!["_".to_owned(), "_".to_owned(), "[1, 2]".to_owned()], Ok("()".to_owned())),
+	]);
+}